@@ -0,0 +1,61 @@
+//! Compares re-parsing the whole buffer on every read against `HeadParser` for a head arriving in small fragments.
+//! Run with `cargo bench`.
+extern crate http_handler;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use http_handler::grammar::message_head;
+use http_handler::parser::HeadParser;
+
+fn head(headers: usize) -> Vec<u8> {
+    let mut head = b"GET /index.html?q=now HTTP/1.1\r\nHost: www.example.com\r\n".to_vec();
+    for i in 0..headers {
+        head.extend_from_slice(format!("X-Header-{}: some value that is typical of a real header\r\n", i).as_bytes());
+    }
+    head.extend_from_slice(b"\r\n");
+    head
+}
+
+fn reparse(head: &[u8], fragment: usize) -> usize {
+    let mut end = 0;
+    loop {
+        end = (end + fragment).min(head.len());
+        if let Ok((remainder, parsed)) = message_head(&head[..end]) {
+            black_box(parsed);
+            return end - remainder.len();
+        }
+        assert!(end < head.len(), "head should parse");
+    }
+}
+
+fn incremental(head: &[u8], fragment: usize) -> usize {
+    let mut parser = HeadParser::new();
+    let mut end = 0;
+    loop {
+        end = (end + fragment).min(head.len());
+        if let Some(length) = parser.parse(&head[..end]).unwrap() {
+            black_box(message_head(&head[..length]).unwrap());
+            return length;
+        }
+        assert!(end < head.len(), "head should be complete");
+    }
+}
+
+fn time<F>(iterations: u32, mut fun: F) -> Duration where F: FnMut() -> usize {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        black_box(fun());
+    }
+    start.elapsed() / iterations
+}
+
+fn main() {
+    for &(headers, fragment) in &[(10, 1), (10, 16), (50, 1), (50, 16), (50, 4096)] {
+        let head = head(headers);
+        assert_eq!(reparse(&head, fragment), incremental(&head, fragment));
+        let iterations = 20;
+        let reparsed = time(iterations, || reparse(&head, fragment));
+        let resumed = time(iterations, || incremental(&head, fragment));
+        println!("{} bytes in {} byte fragments: reparse {:?}, incremental {:?}", head.len(), fragment, reparsed, resumed);
+    }
+}
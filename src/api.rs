@@ -1,14 +1,22 @@
-use std::path::{Path};
-use std::fs::{File, Metadata, canonicalize};
 use std::io::{BufRead, Read, Write, Result};
 use std::cmp::min;
 use std::borrow::Cow;
-use std::fmt;
-use regex::Regex;
+use std::{fmt, str};
 use crate::ast::*;
 use crate::grammar::*;
 use crate::parser::*;
 use crate::io::*;
+pub use crate::uri::{Uri, Authority};
+pub use crate::method::Method;
+pub use crate::status::StatusCode;
+pub use crate::header::{TypedHeader, MediaType};
+pub use crate::file::FileHandler;
+use crate::misc::join_slice;
+use crate::form::{parse_form, serialize_form, serialize_query};
+use crate::multipart::FormData;
+use crate::error::{Error, Limit};
+use crate::framing::{ParseMode, Framing, validate_head, validate_headers, remove_chunked};
+use crate::sendfile::SendFile;
 
 
 pub trait HttpHandler {
@@ -20,44 +28,6 @@ pub trait WriteTo {
     fn write_to(&mut self, write: &mut dyn Write) -> Result<usize>;
 }
 
-pub struct FileHandler<T: AsRef<Path>> {
-    base: T,
-}
-
-impl<T: AsRef<Path>> FileHandler<T> {
-    pub fn new(base: T) -> FileHandler<T> {
-        FileHandler {
-            base,
-        }
-    }
-
-    pub fn get(&self, path: &str) -> Result<Response> {
-        let full_path = canonicalize(self.base.as_ref().join(&path[1..]))?;
-        if !full_path.starts_with(&self.base) {
-            return Ok(Response::unauthorized().message("Not allowed outside of base"));
-        }
-        let file: File = File::open(&full_path)?;
-        let metadata: Metadata = file.metadata()?;
-        if metadata.is_dir() {
-            return Ok(Response::not_found().message("Path denotes a directory"));
-        }
-        Ok(Response::ok().
-            content_type("text/plain".to_string()).
-            content_length(metadata.len()).
-            entity(MessageBody::Reader(Box::new(file))))
-    }
-}
-
-impl<T: AsRef<Path>> HttpHandler for FileHandler<T> {
-    fn handle<F>(&mut self, request: &mut Request, mut fun: F) -> Result<()>
-        where F: FnMut(&mut Response) -> Result<()> + Sized {
-        fun(&mut match *request {
-            Request { method: "GET", uri: Uri { path, .. }, .. } => { self.get(path).unwrap_or_else(|_|Response::not_found().message("Not Found")) }
-            _ => { Response::method_not_allowed() }
-        })
-    }
-}
-
 pub struct LogHandler<H> where H: HttpHandler {
     handler: H,
 }
@@ -81,51 +51,6 @@ impl<H> HttpHandler for LogHandler<H> where H: HttpHandler {
     }
 }
 
-#[derive(PartialEq, Debug)]
-pub struct Uri<'a> {
-    pub scheme: Option<&'a str>,
-    pub authority: Option<&'a str>,
-    pub path: &'a str,
-    pub query: Option<&'a str>,
-    pub fragment: Option<&'a str>,
-}
-
-impl<'a> Uri<'a> {
-    pub fn parse(value: &'a str) -> Uri<'a> {
-        lazy_static! {
-            static ref RFC3986: Regex = Regex::new("^(?:([^:/?\\#]+):)?(?://([^/?\\#]*))?([^?\\#]*)(?:\\?([^\\#]*))?(?:\\#(.*))?").unwrap();
-        }
-
-        let result = RFC3986.captures(value).unwrap();
-        Uri {
-            scheme: result.get(1).map(|s|s.as_str()),
-            authority: result.get(2).map(|s|s.as_str()),
-            path: result.get(3).unwrap().as_str(),
-            query: result.get(4).map(|s|s.as_str()),
-            fragment: result.get(5).map(|s|s.as_str()),
-        }
-    }
-}
-
-impl<'a> fmt::Display for Uri<'a> {
-    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
-        if let Some(scheme) = self.scheme {
-            write!(format, "{}:", scheme)?;
-        }
-        if let Some(authority) = self.authority {
-            write!(format, "//{}", authority)?;
-        }
-        format.write_str(self.path)?;
-        if let Some(query) = self.query {
-            write!(format, "?{}", query)?;
-        }
-        if let Some(fragment) = self.fragment {
-            write!(format, "#{}", fragment)?;
-        }
-        Ok(())
-    }
-}
-
 #[derive(PartialEq, Debug)]
 pub enum Message<'a> {
     Request(Request<'a>),
@@ -134,20 +59,42 @@ pub enum Message<'a> {
 
 impl<'a> Message<'a> {
     pub fn parse(slice: &'a [u8]) -> Result<(Message<'a>, &'a [u8])> {
-        result(http_message(slice)).map(|(message, remainder)| (Message::from(message), remainder))
+        result("HTTP-message", slice, http_message(slice)).map(|(message, remainder)| (Message::from(message), remainder))
     }
 
     pub fn read<R>(slice: &'a [u8], reader: &'a mut R) -> Result<(Message<'a>, usize)> where R: Read {
-        result(message_head(slice)).map(move |(head, remainder)| {
-            let head_length = slice.len() - remainder.len();
-            let headers = head.headers;
-            let (body, body_read) = MessageBody::read(&headers, remainder, reader);
-
-            (match head.start_line {
-                StartLine::RequestLine(line) => Message::Request(Request::new(line.method, line.request_target, headers, body)),
-                StartLine::StatusLine(line) => Message::Response(Response::new(line.code, line.description, headers, body)),
-            }, head_length + body_read)
-        })
+        Message::read_mode(slice, reader, ParseMode::Strict)
+    }
+
+    pub fn read_mode<R>(slice: &'a [u8], reader: &'a mut R, mode: ParseMode) -> Result<(Message<'a>, usize)> where R: Read {
+        Message::read_for(slice, reader, mode, None)
+    }
+
+    /// `method` is that of the request a response answers, it decides whether the response has a body
+    pub fn read_for<R>(slice: &'a [u8], reader: &'a mut R, mode: ParseMode, method: Option<Method>) -> Result<(Message<'a>, usize)> where R: Read {
+        let (head, remainder) = result("message_head", slice, message_head(slice))?;
+        let head_length = slice.len() - remainder.len();
+        validate_head(&slice[..head_length], mode)?;
+        let request = matches!(head.start_line, StartLine::RequestLine(_));
+        validate_headers(&head.headers, mode, request)?;
+
+        let mut headers = head.headers;
+        let framing = match head.start_line {
+            StartLine::RequestLine(_) => Framing::of(&headers),
+            StartLine::StatusLine(ref line) => Framing::response(method, line.code, &headers),
+        };
+        let (body, body_read) = MessageBody::read(framing, remainder, reader);
+        if framing == Framing::Chunked {
+            remove_chunked(&mut headers);
+            if let MessageBody::Owned(ref data) = body {
+                headers.replace("Content-Length", data.len().to_string());
+            }
+        }
+
+        Ok((match head.start_line {
+            StartLine::RequestLine(line) => Message::Request(Request::new(line.method, line.request_target, headers, body)),
+            StartLine::StatusLine(line) => Message::Response(Response::new(line.code, line.description, headers, body)),
+        }, head_length + body_read))
     }
 }
 
@@ -171,47 +118,73 @@ impl<'a> WriteTo for Message<'a> {
 
 #[derive(PartialEq, Debug)]
 pub struct Request<'a> {
-    pub method: &'a str,
+    pub method: Method<'a>,
     pub uri: Uri<'a>,
+    pub form: TargetForm,
     pub headers: Headers<'a>,
     pub entity: MessageBody<'a>,
+    pub params: Parameters<'a>,
+    pub base: &'a str,
 }
 
-impl<'a> Request<'a> {
-    pub fn new(method: &'a str, url: &'a str, headers: Headers<'a>, entity: MessageBody<'a>) -> Request<'a> {
-        Request { method, uri: Uri::parse(url), headers, entity }
-    }
+pub const FORM_LIMIT: u64 = 1024 * 1024;
 
-    pub fn request(method: &'a str, url: &'a str) -> Request<'a> {
+impl<'a> Request<'a> {
+    pub fn new<M>(method: M, url: &'a str, headers: Headers<'a>, entity: MessageBody<'a>) -> Request<'a>
+        where M: Into<Method<'a>> {
+        let method = method.into();
+        let form = TargetForm::of(method.as_str(), url);
+        let uri = match form {
+            TargetForm::Authority => Uri { scheme: None, authority: Some(url), path: "", query: None, fragment: None },
+            _ => Uri::parse(url),
+        };
+        Request { method, uri, form, headers, entity, params: Parameters::new(), base: "" }
+    }
+
+    #[allow(clippy::self_named_constructors)]
+    pub fn request<M>(method: M, url: &'a str) -> Request<'a> where M: Into<Method<'a>> {
         Request::new(method, url, Headers::new(), MessageBody::None)
     }
 
     pub fn parse(slice: &'a [u8]) -> Result<(Request<'a>, &'a [u8])> {
-        result(http_message(slice)).map(|(request, remainder)| (Request::from(request), remainder))
+        result("HTTP-message", slice, http_message(slice)).map(|(request, remainder)| (Request::from(request), remainder))
     }
 
     pub fn get(url: &'a str) -> Request<'a> {
-        Request::request("GET", url)
+        Request::request(Method::Get, url)
+    }
+
+    pub fn head(url: &'a str) -> Request<'a> {
+        Request::request(Method::Head, url)
     }
 
     pub fn post(url: &'a str) -> Request<'a> {
-        Request::request("POST", url)
+        Request::request(Method::Post, url)
     }
 
     pub fn put(url: &'a str) -> Request<'a> {
-        Request::request("PUT", url)
+        Request::request(Method::Put, url)
+    }
+
+    pub fn patch(url: &'a str) -> Request<'a> {
+        Request::request(Method::Patch, url)
     }
 
     pub fn delete(url: &'a str) -> Request<'a> {
-        Request::request("DELETE", url)
+        Request::request(Method::Delete, url)
     }
 
+    pub fn options(url: &'a str) -> Request<'a> {
+        Request::request(Method::Options, url)
+    }
+
+    #[deprecated(note = "use `Request::options`")]
     pub fn option(url: &'a str) -> Request<'a> {
-        Request::request("OPTION", url)
+        Request::options(url)
     }
 
-    pub fn method(mut self, method: &'a str) -> Self {
-        self.method = method;
+    pub fn method<M>(mut self, method: M) -> Self where M: Into<Method<'a>> {
+        self.method = method.into();
         self
     }
 
@@ -229,6 +202,108 @@ impl<'a> Request<'a> {
         self.headers.remove(name);
         self
     }
+
+    pub fn get_typed<H>(&'a self) -> Option<Result<H>> where H: TypedHeader<'a> {
+        self.headers.get_typed()
+    }
+
+    pub fn typed<'b, H>(mut self, header: &H) -> Self where H: TypedHeader<'b> {
+        self.headers.set_typed(header);
+        self
+    }
+
+    pub fn form(mut self, parameters: &Parameters) -> Self {
+        self.entity = MessageBody::Owned(serialize_form(parameters).into_bytes());
+        self.header("Content-Type", "application/x-www-form-urlencoded")
+    }
+
+    pub fn multipart(mut self, form: FormData<'a>) -> Self {
+        let content_type = form.content_type();
+        let content_length = form.content_length();
+        self.entity = MessageBody::Reader(form.into_reader());
+        self.header("Content-Type", content_type).header("Content-Length", content_length.to_string())
+    }
+
+    pub fn url(path: &str, parameters: &Parameters) -> String {
+        if parameters.is_empty() {
+            return path.to_string();
+        }
+        let separator = if !path.contains('?') { "?" } else if path.ends_with(['?', '&']) { "" } else { "&" };
+        format!("{}{}{}", path, separator, serialize_query(parameters))
+    }
+
+    /// Empty unless the body is `application/x-www-form-urlencoded`, a streaming body is read up to `FORM_LIMIT`
+    pub fn form_params(&mut self) -> Result<Parameters<'_>> {
+        let is_form = self.get_header("Content-Type").
+            and_then(|value| value.split(';').next()).
+            is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case("application/x-www-form-urlencoded"));
+        if !is_form {
+            return Ok(Parameters::new());
+        }
+        if let MessageBody::Reader(ref mut reader) = self.entity {
+            let mut body = vec!();
+            reader.take(FORM_LIMIT + 1).read_to_end(&mut body)?;
+            if body.len() as u64 > FORM_LIMIT {
+                // the connection is closed on this error so there is no point draining the rest
+                self.entity.discard();
+                return Err(Error::LimitExceeded(Limit::Form).into());
+            }
+            self.entity = MessageBody::Owned(body);
+        }
+        let body = match self.entity {
+            MessageBody::Slice(slice) => slice,
+            MessageBody::Owned(ref vec) => vec.as_slice(),
+            _ => return Ok(Parameters::new()),
+        };
+        match str::from_utf8(body) {
+            Ok(body) => Ok(parse_form(body)),
+            Err(e) => Err(Error::parse("UTF-8", e.valid_up_to()).into()),
+        }
+    }
+
+    /// The path before any `Mount` made it relative, `base` followed by the current path
+    pub fn original_path(&self) -> Cow<'a, str> {
+        if self.base.is_empty() {
+            return Cow::from(self.uri.path);
+        }
+        match join_slice(self.base.as_bytes(), self.uri.path.as_bytes()) {
+            Ok(joined) => Cow::from(str::from_utf8(joined).unwrap()),
+            Err(_) if self.uri.path == "/" => Cow::from(self.base),
+            Err(_) => Cow::from(format!("{}{}", self.base, self.uri.path)),
+        }
+    }
+
+    pub fn request_target(&self) -> String {
+        match (self.form, self.uri.authority) {
+            (TargetForm::Authority, Some(authority)) => authority.to_string(),
+            _ => self.uri.to_string(),
+        }
+    }
+
+    /// The authority of an absolute or authority-form target, otherwise the `Host` header
+    pub fn host(&self) -> Option<Authority<'_>> {
+        match self.uri.authority {
+            Some(_) => self.uri.parse_authority(),
+            None => self.get_header("Host").and_then(|host| Authority::host(host).ok()),
+        }
+    }
+
+    pub fn absolute_uri(&self) -> Option<String> {
+        match (self.uri.scheme, self.uri.authority) {
+            (Some(_), Some(_)) => Some(self.uri.to_string()),
+            (None, Some(_)) => Some(format!("http:{}", self.uri)),
+            _ => self.get_header("Host").map(|host| format!("http://{}{}", host, self.uri)),
+        }
+    }
+
+    pub fn write_with_target(&mut self, request_target: &str, write: &mut dyn Write) -> Result<usize> {
+        let text = format!("{}{}\r\n",
+                           RequestLine { method: self.method.as_str(), request_target, version: HttpVersion { major: 1, minor: 1 } },
+                           self.headers);
+        let head = write.write(text.as_bytes())?;
+        let body = self.entity.write_to(write)?;
+        Ok(head + body)
+    }
 }
 
 impl<'a> From<HttpMessage<'a>> for Request<'a> {
@@ -243,7 +318,7 @@ impl<'a> From<HttpMessage<'a>> for Request<'a> {
 impl<'a> fmt::Display for Request<'a> {
     fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
         write!(format, "{}{}\r\n{}",
-               RequestLine { method: self.method, request_target: self.uri.to_string().as_str(), version: HttpVersion { major: 1, minor: 1 } },
+               RequestLine { method: self.method.as_str(), request_target: self.request_target().as_str(), version: HttpVersion { major: 1, minor: 1 } },
                self.headers,
                self.entity)
     }
@@ -251,12 +326,8 @@ impl<'a> fmt::Display for Request<'a> {
 
 impl<'a> WriteTo for Request<'a> {
     fn write_to(&mut self, write: &mut dyn Write) -> Result<usize> {
-        let text = format!("{}{}\r\n",
-                           RequestLine { method: self.method, request_target: self.uri.to_string().as_str(), version: HttpVersion { major: 1, minor: 1 } },
-                           self.headers);
-        let head = write.write(text.as_bytes())?;
-        let body = self.entity.write_to(write)?;
-        Ok(head + body)
+        let request_target = self.request_target();
+        self.write_with_target(&request_target, write)
     }
 }
 
@@ -273,28 +344,17 @@ impl<'a> Response<'a> {
         Response { code, description, headers, entity }.build()
     }
 
+    #[allow(clippy::self_named_constructors)]
     pub fn response(code: u16, description: &'a str) -> Response<'a> {
         Response::new(code, description, Headers::new(), MessageBody::None)
     }
 
-    pub fn ok() -> Response<'a> {
-        Response::response(200, "OK")
-    }
-
-    pub fn bad_request() -> Response<'a> {
-        Response::response(400, "Bad Request")
-    }
-
-    pub fn unauthorized() -> Response<'a> {
-        Response::response(401, "Unauthorized")
-    }
-
-    pub fn not_found() -> Response<'a> {
-        Response::response(404, "Not Found")
+    pub fn status(status: StatusCode) -> Response<'a> {
+        Response::response(status.0, status.reason().unwrap_or(""))
     }
 
-    pub fn method_not_allowed() -> Response<'a> {
-        Response::response(405, "Method Not Allowed")
+    pub fn status_code(&self) -> StatusCode {
+        StatusCode(self.code)
     }
 
     pub fn code(mut self, code: u16) -> Response<'a> {
@@ -310,7 +370,7 @@ impl<'a> Response<'a> {
     pub fn message(self, message: &'a str) -> Response<'a> {
         let bytes = message.as_bytes();
         self.description(message).
-            content_type("text/plain".to_string()).
+            content_type("text/plain").
             entity(MessageBody::Slice(bytes))
     }
 
@@ -328,13 +388,22 @@ impl<'a> Response<'a> {
         self
     }
 
+    pub fn get_typed<H>(&'a self) -> Option<Result<H>> where H: TypedHeader<'a> {
+        self.headers.get_typed()
+    }
+
+    pub fn typed<'b, H>(mut self, header: &H) -> Response<'a> where H: TypedHeader<'b> {
+        self.headers.set_typed(header);
+        self
+    }
+
     pub fn entity(mut self, entity: MessageBody<'a>) -> Response<'a> {
         self.entity = entity;
         self.build()
     }
 
-    pub fn content_type(self, media_type: String) -> Response<'a> {
-        self.header("Content-Type", media_type)
+    pub fn content_type<M>(self, media_type: M) -> Response<'a> where M: fmt::Display {
+        self.header("Content-Type", media_type.to_string())
     }
 
     pub fn content_length(self, length: u64) -> Response<'a> {
@@ -345,6 +414,8 @@ impl<'a> Response<'a> {
         match self.entity {
             MessageBody::None => { Some(0) }
             MessageBody::Slice(slice) => { Some(slice.len() as u64) }
+            MessageBody::Owned(ref vec) => { Some(vec.len() as u64) }
+            MessageBody::File(_, ref range) => { Some(range.end - range.start) }
             _ => None
         }
     }
@@ -375,12 +446,34 @@ impl<'a> fmt::Display for Response<'a> {
     }
 }
 
-impl<'a> WriteTo for Response<'a> {
-    fn write_to(&mut self, write: &mut dyn Write) -> Result<usize> {
+impl<'a> Response<'a> {
+    pub fn write_head(&self, write: &mut dyn Write) -> Result<usize> {
         let text = format!("{}{}\r\n",
                            StatusLine { code: self.code, description: self.description, version: HttpVersion { major: 1, minor: 1 } },
                            self.headers);
-        let head = write.write(text.as_bytes())?;
+        write.write(text.as_bytes())
+    }
+
+    /// For `HEAD`, the body is discarded without being read
+    pub fn send_head(&mut self, write: &mut dyn Write) -> Result<usize> {
+        self.entity.discard();
+        self.write_head(write)
+    }
+
+    /// A `MessageBody::File` goes through `SendFile` so it can skip userspace
+    pub fn send<S>(&mut self, sink: &mut S) -> Result<usize> where S: SendFile {
+        let head = self.write_head(sink)?;
+        let body = match self.entity {
+            MessageBody::File(ref mut file, ref range) => sink.send_file(file, range.clone())? as usize,
+            ref mut entity => entity.write_to(sink)?,
+        };
+        Ok(head + body)
+    }
+}
+
+impl<'a> WriteTo for Response<'a> {
+    fn write_to(&mut self, write: &mut dyn Write) -> Result<usize> {
+        let head = self.write_head(write)?;
         let body = self.entity.write_to(write)?;
         Ok(head + body)
     }
@@ -418,7 +511,7 @@ impl<R> ChunkStream<R> where R: BufRead + Sized {
     }
 }
 
-impl<'a, R> Drop for ChunkStream<R> where R: BufRead + Sized {
+impl<R> Drop for ChunkStream<R> where R: BufRead + Sized {
     fn drop(&mut self) {
         while let Some(Ok(_)) = self.next() {}
     }
@@ -433,29 +526,27 @@ impl<'a, R> Streamer<'a> for ChunkStream<R> where R: BufRead + Sized {
             return None;
         }
 
-        loop {
-            let buffer = self.read.fill_buf().unwrap();
-            if buffer.len() == 0 {
-                self.state = ChunkStreamState::Finished;
-                return None;
-            }
+        let buffer = self.read.fill_buf().unwrap();
+        if buffer.is_empty() {
+            self.state = ChunkStreamState::Finished;
+            return None;
+        }
 
-            return match Chunk::read(buffer) {
-                Ok((last @ Chunk::Last(..), consumed)) => {
-                    self.state = ChunkStreamState::Last(consumed);
-                    Some(Ok(last))
-                },
-                Ok((chunk, consumed)) => {
-                    self.state = ChunkStreamState::Consumed(consumed);
-                    Some(Ok(chunk))
-                },
-                Err(e) => Some(Err(e))
-            };
+        match Chunk::read(buffer) {
+            Ok((last @ Chunk::Last(..), consumed)) => {
+                self.state = ChunkStreamState::Last(consumed);
+                Some(Ok(last))
+            },
+            Ok((chunk, consumed)) => {
+                self.state = ChunkStreamState::Consumed(consumed);
+                Some(Ok(chunk))
+            },
+            Err(e) => Some(Err(e))
         }
     }
 }
 
-impl<'a, R> Read for ChunkStream<R> where R: BufRead + Sized {
+impl<R> Read for ChunkStream<R> where R: BufRead + Sized {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         match self.next() {
             None => Ok(0),
@@ -479,48 +570,79 @@ mod tests {
     use super::*;
 
     #[test]
-    fn can_parse_uri() {
-        let uri = Uri::parse("http://authority/some/path?query=string#fragment");
-        assert_eq!(uri.scheme, Some("http"));
-        assert_eq!(uri.authority, Some("authority"));
-        assert_eq!(uri.path, "/some/path");
-        assert_eq!(uri.query, Some("query=string"));
-        assert_eq!(uri.fragment, Some("fragment"));
+    fn exposes_request_target_form() {
+        let (request, _) = Request::parse(b"CONNECT www.example.com:443 HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(request.form, TargetForm::Authority);
+        assert_eq!(request.uri.authority, Some("www.example.com:443"));
+        assert_eq!(request.uri.scheme, None);
+        assert_eq!(request.to_string(), "CONNECT www.example.com:443 HTTP/1.1\r\n\r\n");
+        assert_eq!(request.host().map(|host| host.port), Some(Some(443)));
+
+        assert_eq!(Request::parse(b"OPTIONS * HTTP/1.1\r\n\r\n").unwrap().0.form, TargetForm::Asterisk);
+        assert_eq!(Request::parse(b"GET http://example.com/ HTTP/1.1\r\n\r\n").unwrap().0.form, TargetForm::Absolute);
+        assert_eq!(Request::get("/index.html").form, TargetForm::Origin);
+        assert!(Request::parse(b"GET * HTTP/1.1\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn has_typed_methods() {
+        assert_eq!(Request::options("*").to_string(), "OPTIONS * HTTP/1.1\r\n\r\n");
+        assert_eq!(Request::patch("/").method, Method::Patch);
+        let (request, _) = Request::parse(b"PROPFIND /dav HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(request.method, Method::Extension("PROPFIND"));
+        assert!(!request.method.is_idempotent());
     }
 
     #[test]
-    fn supports_relative() {
-        let uri = Uri::parse("some/path");
-        assert_eq!(uri.scheme, None);
-        assert_eq!(uri.authority, None);
-        assert_eq!(uri.path, "some/path");
-        assert_eq!(uri.query, None);
-        assert_eq!(uri.fragment, None);
+    fn can_find_the_host() {
+        use crate::uri::Host;
+
+        assert_eq!(Request::get("/").header("Host", "Example.com:8080").host().map(|host| (host.host.name().into_owned(), host.port)), Some(("example.com".to_string(), Some(8080))));
+        assert_eq!(Request::get("http://[::1]/").header("Host", "ignored").host().map(|host| host.host), Some(Host::Ipv6(std::net::Ipv6Addr::LOCALHOST)));
+        assert_eq!(Request::get("/").host(), None);
+        assert_eq!(Request::get("/").header("Host", "bad host").host(), None);
     }
 
     #[test]
-    fn supports_urns() {
-        let uri = Uri::parse("uuid:720f11db-1a29-4a68-a034-43f80b27659d");
-        assert_eq!(uri.scheme, Some("uuid"));
-        assert_eq!(uri.authority, None);
-        assert_eq!(uri.path, "720f11db-1a29-4a68-a034-43f80b27659d");
-        assert_eq!(uri.query, None);
-        assert_eq!(uri.fragment, None);
+    fn can_build_and_read_form_bodies() {
+        let mut params = Parameters::new();
+        params.add("name", "Dan Bodart").add("lang", "rust");
+        let mut request = Request::post("/").form(&params);
+        assert_eq!(request.get_header("Content-Type"), Some("application/x-www-form-urlencoded"));
+        assert_eq!(request.entity, MessageBody::Owned(b"name=Dan+Bodart&lang=rust".to_vec()));
+        assert_eq!(request.form_params().unwrap(), params);
+
+        let mut streamed = Request::post("/").header("Content-Type", "application/x-www-form-urlencoded; charset=utf-8");
+        streamed.entity = MessageBody::Reader(Box::new(&b"a=1&a=2"[..]));
+        assert_eq!(streamed.form_params().unwrap().values("a"), vec!("1", "2"));
+
+        let mut json = Request::post("/").header("Content-Type", "application/json");
+        json.entity = MessageBody::Slice(b"a=1");
+        assert!(json.form_params().unwrap().is_empty());
+
+        let mut large = Request::post("/").header("Content-Type", "application/x-www-form-urlencoded");
+        large.entity = MessageBody::Reader(Box::new(std::io::repeat(b'a').take(FORM_LIMIT + 1)));
+        let error = large.form_params().unwrap_err();
+        assert!(matches!(Error::of(&error), Some(Error::LimitExceeded(Limit::Form))));
+        assert_eq!(large.entity, MessageBody::None);
     }
 
     #[test]
-    fn is_reverse_able() {
-        let original = "http://authority/some/path?query=string#fragment";
-        assert_eq!(Uri::parse(original).to_string(), original.to_string());
-        let another = "some/path";
-        assert_eq!(Uri::parse(another).to_string(), another.to_string());
+    fn can_build_a_url_with_query_parameters() {
+        let mut params = Parameters::new();
+        params.add("q", "hello world").add("a&b", "1");
+        assert_eq!(Request::url("/search", &params), "/search?q=hello%20world&a%26b=1");
+        assert_eq!(Request::url("/search?page=2", &params), "/search?page=2&q=hello%20world&a%26b=1");
+        assert_eq!(Request::url("/search?", &params), "/search?q=hello%20world&a%26b=1");
+        assert_eq!(Request::url("/search", &Parameters::new()), "/search");
+        assert_eq!(Request::get(&Request::url("/search", &params)).uri.query_params(), params);
     }
 
     #[test]
     fn can_pattern_match_a_request() {
         let request = Request::get("/some/path").header("Content-Type", "text/plain");
         match request {
-            Request { method: "GET", uri: Uri { path: "/some/path", .. }, ref headers, .. } if headers.get("Content-Type") == Some("text/plain") => {},
+            Request { method: Method::Get, uri: Uri { path: "/some/path", .. }, ref headers, .. } if headers.get("Content-Type") == Some("text/plain") => {},
             _ => {
                 panic!("Should have matched");
             }
@@ -1,10 +1,13 @@
-use std::{fmt, str, usize};
-use std::io::{Read, Write, Result, copy, sink};
+use std::{fmt, ops, str};
+use std::fs::File;
+use std::io::{Read, Write, Result, copy, empty, sink};
 use crate::api::{WriteTo};
 use std::borrow::{Cow, Borrow};
-use crate::parser::result;
+use crate::parser::{result, parse_all};
+use crate::header::TypedHeader;
+use crate::sendfile::copy_range;
+use crate::framing::Framing;
 use nom::IResult;
-use crate::io::SimpleError;
 
 #[derive(PartialEq, Debug)]
 pub struct HttpVersion {
@@ -31,6 +34,44 @@ impl<'a> fmt::Display for RequestLine<'a> {
     }
 }
 
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TargetForm {
+    Origin,
+    Absolute,
+    Authority,
+    Asterisk,
+}
+
+impl TargetForm {
+    /// Classifies a target without validating it, `CONNECT` always uses authority-form
+    pub fn of(method: &str, request_target: &str) -> TargetForm {
+        match (method, request_target) {
+            ("CONNECT", _) => TargetForm::Authority,
+            (_, "*") => TargetForm::Asterisk,
+            (_, target) if target.starts_with('/') => TargetForm::Origin,
+            _ => TargetForm::Absolute,
+        }
+    }
+
+    /// The form of a valid target, `None` when the target is invalid or not allowed for the method
+    pub fn parse(method: &str, request_target: &str) -> Option<TargetForm> {
+        use crate::grammar::{origin_form, absolute_form, authority_form, asterisk_form};
+
+        let form = TargetForm::of(method, request_target);
+        let parser = match form {
+            TargetForm::Origin => origin_form,
+            TargetForm::Absolute => absolute_form,
+            TargetForm::Authority => authority_form,
+            TargetForm::Asterisk if method == "OPTIONS" => asterisk_form,
+            TargetForm::Asterisk => return None,
+        };
+        match parser(request_target.as_bytes()) {
+            Ok(([], _)) => Some(form),
+            _ => None,
+        }
+    }
+}
+
 #[derive(PartialEq, Debug)]
 pub struct StatusLine<'a> {
     pub version: HttpVersion,
@@ -96,20 +137,19 @@ impl<'a> fmt::Display for Headers<'a> {
 
 type NomParser<'a, T> = fn(&'a[u8]) -> IResult<&'a[u8], Vec<T>>;
 
-
 impl<'a> Headers<'a> {
     pub fn new() -> Headers<'a> {
         Headers(vec!())
     }
 
     pub fn get(&'a self, name: &str) -> Option<&'a str> {
-        (&self.0).into_iter().
+        self.0.iter().
             find(|header| name.eq_ignore_ascii_case(header.name())).
             map(|header| header.value())
     }
 
     pub fn headers(&'a self, name: &str) -> Vec<&'a str> {
-        (&self.0).into_iter().
+        self.0.iter().
             filter(|header| name.eq_ignore_ascii_case(header.name())).
             map(|header| header.value()).
             collect()
@@ -126,13 +166,7 @@ impl<'a> Headers<'a> {
 
     pub fn parse_nom<T>(&'a self, name: &str, fun: NomParser<'a, T>) -> Result<Vec<T>>
         where T: 'a {
-        self.parse(name, |s| {
-            let (encodings, remainder) = result(fun(s.as_bytes()))?;
-            if !remainder.is_empty() {
-                return Err(SimpleError::error(format!("Invalid header '{}' has remainder '{}'", name, str::from_utf8(remainder).unwrap())))
-            }
-            Ok(encodings)
-        })
+        self.parse(name, |s| parse_all(name, s.as_bytes(), fun(s.as_bytes())))
     }
 
     pub fn transfer_encoding(&'a self) -> Vec<TransferCoding<'a>>{
@@ -143,7 +177,8 @@ impl<'a> Headers<'a> {
 
     pub fn content_length(&self) -> Option<u64> {
         self.get("Content-Length").
-            and_then(|value| value.parse().ok())
+            and_then(|value| value.split(',').next()).
+            and_then(|value| value.trim().parse().ok())
     }
 
     pub fn replace<V>(&mut self, name: &'a str, value: V) -> &mut Headers<'a>
@@ -157,18 +192,78 @@ impl<'a> Headers<'a> {
         self.0.retain(|header| !name.eq_ignore_ascii_case(header.name()));
         self
     }
+
+    pub fn get_typed<H>(&'a self) -> Option<Result<H>> where H: TypedHeader<'a> {
+        let values = self.headers(H::NAME);
+        if values.is_empty() {
+            return None;
+        }
+        Some(H::parse(&values))
+    }
+
+    pub fn set_typed<'b, H>(&mut self, header: &H) -> &mut Headers<'a> where H: TypedHeader<'b> {
+        self.replace(H::NAME, header.format())
+    }
+}
+
+impl<'a> Default for Headers<'a> {
+    fn default() -> Self {
+        Headers::new()
+    }
+}
+
+#[derive(PartialEq, Debug, Default)]
+pub struct Parameters<'a> (pub Vec<(Cow<'a, str>, Cow<'a, str>)>);
+
+impl<'a> Parameters<'a> {
+    pub fn new() -> Parameters<'a> {
+        Parameters(vec!())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.iter().
+            find(|(key, _)| key == name).
+            map(|(_, value)| value.borrow())
+    }
+
+    pub fn values(&self, name: &str) -> Vec<&str> {
+        self.0.iter().
+            filter(|(key, _)| key == name).
+            map(|(_, value)| value.borrow()).
+            collect()
+    }
+
+    pub fn add<N, V>(&mut self, name: N, value: V) -> &mut Parameters<'a>
+        where N: Into<Cow<'a, str>>,
+              V: Into<Cow<'a, str>> {
+        self.0.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 }
 
 pub enum MessageBody<'a> {
     None,
     Slice(&'a [u8]),
+    Owned(Vec<u8>),
     Reader(Box<dyn Read + 'a>),
+    File(File, ops::Range<u64>),
 }
 
 impl<'a> MessageBody<'a> {
-    pub fn read<R>(headers: &Headers, slice: &'a [u8], reader: &'a mut R) -> (MessageBody<'a>, usize) where R: Read {
-        match headers.content_length() {
-            Some(body_length) if body_length > 0 => {
+    pub fn read<R>(framing: Framing, slice: &'a [u8], reader: &'a mut R) -> (MessageBody<'a>, usize) where R: Read {
+        use crate::framing::ChunkedReader;
+        use crate::grammar::chunked_body;
+
+        match framing {
+            Framing::Length(body_length) => {
                 let slice_length = slice.len() as u64;
                 if body_length <= slice_length {
                     let length = body_length as usize;
@@ -178,7 +273,12 @@ impl<'a> MessageBody<'a> {
                     (MessageBody::Reader(Box::new(slice.chain(more))), slice.len())
                 }
             }
-            _ => (MessageBody::None, 0)
+            Framing::Chunked => match chunked_body(slice) {
+                Ok((remainder, body)) => (MessageBody::Owned(body.data()), slice.len() - remainder.len()),
+                Err(_) => (MessageBody::Reader(Box::new(ChunkedReader::new(slice.chain(reader)))), slice.len()),
+            },
+            Framing::UntilClose => (MessageBody::Reader(Box::new(slice.chain(reader))), slice.len()),
+            Framing::None => (MessageBody::None, 0)
         }
     }
 
@@ -194,15 +294,35 @@ impl<'a> MessageBody<'a> {
                     Ok(())
                 }
             },
+            MessageBody::Owned(ref vec) => {
+                if let Ok(result) = str::from_utf8(vec) {
+                    format.write_str(result)
+                } else {
+                    Ok(())
+                }
+            },
             _ => Ok(()),
         }
     }
+
+    pub fn is_replayable(&self) -> bool {
+        !matches!(*self, MessageBody::Reader(_))
+    }
+
+    /// Drops the body without draining it, for bodies that are not read from the connection
+    pub fn discard(&mut self) {
+        if let MessageBody::Reader(ref mut reader) = *self {
+            *reader = Box::new(empty());
+        }
+        *self = MessageBody::None;
+    }
 }
 
 impl<'a> Drop for MessageBody<'a> {
     fn drop(&mut self) {
         if let MessageBody::Reader(ref mut reader) = *self {
-            copy(reader, &mut sink()).expect("should be able to copy");
+            // a body that can not be drained will fail the next read on the connection instead
+            let _ = copy(reader, &mut sink());
         }
     }
 }
@@ -212,6 +332,7 @@ impl<'a> PartialEq for MessageBody<'a> {
         match (self, other) {
             (&MessageBody::None, &MessageBody::None) | (&MessageBody::Reader(_), &MessageBody::Reader(_)) => true,
             (&MessageBody::Slice(slice_a), &MessageBody::Slice(slice_b)) => slice_a == slice_b,
+            (MessageBody::Owned(vec_a), MessageBody::Owned(vec_b)) => vec_a == vec_b,
             _ => false
         }
     }
@@ -244,6 +365,12 @@ impl<'a> WriteTo for MessageBody<'a> {
             MessageBody::Slice(slice) => {
                 writer.write(slice)
             },
+            MessageBody::Owned(ref vec) => {
+                writer.write(vec)
+            },
+            MessageBody::File(ref mut file, ref range) => {
+                copy_range(file, range.clone(), writer).map(|c| c as usize)
+            },
             _ => Ok(0),
         }
     }
@@ -314,16 +441,16 @@ pub enum Chunk<'a> {
 }
 
 impl<'a> Chunk<'a> {
-    pub fn read(slice: &[u8]) -> Result<(Chunk, usize)> {
+    pub fn read(slice: &[u8]) -> Result<(Chunk<'_>, usize)> {
         use crate::grammar::*;
 
-        let ((size, extensions), remainder) = result(chunk_head(slice))?;
+        let ((size, extensions), remainder) = result("chunk_head", slice, chunk_head(slice))?;
         if size > 0 {
             let s = size as usize;
             let consumed = (slice.len() - remainder.len()) + s + 2;
             Ok((Chunk::Slice(extensions, &remainder[..s]), consumed))
         } else {
-            let (trailers, remainder) = result(headers(remainder))?;
+            let (trailers, remainder) = result("trailer_part", slice, headers(remainder))?;
             let consumed = (slice.len() - remainder.len()) + 2;
             Ok((Chunk::Last(extensions, trailers), consumed))
         }
@@ -342,6 +469,15 @@ impl<'a> ChunkedBody<'a> {
             chunks,
         }
     }
+
+    pub fn data(&self) -> Vec<u8> {
+        self.chunks.iter().fold(Vec::new(), |mut data, chunk| {
+            if let Chunk::Slice(_, slice) = chunk {
+                data.extend_from_slice(slice);
+            }
+            data
+        })
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -0,0 +1,160 @@
+use std::time::SystemTime;
+use crate::api::{Request, Response, StatusCode};
+use crate::date::{from_seconds, seconds};
+use crate::header::{EntityTag, EntityTags, ETag, IfMatch, IfModifiedSince, IfNoneMatch, IfUnmodifiedSince, LastModified};
+
+/// Fields a `304 Not Modified` keeps from the `200 OK` it stands in for, RFC 7232 section 4.1
+const NOT_MODIFIED_FIELDS: [&str; 6] = ["Cache-Control", "Content-Location", "Date", "ETag", "Expires", "Vary"];
+
+/// A weak validator from the size and modification time, good enough for files that are replaced rather than edited in place.
+/// `If-Match` compares strongly so only `*` can match it.
+pub fn weak_etag(length: u64, modified: SystemTime) -> String {
+    EntityTag::weak(&format!("{:x}-{:x}", length, seconds(modified))).to_string()
+}
+
+/// HTTP-dates only have whole seconds
+fn truncate(time: SystemTime) -> SystemTime {
+    from_seconds(seconds(time))
+}
+
+fn matches<F>(tags: &EntityTags, etag: Option<&EntityTag>, compare: F) -> bool where F: Fn(&EntityTag, &EntityTag) -> bool {
+    match (tags, etag) {
+        (EntityTags::Any, etag) => etag.is_some(),
+        (EntityTags::Tags(tags), Some(etag)) => tags.iter().any(|tag| compare(tag, etag)),
+        (EntityTags::Tags(_), None) => false,
+    }
+}
+
+impl<'a> Request<'a> {
+    /// Evaluates the conditional fields in RFC 7232 section 6 order against the selected representation,
+    /// `Some` is the status to send instead of applying the method. Invalid dates are ignored.
+    pub fn preconditions(&self, etag: Option<&EntityTag>, last_modified: Option<SystemTime>) -> Option<StatusCode> {
+        let last_modified = last_modified.map(truncate);
+        match self.headers.get_typed::<IfMatch>() {
+            Some(Ok(IfMatch(tags))) => if !matches(&tags, etag, |tag, etag| tag.strong_eq(etag)) {
+                return Some(StatusCode::PRECONDITION_FAILED);
+            },
+            Some(Err(_)) => return Some(StatusCode::PRECONDITION_FAILED),
+            None => if let (Some(Ok(IfUnmodifiedSince(date))), Some(last_modified)) = (self.headers.get_typed::<IfUnmodifiedSince>(), last_modified) {
+                if last_modified > date {
+                    return Some(StatusCode::PRECONDITION_FAILED);
+                }
+            },
+        }
+        let cacheable = self.method.is_cacheable();
+        match self.headers.get_typed::<IfNoneMatch>() {
+            Some(Ok(IfNoneMatch(tags))) => if matches(&tags, etag, |tag, etag| tag.weak_eq(etag)) {
+                return Some(if cacheable { StatusCode::NOT_MODIFIED } else { StatusCode::PRECONDITION_FAILED });
+            },
+            Some(Err(_)) => {},
+            None => if let (true, Some(Ok(IfModifiedSince(date))), Some(last_modified)) = (cacheable, self.headers.get_typed::<IfModifiedSince>(), last_modified) {
+                if last_modified <= date {
+                    return Some(StatusCode::NOT_MODIFIED);
+                }
+            },
+        }
+        None
+    }
+}
+
+/// Applies the request's preconditions to a successful response using its `ETag` and `Last-Modified`
+pub fn conditional<'r>(request: &Request, response: Response<'r>) -> Response<'r> {
+    if !response.status_code().is_success() {
+        return response;
+    }
+    let status = {
+        let etag = response.headers.get_typed::<ETag>().and_then(|etag| etag.ok());
+        let last_modified = response.headers.get_typed::<LastModified>().and_then(|date| date.ok());
+        request.preconditions(etag.as_ref().map(|ETag(etag)| etag), last_modified.map(|LastModified(date)| date))
+    };
+    match status {
+        Some(StatusCode::NOT_MODIFIED) => {
+            let mut not_modified = Response::not_modified().remove_header("Content-Length");
+            for name in NOT_MODIFIED_FIELDS {
+                if let Some(value) = response.get_header(name) {
+                    not_modified = not_modified.header(name, value.to_string());
+                }
+            }
+            not_modified
+        }
+        Some(status) => Response::status(status),
+        None => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODIFIED: i64 = 784_111_777;
+
+    fn status(name: &'static str, value: &'static str) -> Option<u16> {
+        preconditions(Request::get("/").header(name, value))
+    }
+
+    fn preconditions(request: Request) -> Option<u16> {
+        request.preconditions(Some(&EntityTag::weak("5-2ebc98a1")), Some(from_seconds(MODIFIED))).map(u16::from)
+    }
+
+    #[test]
+    fn builds_weak_etags() {
+        assert_eq!(weak_etag(5, from_seconds(MODIFIED)), "W/\"5-2ebc98a1\"");
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison() {
+        assert_eq!(status("If-None-Match", "\"5-2ebc98a1\""), Some(304));
+        assert_eq!(status("If-None-Match", "\"other\", W/\"5-2ebc98a1\""), Some(304));
+        assert_eq!(status("If-None-Match", "*"), Some(304));
+        assert_eq!(status("If-None-Match", "\"other\""), None);
+        assert_eq!(preconditions(Request::put("/").header("If-None-Match", "*")), Some(412));
+    }
+
+    #[test]
+    fn if_match_uses_strong_comparison() {
+        assert_eq!(status("If-Match", "W/\"5-2ebc98a1\""), Some(412));
+        assert_eq!(status("If-Match", "*"), None);
+        assert_eq!(status("If-Match", "not a tag"), Some(412));
+        assert_eq!(Request::get("/").header("If-Match", "\"a\"").preconditions(Some(&EntityTag::strong("a")), None), None);
+        assert_eq!(Request::get("/").header("If-Match", "*").preconditions(None, None), Some(StatusCode::PRECONDITION_FAILED));
+    }
+
+    #[test]
+    fn compares_dates_to_the_second() {
+        assert_eq!(status("If-Modified-Since", "Sun, 06 Nov 1994 08:49:37 GMT"), Some(304));
+        assert_eq!(status("If-Modified-Since", "Sun, 06 Nov 1994 08:49:36 GMT"), None);
+        assert_eq!(status("If-Modified-Since", "not a date"), None);
+        assert_eq!(preconditions(Request::post("/").header("If-Modified-Since", "Sun, 06 Nov 1994 08:49:37 GMT")), None);
+        assert_eq!(status("If-Unmodified-Since", "Sun, 06 Nov 1994 08:49:36 GMT"), Some(412));
+        assert_eq!(status("If-Unmodified-Since", "Sun, 06 Nov 1994 08:49:37 GMT"), None);
+        assert_eq!(Request::get("/").header("If-Modified-Since", "Sun, 06 Nov 1994 08:49:37 GMT").
+            preconditions(None, Some(from_seconds(MODIFIED) + std::time::Duration::from_millis(500))), Some(StatusCode::NOT_MODIFIED));
+    }
+
+    #[test]
+    fn follows_rfc_7232_precedence() {
+        let request = Request::get("/").
+            header("If-None-Match", "\"other\"").
+            header("If-Modified-Since", "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(preconditions(request), None);
+        let request = Request::get("/").
+            header("If-Match", "\"5-2ebc98a1\"").
+            header("If-Unmodified-Since", "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(preconditions(request), Some(412));
+        let request = Request::get("/").
+            header("If-Unmodified-Since", "Sun, 06 Nov 1994 08:49:36 GMT").
+            header("If-None-Match", "*");
+        assert_eq!(preconditions(request), Some(412));
+    }
+
+    #[test]
+    fn not_modified_keeps_validators() {
+        let response = Response::ok().
+            header("ETag", "W/\"5-2ebc98a1\"".to_string()).
+            header("Cache-Control", "max-age=60".to_string()).
+            content_type("text/plain").
+            entity(crate::ast::MessageBody::Slice(b"Hello"));
+        let response = conditional(&Request::get("/").header("If-None-Match", "W/\"5-2ebc98a1\""), response);
+        assert_eq!(response.to_string(), "HTTP/1.1 304 Not Modified\r\nCache-Control: max-age=60\r\nETag: W/\"5-2ebc98a1\"\r\n\r\n");
+    }
+}
@@ -0,0 +1,399 @@
+use std::io::{BufRead, BufReader, Write, Result};
+use std::fs::File;
+use std::path::Path;
+use std::net::IpAddr;
+use std::cmp::Reverse;
+use std::time::{SystemTime, Duration};
+use crate::api::*;
+use crate::date::*;
+use crate::uri::Authority;
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    pub host_only: bool,
+    pub path: String,
+    pub expires: Option<SystemTime>,
+    pub secure: bool,
+    pub http_only: bool,
+}
+
+impl Cookie {
+    pub fn new<N, V>(name: N, value: V) -> Cookie where N: Into<String>, V: Into<String> {
+        Cookie {
+            name: name.into(),
+            value: value.into(),
+            domain: String::new(),
+            host_only: true,
+            path: String::new(),
+            expires: None,
+            secure: false,
+            http_only: false,
+        }
+    }
+
+    /// Parses a `Set-Cookie` value following RFC 6265 section 5.2, `domain` and `path` are left
+    /// empty when the attributes are missing so they can be defaulted from the request
+    pub fn parse(set_cookie: &str) -> Option<Cookie> {
+        let mut parts = set_cookie.split(';');
+        let (name, value) = split_pair(parts.next()?)?;
+        if name.is_empty() {
+            return None;
+        }
+        let mut cookie = Cookie::new(name, value);
+        let mut max_age = None;
+
+        for attribute in parts {
+            let (name, value) = split_pair(attribute).unwrap_or((attribute.trim(), ""));
+            match name.to_ascii_lowercase().as_str() {
+                "expires" => if let Some(expires) = parse_cookie_date(value) {
+                    cookie.expires = Some(expires);
+                },
+                "max-age" => if let Some(delta) = parse_max_age(value) {
+                    max_age = Some(delta);
+                },
+                "domain" if !value.is_empty() => {
+                    cookie.domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    cookie.host_only = false;
+                },
+                "path" => {
+                    cookie.path = if value.starts_with('/') { value.to_string() } else { String::new() };
+                },
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                _ => {}
+            }
+        }
+
+        if let Some(delta) = max_age {
+            cookie.expires = Some(if delta <= 0 { from_seconds(0) } else { SystemTime::now() + Duration::from_secs(delta as u64) });
+        }
+        Some(cookie)
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.map(|expires| expires <= now).unwrap_or(false)
+    }
+
+    pub fn matches(&self, secure: bool, host: &str, path: &str) -> bool {
+        (secure || !self.secure) &&
+            (if self.host_only { host == self.domain } else { domain_match(host, &self.domain) }) &&
+            path_match(path, &self.path)
+    }
+}
+
+fn split_pair(value: &str) -> Option<(&str, &str)> {
+    let index = value.find('=')?;
+    Some((value[..index].trim(), value[index + 1..].trim()))
+}
+
+fn parse_max_age(value: &str) -> Option<i64> {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    if digits.is_empty() || !digits.bytes().all(|chr| chr.is_ascii_digit()) {
+        return None;
+    }
+    Some(value.parse().unwrap_or(if value.starts_with('-') { i64::MIN } else { i64::MAX }))
+}
+
+// RFC 6265 section 5.1.3
+pub fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain || (host.ends_with(domain) &&
+        host[..host.len() - domain.len()].ends_with('.') &&
+        host.parse::<IpAddr>().is_err())
+}
+
+// RFC 6265 section 5.1.4
+pub fn default_path(path: &str) -> &str {
+    if !path.starts_with('/') {
+        return "/";
+    }
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(index) => &path[..index],
+    }
+}
+
+pub fn path_match(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path || (request_path.starts_with(cookie_path) &&
+        (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/')))
+}
+
+fn host(authority: &str) -> String {
+    match Authority::parse(authority) {
+        Ok(authority) => authority.host.name().into_owned(),
+        Err(_) => authority.to_ascii_lowercase(),
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct CookieJar {
+    pub cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    pub fn new() -> CookieJar {
+        CookieJar::default()
+    }
+
+    /// Stores the cookie from a `Set-Cookie` header received in response to `uri` (RFC 6265 section 5.3)
+    pub fn set_cookie(&mut self, uri: &Uri, set_cookie: &str) {
+        if let Some(cookie) = Cookie::parse(set_cookie) {
+            self.store(uri, cookie);
+        }
+    }
+
+    pub fn store(&mut self, uri: &Uri, mut cookie: Cookie) {
+        let host = host(uri.authority.unwrap_or(""));
+        if cookie.host_only {
+            cookie.domain = host;
+        } else if !domain_match(&host, &cookie.domain) {
+            return;
+        }
+        if cookie.path.is_empty() {
+            cookie.path = default_path(uri.path).to_string();
+        }
+        if cookie.secure && uri.scheme != Some("https") {
+            return;
+        }
+
+        let existing = self.cookies.iter().position(|c| c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path);
+        if cookie.is_expired(SystemTime::now()) {
+            if let Some(index) = existing {
+                self.cookies.remove(index);
+            }
+            return;
+        }
+        match existing {
+            Some(index) => self.cookies[index] = cookie,
+            None => self.cookies.push(cookie),
+        }
+    }
+
+    /// Cookies to send to `uri`, longest paths first (RFC 6265 section 5.4)
+    pub fn cookies(&mut self, uri: &Uri) -> Vec<&Cookie> {
+        let now = SystemTime::now();
+        self.cookies.retain(|cookie| !cookie.is_expired(now));
+
+        let host = host(uri.authority.unwrap_or(""));
+        let path = if uri.path.is_empty() { "/" } else { uri.path };
+        let secure = uri.scheme == Some("https");
+        let mut result: Vec<&Cookie> = self.cookies.iter().filter(|cookie| cookie.matches(secure, &host, path)).collect();
+        result.sort_by_key(|cookie| Reverse(cookie.path.len()));
+        result
+    }
+
+    pub fn header(&mut self, uri: &Uri) -> Option<String> {
+        let cookies = self.cookies(uri);
+        if cookies.is_empty() {
+            return None;
+        }
+        Some(cookies.iter().map(|cookie| format!("{}={}", cookie.name, cookie.value)).collect::<Vec<_>>().join("; "))
+    }
+
+    /// Loads a jar saved in the Netscape `cookies.txt` format used by curl and wget
+    pub fn load<P>(path: P) -> Result<CookieJar> where P: AsRef<Path> {
+        let mut jar = CookieJar::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let (http_only, line) = match line.strip_prefix("#HttpOnly_") {
+                Some(rest) => (true, rest),
+                None => (false, line.as_str()),
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 7 {
+                continue;
+            }
+            let expires: i64 = fields[4].parse().unwrap_or(0);
+            jar.cookies.push(Cookie {
+                name: fields[5].to_string(),
+                value: fields[6].to_string(),
+                domain: fields[0].trim_start_matches('.').to_string(),
+                host_only: fields[1] != "TRUE",
+                path: fields[2].to_string(),
+                expires: if expires == 0 { None } else { Some(from_seconds(expires)) },
+                secure: fields[3] == "TRUE",
+                http_only,
+            });
+        }
+        Ok(jar)
+    }
+
+    pub fn save<P>(&self, path: P) -> Result<()> where P: AsRef<Path> {
+        let mut file = File::create(path)?;
+        writeln!(file, "# Netscape HTTP Cookie File")?;
+        for cookie in &self.cookies {
+            writeln!(file, "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                     if cookie.http_only { "#HttpOnly_" } else { "" },
+                     if cookie.host_only { "" } else { "." },
+                     cookie.domain,
+                     if cookie.host_only { "FALSE" } else { "TRUE" },
+                     cookie.path,
+                     if cookie.secure { "TRUE" } else { "FALSE" },
+                     cookie.expires.map(seconds).unwrap_or(0),
+                     cookie.name,
+                     cookie.value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sends matching cookies with each request and stores any `Set-Cookie` in the response
+pub struct CookieHandler<H> where H: HttpHandler {
+    handler: H,
+    pub jar: CookieJar,
+}
+
+impl<H> CookieHandler<H> where H: HttpHandler {
+    pub fn new(handler: H) -> CookieHandler<H> {
+        CookieHandler::with_jar(handler, CookieJar::new())
+    }
+
+    pub fn with_jar(handler: H, jar: CookieJar) -> CookieHandler<H> {
+        CookieHandler {
+            handler,
+            jar,
+        }
+    }
+}
+
+impl<H> HttpHandler for CookieHandler<H> where H: HttpHandler {
+    fn handle<F>(&mut self, request: &mut Request, mut fun: F) -> Result<()>
+        where F: FnMut(&mut Response) -> Result<()> + Sized {
+        let target = request.absolute_uri().unwrap_or_else(|| request.uri.to_string());
+        let uri = Uri::parse(&target);
+        if let Some(cookies) = self.jar.header(&uri) {
+            let value = match request.get_header("Cookie") {
+                Some(existing) => format!("{}; {}", existing, cookies),
+                None => cookies,
+            };
+            request.headers.replace("Cookie", value);
+        }
+        let jar = &mut self.jar;
+        self.handler.handle(request, |response| {
+            for set_cookie in response.headers.headers("Set-Cookie") {
+                jar.set_cookie(&uri, set_cookie);
+            }
+            fun(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::misc::TempDir;
+
+    #[test]
+    fn parses_set_cookie() {
+        let cookie = Cookie::parse("SID=31d4d96e407aad42; Path=/; Domain=.Example.com; Secure; HttpOnly; Expires=Wed, 09 Jun 2021 10:18:14 GMT").unwrap();
+        assert_eq!(cookie.name, "SID");
+        assert_eq!(cookie.value, "31d4d96e407aad42");
+        assert_eq!(cookie.path, "/");
+        assert_eq!(cookie.domain, "example.com");
+        assert!(!cookie.host_only);
+        assert!(cookie.secure);
+        assert!(cookie.http_only);
+        assert_eq!(cookie.expires.map(seconds), Some(1_623_233_894));
+    }
+
+    #[test]
+    fn ignores_invalid_set_cookie() {
+        assert_eq!(Cookie::parse("no-equals-sign"), None);
+        assert_eq!(Cookie::parse("=value"), None);
+        assert_eq!(Cookie::parse("lang=en-US; Path=relative").unwrap().path, "");
+        assert_eq!(Cookie::parse("lang=en-US; Expires=never").unwrap().expires, None);
+    }
+
+    #[test]
+    fn max_age_takes_precedence_over_expires() {
+        let cookie = Cookie::parse("lang=en; Max-Age=60; Expires=Thu, 01 Jan 1970 00:00:00 GMT").unwrap();
+        assert!(!cookie.is_expired(SystemTime::now()));
+        let expired = Cookie::parse("lang=en; Max-Age=0").unwrap();
+        assert!(expired.is_expired(SystemTime::now()));
+    }
+
+    #[test]
+    fn matches_domains_and_paths() {
+        assert!(domain_match("www.example.com", "example.com"));
+        assert!(!domain_match("www.notexample.com", "example.com"));
+        assert!(!domain_match("1.2.3.4", "2.3.4"));
+        assert!(path_match("/docs/web", "/docs"));
+        assert!(path_match("/docs/", "/docs/"));
+        assert!(!path_match("/docsets", "/docs"));
+        assert_eq!(default_path("/docs/web/index.html"), "/docs/web");
+        assert_eq!(default_path("/index.html"), "/");
+        assert_eq!(default_path(""), "/");
+    }
+
+    #[test]
+    fn jar_sends_matching_cookies() {
+        let mut jar = CookieJar::new();
+        jar.set_cookie(&Uri::parse("http://www.example.com/docs/index.html"), "a=1");
+        jar.set_cookie(&Uri::parse("http://www.example.com/"), "b=2; Domain=example.com; Path=/");
+        jar.set_cookie(&Uri::parse("http://www.example.com/"), "c=3; Secure");
+        jar.set_cookie(&Uri::parse("https://www.example.com/"), "d=4; Secure");
+        jar.set_cookie(&Uri::parse("http://www.example.com/"), "e=5; Domain=other.com");
+
+        assert_eq!(jar.header(&Uri::parse("http://www.example.com/docs/web")), Some("a=1; b=2".to_string()));
+        assert_eq!(jar.header(&Uri::parse("http://api.example.com/docs/web")), Some("b=2".to_string()));
+        assert_eq!(jar.header(&Uri::parse("https://www.example.com:8443/")), Some("b=2; d=4".to_string()));
+        assert_eq!(jar.header(&Uri::parse("http://other.com/")), None);
+    }
+
+    #[test]
+    fn jar_replaces_and_expires_cookies() {
+        let uri = Uri::parse("http://example.com/");
+        let mut jar = CookieJar::new();
+        jar.set_cookie(&uri, "a=1");
+        jar.set_cookie(&uri, "a=2");
+        assert_eq!(jar.header(&uri), Some("a=2".to_string()));
+        jar.set_cookie(&uri, "a=; Expires=Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(jar.header(&uri), None);
+    }
+
+    #[test]
+    fn jar_can_be_saved_and_loaded() {
+        let mut jar = CookieJar::new();
+        jar.set_cookie(&Uri::parse("https://example.com/"), "a=1; Domain=example.com; Secure; HttpOnly; Max-Age=3600");
+        jar.set_cookie(&Uri::parse("http://example.com/docs/"), "b=2");
+        let dir = TempDir::new("cookies");
+        let path = dir.join("cookies.txt");
+        jar.save(&path).unwrap();
+        let mut loaded = CookieJar::load(&path).unwrap();
+
+        assert_eq!(loaded.cookies.len(), 2);
+        assert_eq!(loaded.cookies[0].expires.map(seconds), jar.cookies[0].expires.map(seconds));
+        assert!(loaded.cookies[0].http_only);
+        assert_eq!(loaded.header(&Uri::parse("https://www.example.com/docs/")), Some("a=1".to_string()));
+        assert_eq!(loaded.header(&Uri::parse("https://example.com/docs/")), Some("b=2; a=1".to_string()));
+    }
+
+    struct Server;
+
+    impl HttpHandler for Server {
+        fn handle<F>(&mut self, request: &mut Request, mut fun: F) -> Result<()>
+            where F: FnMut(&mut Response) -> Result<()> + Sized {
+            match request.get_header("Cookie") {
+                Some(cookie) => fun(&mut Response::ok().header("X-Cookie", cookie.to_string())),
+                None => fun(&mut Response::ok().header("Set-Cookie", "session=abc; Path=/".to_string())),
+            }
+        }
+    }
+
+    #[test]
+    fn handler_round_trips_cookies() {
+        let mut handler = CookieHandler::new(Server);
+        handler.handle(&mut Request::get("/login").header("Host", "example.com"), |_| Ok(())).unwrap();
+        let mut echoed = None;
+        handler.handle(&mut Request::get("/account").header("Host", "example.com"), |response| {
+            echoed = response.get_header("X-Cookie").map(String::from);
+            Ok(())
+        }).unwrap();
+        assert_eq!(echoed, Some("session=abc".to_string()));
+    }
+}
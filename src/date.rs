@@ -0,0 +1,235 @@
+use std::time::{SystemTime, Duration, UNIX_EPOCH};
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+pub fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+pub fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 in the proleptic Gregorian calendar
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Inverse of `days_from_civil` returning year, month and day
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = if days >= 0 { days } else { days - 146_096 } / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    (year_of_era + era * 400 + if month <= 2 { 1 } else { 0 }, month as u32, day as u32)
+}
+
+pub fn timestamp(year: i64, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> Option<SystemTime> {
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let seconds = days_from_civil(year, month, day) * 86_400 + i64::from(hour * 3_600 + minute * 60 + second);
+    Some(from_seconds(seconds))
+}
+
+/// A two digit year more than 50 years after `now` is the most recent past year with those digits, RFC 7231 section 7.1.1.1
+pub fn full_year(year: u32, now: SystemTime) -> i64 {
+    let (current, _, _) = civil_from_days(seconds(now).div_euclid(86_400));
+    let year = current - current.rem_euclid(100) + i64::from(year);
+    if year > current + 50 {
+        year - 100
+    } else if year + 100 <= current + 50 {
+        year + 100
+    } else {
+        year
+    }
+}
+
+pub fn from_seconds(seconds: i64) -> SystemTime {
+    if seconds >= 0 {
+        UNIX_EPOCH + Duration::from_secs(seconds as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs())
+    }
+}
+
+pub fn seconds(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    }
+}
+
+// delimiter = %x09 / %x20-2F / %x3B-40 / %x5B-60 / %x7B-7E
+fn is_delimiter(chr: u8) -> bool {
+    chr == 0x09 || (0x20..=0x2F).contains(&chr) || (0x3B..=0x40).contains(&chr) || (0x5B..=0x60).contains(&chr) || (0x7B..=0x7E).contains(&chr)
+}
+
+// 1*max DIGIT ( non-digit *OCTET )
+fn leading_digits(token: &str, min: usize, max: usize) -> Option<u32> {
+    let count = token.bytes().take_while(u8::is_ascii_digit).count();
+    if count < min || count > max {
+        return None;
+    }
+    token[..count].parse().ok()
+}
+
+// hms-time = time-field ":" time-field ":" time-field
+fn time(token: &str) -> Option<(u32, u32, u32)> {
+    let mut fields = token.splitn(3, ':');
+    let hour = fields.next().filter(|field| field.len() <= 2).and_then(|field| leading_digits(field, 1, 2))?;
+    let minute = fields.next().filter(|field| field.len() <= 2).and_then(|field| leading_digits(field, 1, 2))?;
+    let second = fields.next().and_then(|field| leading_digits(field, 1, 2))?;
+    Some((hour, minute, second))
+}
+
+fn month(token: &str) -> Option<u32> {
+    let prefix = token.get(..3)?.to_ascii_lowercase();
+    MONTHS.iter().position(|month| *month == prefix).map(|index| index as u32 + 1)
+}
+
+/// Lenient date parsing for `Expires` as described by RFC 6265 section 5.1.1
+pub fn parse_cookie_date(value: &str) -> Option<SystemTime> {
+    let (mut found_time, mut found_day, mut found_month, mut found_year) = (None, None, None, None);
+    for token in value.split(|chr: char| chr.is_ascii() && is_delimiter(chr as u8)).filter(|token| !token.is_empty()) {
+        if found_time.is_none() {
+            if let Some(time) = time(token) {
+                found_time = Some(time);
+                continue;
+            }
+        }
+        if found_day.is_none() {
+            if let Some(day) = leading_digits(token, 1, 2) {
+                found_day = Some(day);
+                continue;
+            }
+        }
+        if found_month.is_none() {
+            if let Some(month) = month(token) {
+                found_month = Some(month);
+                continue;
+            }
+        }
+        if found_year.is_none() {
+            if let Some(year) = leading_digits(token, 2, 4) {
+                found_year = Some(year);
+                continue;
+            }
+        }
+    }
+
+    let year = match found_year? {
+        year @ 70..=99 => year + 1900,
+        year @ 0..=69 => year + 2000,
+        year => year,
+    };
+    let (hour, minute, second) = found_time?;
+    if year < 1601 || second > 59 {
+        return None;
+    }
+    timestamp(i64::from(year), found_month?, found_day?, hour, minute, second)
+}
+
+/// Parses any of the three formats allowed for `HTTP-date` (RFC 7231 section 7.1.1.1)
+pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+    use crate::grammar::http_date;
+
+    match http_date(value.trim().as_bytes()) {
+        Ok(([], time)) => Some(time),
+        _ => None,
+    }
+}
+
+/// Formats as IMF-fixdate, the preferred `HTTP-date` format e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+pub fn format_http_date(time: SystemTime) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const NAMES: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let seconds = seconds(time);
+    let days = seconds.div_euclid(86_400);
+    let time_of_day = seconds.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    format!("{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT", DAYS[days.rem_euclid(7) as usize], day, NAMES[month as usize - 1], year,
+            time_of_day / 3_600, time_of_day % 3_600 / 60, time_of_day % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_digit_years_are_at_most_50_years_ahead() {
+        let now = timestamp(2026, 10, 18, 0, 0, 0).unwrap();
+        assert_eq!(full_year(76, now), 2076);
+        assert_eq!(full_year(77, now), 1977);
+        assert_eq!(full_year(94, now), 1994);
+        assert_eq!(full_year(26, now), 2026);
+        assert_eq!(full_year(0, now), 2000);
+        let later = timestamp(2090, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(full_year(40, later), 2140);
+        assert_eq!(full_year(41, later), 2041);
+    }
+
+    #[test]
+    fn days_from_civil() {
+        assert_eq!(super::days_from_civil(1970, 1, 1), 0);
+        assert_eq!(super::days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(super::days_from_civil(1969, 12, 31), -1);
+    }
+
+    #[test]
+    fn civil_from_days() {
+        assert_eq!(super::civil_from_days(0), (1970, 1, 1));
+        assert_eq!(super::civil_from_days(11_017), (2000, 3, 1));
+        assert_eq!(super::civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn formats_http_dates() {
+        assert_eq!(format_http_date(from_seconds(784_111_777)), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(format_http_date(from_seconds(0)), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(format_http_date(from_seconds(951_782_400)), "Tue, 29 Feb 2000 00:00:00 GMT");
+        assert_eq!(parse_http_date(&format_http_date(from_seconds(1_623_233_894))).map(seconds), Some(1_623_233_894));
+    }
+
+    #[test]
+    fn timestamp_validates_fields() {
+        assert_eq!(timestamp(1994, 11, 6, 8, 49, 37).map(seconds), Some(784_111_777));
+        assert_eq!(timestamp(2019, 2, 29, 0, 0, 0), None);
+        assert!(timestamp(2020, 2, 29, 0, 0, 0).is_some());
+        assert_eq!(timestamp(2020, 13, 1, 0, 0, 0), None);
+    }
+
+    #[test]
+    fn parses_cookie_dates() {
+        assert_eq!(parse_cookie_date("Sun, 06 Nov 1994 08:49:37 GMT").map(seconds), Some(784_111_777));
+        assert_eq!(parse_cookie_date("Sunday, 06-Nov-94 08:49:37 GMT").map(seconds), Some(784_111_777));
+        assert_eq!(parse_cookie_date("Sun Nov  6 08:49:37 1994").map(seconds), Some(784_111_777));
+        assert_eq!(parse_cookie_date("Wed, 09 Jun 2021 10:18:14 GMT").map(seconds), Some(1_623_233_894));
+        assert_eq!(parse_cookie_date("Thu, 01-Jan-1970 00:00:00 GMT").map(seconds), Some(0));
+    }
+
+    #[test]
+    fn rejects_invalid_cookie_dates() {
+        assert_eq!(parse_cookie_date(""), None);
+        assert_eq!(parse_cookie_date("Sun, 06 Nov 1994"), None);
+        assert_eq!(parse_cookie_date("Sun, 31 Nov 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_cookie_date("Sun, 06 Nov 1994 24:49:37 GMT"), None);
+        assert_eq!(parse_cookie_date("Sun, 06 Nov 1600 08:49:37 GMT"), None);
+    }
+}
@@ -0,0 +1,172 @@
+use std::borrow::Cow;
+use std::{error, fmt, io};
+use crate::framing::Violation;
+use crate::status::StatusCode;
+
+/// Every failure in the crate, carried inside `io::Error` so handlers keep returning `io::Result`
+#[derive(Debug)]
+pub enum Error {
+    /// `rule` is the grammar rule that failed and `offset` how far into its input it got
+    Parse { rule: String, offset: usize },
+    /// The input ended before a complete message
+    Incomplete,
+    LimitExceeded(Limit),
+    Io(io::Error),
+    Timeout,
+    Protocol(Cow<'static, str>),
+    Framing(Violation),
+    /// A server this one depends on, such as a proxy, failed or answered with something unusable
+    Upstream(Cow<'static, str>),
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Limit {
+    Head,
+    ChunkLine,
+    PartHead,
+    Form,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
+        format.write_str(match *self {
+            Limit::Head => "Message head",
+            Limit::ChunkLine => "Chunk line",
+            Limit::PartHead => "Multipart part head",
+            Limit::Form => "Form body",
+        })
+    }
+}
+
+impl Error {
+    pub fn parse<R>(rule: R, offset: usize) -> Error where R: Into<String> {
+        Error::Parse { rule: rule.into(), offset }
+    }
+
+    pub fn protocol<M>(message: M) -> Error where M: Into<Cow<'static, str>> {
+        Error::Protocol(message.into())
+    }
+
+    pub fn upstream<M>(message: M) -> Error where M: Into<Cow<'static, str>> {
+        Error::Upstream(message.into())
+    }
+
+    /// The `Error` inside an `io::Error` if there is one
+    pub fn of(error: &io::Error) -> Option<&Error> {
+        error.get_ref().and_then(|inner| inner.downcast_ref::<Error>())
+    }
+
+    /// The status a server should respond with, `None` when the connection can not be answered
+    pub fn status(&self) -> Option<StatusCode> {
+        match *self {
+            Error::Parse { .. } | Error::Protocol(_) | Error::Framing(_) => Some(StatusCode::BAD_REQUEST),
+            Error::LimitExceeded(Limit::Head) => Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE),
+            Error::LimitExceeded(_) => Some(StatusCode::CONTENT_TOO_LARGE),
+            Error::Timeout => Some(StatusCode::REQUEST_TIMEOUT),
+            Error::Upstream(_) => Some(StatusCode::BAD_GATEWAY),
+            Error::Incomplete | Error::Io(_) => None,
+        }
+    }
+
+    fn kind(&self) -> io::ErrorKind {
+        match *self {
+            Error::Io(ref error) => error.kind(),
+            Error::Incomplete => io::ErrorKind::UnexpectedEof,
+            Error::Timeout => io::ErrorKind::TimedOut,
+            _ => io::ErrorKind::InvalidData,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Parse { ref rule, offset } => write!(format, "Invalid {} at byte {}", rule, offset),
+            Error::Incomplete => format.write_str("Input ended before the message was complete"),
+            Error::LimitExceeded(limit) => write!(format, "{} is too large", limit),
+            Error::Io(ref error) => write!(format, "{}", error),
+            Error::Timeout => format.write_str("Timed out"),
+            Error::Protocol(ref message) | Error::Upstream(ref message) => format.write_str(message),
+            Error::Framing(violation) => write!(format, "{}", violation),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref error) => Some(error),
+            Error::Framing(ref violation) => Some(violation),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> io::Error {
+        match error {
+            Error::Io(error) => error,
+            error => io::Error::new(error.kind(), error),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        if Error::of(&error).is_some() {
+            let kind = error.kind();
+            return match error.into_inner().map(|inner| inner.downcast::<Error>()) {
+                Some(Ok(inner)) => *inner,
+                _ => Error::Io(io::Error::from(kind)),
+            };
+        }
+        match error.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Error::Timeout,
+            _ => Error::Io(error),
+        }
+    }
+}
+
+impl From<Violation> for Error {
+    fn from(violation: Violation) -> Error {
+        Error::Framing(violation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_io_error() {
+        let error: io::Error = Error::parse("request_line", 4).into();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "Invalid request_line at byte 4");
+        assert!(matches!(Error::of(&error), Some(Error::Parse { offset: 4, .. })));
+        assert!(matches!(Error::from(error), Error::Parse { ref rule, offset: 4 } if rule == "request_line"));
+
+        let error: io::Error = Error::Incomplete.into();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(matches!(Error::from(error), Error::Incomplete));
+    }
+
+    #[test]
+    fn wraps_plain_io_errors() {
+        assert!(matches!(Error::from(io::Error::from(io::ErrorKind::TimedOut)), Error::Timeout));
+        let error = Error::from(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert!(matches!(error, Error::Io(ref e) if e.kind() == io::ErrorKind::ConnectionReset));
+        assert_eq!(io::Error::from(error).kind(), io::ErrorKind::ConnectionReset);
+    }
+
+    #[test]
+    fn maps_to_status() {
+        assert_eq!(Error::parse("header_field", 0).status(), Some(StatusCode(400)));
+        assert_eq!(Error::Framing(Violation::ObsFold).status(), Some(StatusCode(400)));
+        assert_eq!(Error::LimitExceeded(Limit::Head).status(), Some(StatusCode(431)));
+        assert_eq!(Error::LimitExceeded(Limit::PartHead).status(), Some(StatusCode(413)));
+        assert_eq!(Error::LimitExceeded(Limit::Form).status(), Some(StatusCode(413)));
+        assert_eq!(Error::Timeout.status(), Some(StatusCode(408)));
+        assert_eq!(Error::upstream("Proxy refused tunnel").status(), Some(StatusCode(502)));
+        assert_eq!(Error::Incomplete.status(), None);
+    }
+}
@@ -0,0 +1,689 @@
+use std::path::{Path, PathBuf};
+use std::fs::{File, Metadata, canonicalize, symlink_metadata};
+use std::io::{self, Read, Seek, SeekFrom, Result};
+use std::time::SystemTime;
+use crate::api::{HttpHandler, Request, Response, Uri, Method, MediaType};
+use crate::mime::{MimeTypes, sniff, SNIFF_LENGTH};
+use crate::negotiation::not_acceptable;
+use crate::conditional::{conditional, weak_etag};
+use crate::date::seconds;
+use crate::range::ranged;
+use crate::header::{AcceptEncoding, Allow, EntityTag, LastModified};
+use crate::ast::MessageBody;
+use crate::form::{percent_decode, percent_decode_bytes};
+use crate::error::Error;
+use crate::listing;
+use crate::uri::{encode, Component};
+
+/// What to do when a requested path goes through a symbolic link
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum Symlinks {
+    Follow,
+    Deny,
+    /// Follow links whose target is inside the base directory
+    #[default]
+    WithinBase,
+}
+
+/// Content codings in order of preference with the extension of their precompressed siblings
+const PRECOMPRESSED: [(&str, &str); 3] = [("br", "br"), ("zstd", "zst"), ("gzip", "gz")];
+
+/// `HEAD` runs the same code as `GET`, the server leaves out the body
+const ALLOW: [Method<'static>; 3] = [Method::Get, Method::Head, Method::Options];
+
+pub struct FileHandler<T: AsRef<Path>> {
+    base: T,
+    mime_types: MimeTypes,
+    charset: Option<String>,
+    nosniff: bool,
+    index: Vec<String>,
+    listing: bool,
+    hidden: bool,
+    symlinks: Symlinks,
+    precompressed: bool,
+}
+
+impl<T: AsRef<Path>> FileHandler<T> {
+    pub fn new(base: T) -> FileHandler<T> {
+        FileHandler {
+            base,
+            mime_types: MimeTypes::default(),
+            charset: Some("utf-8".to_string()),
+            nosniff: false,
+            index: vec!("index.html".to_string()),
+            listing: false,
+            hidden: false,
+            symlinks: Symlinks::default(),
+            precompressed: false,
+        }
+    }
+
+    /// Replaces the built-in extension table, e.g. `MimeTypes::default().load("/etc/mime.types")?`
+    pub fn mime_types(mut self, mime_types: MimeTypes) -> FileHandler<T> {
+        self.mime_types = mime_types;
+        self
+    }
+
+    /// Added to `text/*` media types, `None` leaves them without a charset
+    pub fn charset(mut self, charset: Option<&str>) -> FileHandler<T> {
+        self.charset = charset.map(String::from);
+        self
+    }
+
+    /// Files looked for in order when a directory is requested, `index.html` by default
+    pub fn index(mut self, index: &[&str]) -> FileHandler<T> {
+        self.index = index.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    /// Lists directories without an index as HTML or JSON depending on `Accept`
+    pub fn listing(mut self, listing: bool) -> FileHandler<T> {
+        self.listing = listing;
+        self
+    }
+
+    /// Includes names starting with `.` in listings
+    pub fn hidden(mut self, hidden: bool) -> FileHandler<T> {
+        self.hidden = hidden;
+        self
+    }
+
+    pub fn symlinks(mut self, symlinks: Symlinks) -> FileHandler<T> {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Serves `app.js.br`, `app.js.zst` or `app.js.gz` in place of `app.js` when `Accept-Encoding` allows it
+    pub fn precompressed(mut self, precompressed: bool) -> FileHandler<T> {
+        self.precompressed = precompressed;
+        self
+    }
+
+    /// Sends `X-Content-Type-Options: nosniff` so browsers trust the media type we send
+    pub fn nosniff(mut self, nosniff: bool) -> FileHandler<T> {
+        self.nosniff = nosniff;
+        self
+    }
+
+    pub fn get(&self, path: &str) -> Result<Response<'_>> {
+        self.respond(&Request::get(path), path)
+    }
+
+    fn respond(&self, request: &Request, path: &str) -> Result<Response<'_>> {
+        let segments = decode_path(path)?;
+        let root = canonicalize(self.base.as_ref()).map_err(io::Error::other)?;
+        let mut full_path = root.clone();
+        for segment in &segments {
+            full_path = self.step(&root, full_path.join(segment))?;
+        }
+        let file: File = File::open(&full_path)?;
+        let metadata: Metadata = file.metadata()?;
+        if metadata.is_dir() {
+            return self.directory(request, &root, &full_path, &segments);
+        }
+        self.file(request, &root, &full_path, file, metadata)
+    }
+
+    /// Applies the symlink policy to one more segment of a path that is already inside `root`
+    fn step(&self, root: &Path, path: PathBuf) -> Result<PathBuf> {
+        if !symlink_metadata(&path)?.file_type().is_symlink() {
+            return Ok(path);
+        }
+        match self.symlinks {
+            Symlinks::Follow => canonicalize(&path),
+            Symlinks::Deny => Err(io::Error::new(io::ErrorKind::PermissionDenied, "Symbolic links are not allowed")),
+            Symlinks::WithinBase => {
+                let target = canonicalize(&path)?;
+                if !target.starts_with(root) {
+                    return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Symbolic link points outside of base"));
+                }
+                Ok(target)
+            }
+        }
+    }
+
+    /// Negotiation, preconditions and ranges are checked in that order once the file is found
+    fn file(&self, request: &Request, root: &Path, path: &Path, mut file: File, metadata: Metadata) -> Result<Response<'_>> {
+        let media_type = self.media_type(path, &mut file)?;
+        let siblings = self.siblings(root, path);
+        let (chosen, file, metadata) = match encoding(request, &siblings) {
+            Some(Some((chosen, sibling))) => {
+                let file = File::open(sibling)?;
+                let metadata = file.metadata()?;
+                (Some(*chosen), file, metadata)
+            }
+            Some(None) => (None, file, metadata),
+            None => return Ok(Response::not_acceptable().
+                header("Vary", "Accept-Encoding".to_string()).
+                message("No acceptable content coding")),
+        };
+        let modified = metadata.modified()?;
+        let mut response = Response::ok().
+            content_type(media_type).
+            header("ETag", etag(metadata.len(), modified, chosen)).
+            typed(&LastModified(modified)).
+            content_length(metadata.len());
+        if let Some(chosen) = chosen {
+            response = response.header("Content-Encoding", chosen.to_string());
+        }
+        if !siblings.is_empty() {
+            response = response.header("Vary", "Accept-Encoding".to_string());
+        }
+        if self.nosniff {
+            response = response.header("X-Content-Type-Options", "nosniff".to_string());
+        }
+        let response = conditional(request, negotiate(request, response));
+        if response.code != 200 {
+            return Ok(response);
+        }
+        Ok(ranged(request, response, file))
+    }
+
+    /// Directories are only served with a trailing slash so relative links in the index resolve inside them
+    fn directory(&self, request: &Request, root: &Path, path: &Path, segments: &[String]) -> Result<Response<'_>> {
+        let original = request.original_path();
+        if !original.ends_with('/') {
+            // rebuilt from the normalized path as an empty segment up front would make it protocol-relative
+            let mut location = String::new();
+            for segment in request.base.split('/').filter(|segment| !segment.is_empty()) {
+                location.push('/');
+                location.push_str(segment);
+            }
+            for segment in segments {
+                location.push('/');
+                location.push_str(&encode(segment, Component::Segment));
+            }
+            let location = match request.uri.query {
+                Some(query) => format!("{}/?{}", location, query),
+                None => format!("{}/", location),
+            };
+            return Ok(Response::moved_permanently().header("Location", location));
+        }
+        for index in &self.index {
+            let index = match self.step(root, path.join(index)) {
+                Ok(index) => index,
+                Err(ref error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error),
+            };
+            if let Ok(file) = File::open(&index) {
+                let metadata = file.metadata()?;
+                if metadata.is_file() {
+                    return self.file(request, root, &index, file, metadata);
+                }
+            }
+        }
+        if !self.listing {
+            return Ok(Response::not_found().message("Path denotes a directory"));
+        }
+        let available = [MediaType::new("text", "html").parameter("charset", "utf-8"), MediaType::new("application", "json")];
+        let (media_type, body) = match request.negotiate_media_type(&available) {
+            Some(media_type) if media_type.is("application/json") => (media_type, listing::json(&listing::entries(path, self.hidden)?)),
+            Some(media_type) => (media_type, listing::html(&percent_decode(&original), &listing::entries(path, self.hidden)?)),
+            None => return Ok(not_acceptable(&available)),
+        };
+        Ok(Response::ok().
+            content_type(media_type).
+            entity(MessageBody::Owned(body.into_bytes())))
+    }
+
+    /// Precompressed files next to `path` that pass the symlink policy, in order of preference
+    fn siblings(&self, root: &Path, path: &Path) -> Vec<(&'static str, PathBuf)> {
+        if !self.precompressed {
+            return vec!();
+        }
+        PRECOMPRESSED.iter().filter_map(|(encoding, extension)| {
+            let mut sibling = path.as_os_str().to_owned();
+            sibling.push(".");
+            sibling.push(extension);
+            let sibling = self.step(root, PathBuf::from(sibling)).ok()?;
+            if !sibling.metadata().ok()?.is_file() {
+                return None;
+            }
+            Some((*encoding, sibling))
+        }).collect()
+    }
+
+    /// By extension, falling back to sniffing the start of the file
+    fn media_type(&self, path: &Path, file: &mut File) -> Result<String> {
+        let media_type = match self.mime_types.lookup(path) {
+            Some(media_type) => media_type.to_string(),
+            None => {
+                let mut start = Vec::with_capacity(SNIFF_LENGTH);
+                file.by_ref().take(SNIFF_LENGTH as u64).read_to_end(&mut start)?;
+                file.seek(SeekFrom::Start(0))?;
+                sniff(&start).to_string()
+            }
+        };
+        match self.charset {
+            Some(ref charset) if is_text(&media_type) => Ok(MediaType::parse(&media_type)?.parameter("charset", charset.as_str()).to_string()),
+            _ => Ok(media_type),
+        }
+    }
+}
+
+fn is_text(media_type: &str) -> bool {
+    MediaType::parse(media_type).is_ok_and(|media_type| media_type.main_type.eq_ignore_ascii_case("text") && media_type.get("charset").is_none())
+}
+
+/// The sibling to send instead of the file, clients without a valid `Accept-Encoding` get the file itself.
+/// `None` when the request refuses the file and every sibling.
+fn encoding<'s>(request: &Request, siblings: &'s [(&'static str, PathBuf)]) -> Option<Option<&'s (&'static str, PathBuf)>> {
+    if siblings.is_empty() || !matches!(request.get_typed::<AcceptEncoding>(), Some(Ok(_))) {
+        return Some(None);
+    }
+    let available: Vec<&str> = siblings.iter().map(|(encoding, _)| *encoding).chain(Some("identity")).collect();
+    let chosen = request.negotiate_encoding(&available)?;
+    Some(siblings.iter().find(|(encoding, _)| *encoding == chosen))
+}
+
+/// Each content coding is a different representation so it needs its own tag
+fn etag(length: u64, modified: SystemTime, encoding: Option<&str>) -> String {
+    match encoding {
+        Some(encoding) => EntityTag::weak(&format!("{:x}-{:x}-{}", length, seconds(modified), encoding)).to_string(),
+        None => weak_etag(length, modified),
+    }
+}
+
+/// Keeps the response when the request accepts its media type, otherwise `406 Not Acceptable`
+fn negotiate<'r>(request: &Request, response: Response<'r>) -> Response<'r> {
+    let available = match response.get_header("Content-Type").map(MediaType::parse) {
+        Some(Ok(media_type)) => [media_type],
+        _ => return response,
+    };
+    if request.negotiate_media_type(&available).is_some() {
+        return response;
+    }
+    not_acceptable(&available)
+}
+
+/// Decodes and normalizes a request path into the segments to look up under the base directory.
+/// Encoded separators, NUL and invalid UTF-8 are rejected and so is `..` above the root.
+pub fn decode_path(path: &str) -> Result<Vec<String>> {
+    let mut segments: Vec<String> = vec!();
+    let mut offset = 0;
+    for raw in path.split('/') {
+        let lower = raw.to_ascii_lowercase();
+        if let Some(index) = ["%2f", "%5c", "%00"].iter().filter_map(|escape| lower.find(escape)).min() {
+            return Err(Error::parse("path", offset + index).into());
+        }
+        let decoded = String::from_utf8(percent_decode_bytes(raw).into_owned()).map_err(|_| Error::parse("path", offset))?;
+        if let Some(index) = raw.find(['\\', '\0']) {
+            return Err(Error::parse("path", offset + index).into());
+        }
+        match decoded.as_str() {
+            "" | "." => {},
+            ".." => if segments.pop().is_none() {
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Path escapes the base directory"));
+            },
+            _ => segments.push(decoded),
+        }
+        offset += raw.len() + 1;
+    }
+    Ok(segments)
+}
+
+/// Malformed paths are `400`, escapes and permissions `403`, missing files `404` and anything else `500`
+fn error_response(error: &io::Error) -> Response<'static> {
+    if let Some(status) = Error::of(error).and_then(Error::status) {
+        return Response::status(status);
+    }
+    match error.kind() {
+        io::ErrorKind::NotFound | io::ErrorKind::NotADirectory | io::ErrorKind::InvalidFilename => Response::not_found().message("Not Found"),
+        io::ErrorKind::PermissionDenied => Response::forbidden().message("Forbidden"),
+        _ => Response::internal_server_error(),
+    }
+}
+
+impl<T: AsRef<Path>> HttpHandler for FileHandler<T> {
+    fn handle<F>(&mut self, request: &mut Request, mut fun: F) -> Result<()>
+        where F: FnMut(&mut Response) -> Result<()> + Sized {
+        fun(&mut match *request {
+            Request { method: Method::Get | Method::Head, uri: Uri { path, .. }, .. } => {
+                self.respond(request, path).unwrap_or_else(|error| error_response(&error))
+            }
+            Request { method: Method::Options, .. } => Response::ok().typed(&Allow(ALLOW.to_vec())),
+            _ => Response::method_not_allowed().typed(&Allow(ALLOW.to_vec())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::WriteTo;
+    use crate::misc::TempDir;
+
+    fn body(response: &mut Response) -> Result<String> {
+        let mut body = Vec::new();
+        response.entity.write_to(&mut body)?;
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+
+    fn content_type<T: AsRef<Path>>(handler: &mut FileHandler<T>, request: Request) -> (u16, Option<String>) {
+        let mut request = request;
+        let mut result = (0, None);
+        handler.handle(&mut request, |response| {
+            result = (response.code, response.get_header("Content-Type").map(String::from));
+            Ok(())
+        }).unwrap();
+        result
+    }
+
+    #[test]
+    fn negotiates_media_type() {
+        let base = TempDir::new("negotiate");
+        std::fs::write(base.join("hello.txt"), "Hello").unwrap();
+
+        let mut handler = FileHandler::new(base.to_path_buf());
+        let mut codes = vec!();
+        for accept in ["text/*", "image/png, */*;q=0.1", "image/png", "text/plain;q=0"] {
+            codes.push(content_type(&mut handler, Request::get("/hello.txt").header("Accept", accept)).0);
+        }
+        assert_eq!(codes, vec!(200, 200, 406, 406));
+    }
+
+    #[test]
+    fn detects_media_type_from_extension_then_content() {
+        let base = TempDir::new("mime");
+        std::fs::write(base.join("index.html"), "<p>Hi</p>").unwrap();
+        std::fs::write(base.join("logo.png"), "not really a png").unwrap();
+        std::fs::write(base.join("image"), b"\x89PNG\r\n\x1a\n\0\0").unwrap();
+        std::fs::write(base.join("page"), "<!DOCTYPE html>").unwrap();
+        std::fs::write(base.join("Cargo.toml"), "[package]").unwrap();
+
+        let mut handler = FileHandler::new(base.to_path_buf());
+        let mut types = vec!();
+        for path in ["/index.html", "/logo.png", "/image", "/page", "/Cargo.toml"] {
+            types.push(content_type(&mut handler, Request::get(path)).1.unwrap());
+        }
+        let mut handler = FileHandler::new(base.to_path_buf()).
+            mime_types(MimeTypes::default().insert("toml", "application/toml")).
+            charset(None).
+            nosniff(true);
+        let mut nosniff = None;
+        handler.handle(&mut Request::get("/page"), |response| {
+            nosniff = response.get_header("X-Content-Type-Options").map(String::from);
+            Ok(())
+        }).unwrap();
+        types.push(content_type(&mut handler, Request::get("/index.html")).1.unwrap());
+        types.push(content_type(&mut handler, Request::get("/Cargo.toml")).1.unwrap());
+
+        assert_eq!(types, vec!("text/html; charset=utf-8", "image/png", "image/png", "text/html; charset=utf-8", "text/plain; charset=utf-8",
+                               "text/html", "application/toml"));
+        assert_eq!(nosniff, Some("nosniff".to_string()));
+    }
+
+    #[test]
+    fn sends_validators_and_honours_conditional_requests() {
+        let base = TempDir::new("conditional");
+        std::fs::write(base.join("hello.txt"), "Hello").unwrap();
+        let mut handler = FileHandler::new(base.to_path_buf());
+        let mut validators = (None, None);
+        handler.handle(&mut Request::get("/hello.txt"), |response| {
+            validators = (response.get_header("ETag").map(String::from), response.get_header("Last-Modified").map(String::from));
+            Ok(())
+        }).unwrap();
+        let (etag, last_modified) = (validators.0.unwrap(), validators.1.unwrap());
+        assert!(etag.starts_with("W/\"5-"));
+
+        let mut codes = vec!();
+        for (name, value) in [("If-None-Match", etag.as_str()), ("If-Modified-Since", last_modified.as_str()),
+                              ("If-Match", etag.as_str()), ("If-Unmodified-Since", "Sun, 06 Nov 1994 08:49:37 GMT"),
+                              ("If-None-Match", "\"other\"")] {
+            codes.push(content_type(&mut handler, Request::get("/hello.txt").header(name, value)).0);
+        }
+        assert_eq!(codes, vec!(304, 304, 412, 412, 200));
+    }
+
+    #[test]
+    fn if_match_only_matches_any_as_file_etags_are_weak() {
+        let base = TempDir::new("if-match");
+        std::fs::write(base.join("hello.txt"), "Hello").unwrap();
+        let mut handler = FileHandler::new(base.to_path_buf());
+        let mut etag = None;
+        handler.handle(&mut Request::get("/hello.txt"), |response| {
+            etag = response.get_header("ETag").map(String::from);
+            Ok(())
+        }).unwrap();
+        let etag = etag.unwrap();
+        let strong = etag.trim_start_matches("W/").to_string();
+
+        let codes: Vec<_> = [etag.as_str(), strong.as_str(), "*"].iter().
+            map(|value| content_type(&mut handler, Request::get("/hello.txt").header("If-Match", *value)).0).
+            collect();
+        assert_eq!(codes, vec!(412, 412, 200));
+    }
+
+    #[test]
+    fn answers_head_and_options() {
+        let base = TempDir::new("head");
+        std::fs::write(base.join("hello.txt"), "Hello").unwrap();
+
+        let mut handler = FileHandler::new(base.to_path_buf());
+        let mut results = vec!();
+        for mut request in [Request::head("/hello.txt"), Request::options("/hello.txt"), Request::delete("/hello.txt")] {
+            handler.handle(&mut request, |response| {
+                results.push((response.code, response.get_header("Content-Length").map(String::from), response.get_header("Allow").map(String::from)));
+                Ok(())
+            }).unwrap();
+        }
+        assert_eq!(results, vec!((200, Some("5".to_string()), None),
+                                 (200, Some("0".to_string()), Some("GET, HEAD, OPTIONS".to_string())),
+                                 (405, Some("0".to_string()), Some("GET, HEAD, OPTIONS".to_string()))));
+    }
+
+    #[test]
+    fn serves_precompressed_siblings() {
+        let base = TempDir::new("precompressed");
+        std::fs::write(base.join("app.js"), "let app = 1;").unwrap();
+        std::fs::write(base.join("app.js.gz"), "gzipped").unwrap();
+        std::fs::write(base.join("app.js.br"), "brotli").unwrap();
+        std::fs::write(base.join("plain.txt"), "Hello").unwrap();
+
+        let mut handler = FileHandler::new(base.to_path_buf()).precompressed(true);
+        let mut results = vec!();
+        for (path, accept) in [("/app.js", "gzip, br"), ("/app.js", "gzip"), ("/app.js", "br;q=0, gzip;q=0"), ("/app.js", ""), ("/plain.txt", "gzip")] {
+            let mut request = Request::get(path);
+            if !accept.is_empty() {
+                request = request.header("Accept-Encoding", accept);
+            }
+            handler.handle(&mut request, |response| {
+                results.push((response.get_header("Content-Encoding").map(String::from),
+                              response.get_header("Content-Type").unwrap().to_string(),
+                              response.get_header("Vary").map(String::from),
+                              response.get_header("ETag").unwrap().to_string(),
+                              body(response)?));
+                Ok(())
+            }).unwrap();
+        }
+        let mut plain = vec!();
+        FileHandler::new(base.to_path_buf()).handle(&mut Request::get("/app.js").header("Accept-Encoding", "br"), |response| {
+            plain.push((response.get_header("Content-Encoding").map(String::from), body(response)?));
+            Ok(())
+        }).unwrap();
+
+        let encodings: Vec<_> = results.iter().map(|(encoding, _, _, _, body)| (encoding.as_deref(), body.as_str())).collect();
+        assert_eq!(encodings, vec!((Some("br"), "brotli"), (Some("gzip"), "gzipped"), (None, "let app = 1;"), (None, "let app = 1;"), (None, "Hello")));
+        assert!(results[..4].iter().all(|(_, content_type, vary, _, _)| content_type == "text/javascript; charset=utf-8" && vary.as_deref() == Some("Accept-Encoding")));
+        assert_eq!(results[4].2, None);
+        assert!(results[0].3.ends_with("-br\"") && results[1].3.ends_with("-gzip\""));
+        assert_ne!(results[2].3, results[0].3);
+        assert_eq!(plain, vec!((None, "let app = 1;".to_string())));
+    }
+
+    #[test]
+    fn refuses_when_no_encoding_is_acceptable() {
+        let base = TempDir::new("unacceptable");
+        std::fs::write(base.join("app.js"), "let app = 1;").unwrap();
+        std::fs::write(base.join("app.js.gz"), "gzipped").unwrap();
+
+        let mut handler = FileHandler::new(base.to_path_buf()).precompressed(true);
+        let identity = respond(&mut handler, Request::get("/app.js").header("Accept-Encoding", "identity;q=0, br"));
+        let any = respond(&mut handler, Request::get("/app.js").header("Accept-Encoding", "*;q=0"));
+        let gzip = respond(&mut handler, Request::get("/app.js").header("Accept-Encoding", "identity;q=0, gzip"));
+
+        assert_eq!((identity.0, any.0), (406, 406));
+        assert_eq!((gzip.0, gzip.3), (200, "gzipped".to_string()));
+    }
+
+    #[test]
+    fn serves_byte_ranges() {
+        let base = TempDir::new("range");
+        std::fs::write(base.join("alphabet.txt"), "abcdefghijklmnopqrstuvwxyz").unwrap();
+        let mut handler = FileHandler::new(base.to_path_buf());
+        let (mut results, mut files) = (vec!(), vec!());
+        for range in ["bytes=2-4", "bytes=0-0,-1", "bytes=30-"] {
+            handler.handle(&mut Request::get("/alphabet.txt").header("Range", range), |response| {
+                files.push(matches!(response.entity, MessageBody::File(..)));
+                let body = body(response)?;
+                results.push((response.code, response.get_header("Content-Range").map(String::from), body.len()));
+                Ok(())
+            }).unwrap();
+        }
+        assert_eq!(results[0], (206, Some("bytes 2-4/26".to_string()), 3));
+        assert_eq!((results[1].0, results[1].1.clone()), (206, None));
+        assert_eq!(results[2], (416, Some("bytes */26".to_string()), 0));
+        assert_eq!(files, vec!(true, false, false));
+    }
+
+    fn respond<T: AsRef<Path>>(handler: &mut FileHandler<T>, request: Request) -> (u16, Option<String>, Option<String>, String) {
+        let mut request = request;
+        let mut result = (0, None, None, String::new());
+        handler.handle(&mut request, |response| {
+            let body = body(response)?;
+            result = (response.code, response.get_header("Content-Type").map(String::from), response.get_header("Location").map(String::from), body);
+            Ok(())
+        }).unwrap();
+        result
+    }
+
+    #[test]
+    fn serves_index_files_and_redirects_directories() {
+        let base = TempDir::new("index");
+        std::fs::create_dir_all(base.join("docs")).unwrap();
+        std::fs::create_dir_all(base.join("empty")).unwrap();
+        std::fs::write(base.join("docs/index.html"), "<h1>Docs</h1>").unwrap();
+        std::fs::write(base.join("docs/default.htm"), "Default").unwrap();
+
+        let mut handler = FileHandler::new(base.to_path_buf());
+        let redirect = respond(&mut handler, Request::get("/docs?page=2"));
+        let index = respond(&mut handler, Request::get("/docs/"));
+        let empty = respond(&mut handler, Request::get("/empty/"));
+        let mut handler = FileHandler::new(base.to_path_buf()).index(&["missing.html", "default.htm"]);
+        let configured = respond(&mut handler, Request::get("/docs/"));
+        let mut mounted = Request::get("/");
+        mounted.base = "/static";
+        let mounted = respond(&mut handler, mounted);
+
+        assert_eq!((redirect.0, redirect.2), (301, Some("/docs/?page=2".to_string())));
+        assert_eq!((index.0, index.3), (200, "<h1>Docs</h1>".to_string()));
+        assert_eq!(empty.0, 404);
+        assert_eq!(configured.3, "Default");
+        assert_eq!(mounted.2, Some("/static/".to_string()));
+    }
+
+    #[test]
+    fn redirects_directories_to_the_normalized_path() {
+        let base = TempDir::new("redirect");
+        std::fs::create_dir_all(base.join("a b")).unwrap();
+
+        let mut handler = FileHandler::new(base.to_path_buf());
+        let escaped = respond(&mut handler, Request::get("http://host//evil.com/.."));
+        let encoded = respond(&mut handler, Request::get("/x/../a%20b"));
+
+        assert_eq!((escaped.0, escaped.2), (301, Some("/".to_string())));
+        assert_eq!((encoded.0, encoded.2), (301, Some("/a%20b/".to_string())));
+    }
+
+    #[test]
+    fn lists_directories_when_enabled() {
+        let base = TempDir::new("listing-handler");
+        std::fs::create_dir_all(base.join("files")).unwrap();
+        std::fs::write(base.join("files/<b>.txt"), "Hello").unwrap();
+        std::fs::write(base.join("files/.secret"), "").unwrap();
+
+        let mut handler = FileHandler::new(base.to_path_buf()).listing(true);
+        let html = respond(&mut handler, Request::get("/files/"));
+        let json = respond(&mut handler, Request::get("/files/").header("Accept", "application/json"));
+        let image = respond(&mut handler, Request::get("/files/").header("Accept", "image/png"));
+        let mut handler = FileHandler::new(base.to_path_buf()).listing(true).hidden(true);
+        let hidden = respond(&mut handler, Request::get("/files/").header("Accept", "application/json"));
+
+        assert_eq!((html.0, html.1), (200, Some("text/html; charset=utf-8".to_string())));
+        assert!(html.3.contains("<title>Index of /files/</title>"));
+        assert!(html.3.contains("<a href=\"./%3Cb%3E.txt\">&lt;b&gt;.txt</a>"));
+        assert!(!html.3.contains("secret"));
+        assert_eq!(json.1, Some("application/json".to_string()));
+        assert!(json.3.starts_with("[{\"name\":\"<b>.txt\",\"type\":\"file\",\"size\":5,"));
+        assert_eq!(image.0, 406);
+        assert!(hidden.3.contains(".secret"));
+    }
+
+    #[test]
+    fn decodes_and_normalizes_paths() {
+        assert_eq!(decode_path("/a/./b//c%20d/../e").unwrap(), vec!("a", "b", "e"));
+        assert_eq!(decode_path("/caf%C3%A9/%2e%2e/x").unwrap(), vec!("x"));
+        assert_eq!(decode_path("").unwrap(), Vec::<String>::new());
+        for (path, offset) in [("/a%2Fb", 2), ("/a/b%5c", 4), ("/a%00", 2), ("/%FF", 1), ("/a\\b", 2)] {
+            let error = decode_path(path).unwrap_err();
+            assert!(matches!(Error::of(&error), Some(Error::Parse { offset: o, .. }) if *o == offset), "{} {:?}", path, error);
+        }
+        for path in ["/..", "/a/../../b", "/%2e%2E/etc/passwd"] {
+            assert_eq!(decode_path(path).unwrap_err().kind(), io::ErrorKind::PermissionDenied, "{}", path);
+        }
+    }
+
+    #[test]
+    fn returns_the_right_status_for_each_failure() {
+        let base = TempDir::new("statuses");
+        std::fs::create_dir_all(base.join("dir")).unwrap();
+        std::fs::write(base.join("dir/file.txt"), "Hello").unwrap();
+        std::fs::write(base.join("caf\u{e9}.txt"), "Caf\u{e9}").unwrap();
+
+        let mut handler = FileHandler::new(base.join("dir/.."));
+        let mut codes = vec!();
+        for path in ["/dir/file.txt", "/caf%C3%A9.txt", "/dir/../dir/file.txt", "/missing", "/dir/file.txt/more",
+                     "/../statuses", "/dir%2Ffile.txt", "/dir/file.txt%00"] {
+            codes.push(respond(&mut handler, Request::get(path)).0);
+        }
+        let mut missing = FileHandler::new(base.join("missing"));
+        codes.push(respond(&mut missing, Request::get("/dir/file.txt")).0);
+        assert_eq!(codes, vec!(200, 200, 200, 404, 404, 403, 400, 400, 500));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn applies_the_symlink_policy() {
+        use std::os::unix::fs::symlink;
+        let outside = TempDir::new("outside");
+        std::fs::write(outside.join("secret.txt"), "Secret").unwrap();
+        let base = TempDir::new("symlinks");
+        std::fs::write(base.join("file.txt"), "Hello").unwrap();
+        symlink(base.join("file.txt"), base.join("inside.txt")).unwrap();
+        symlink(outside.join("secret.txt"), base.join("escape.txt")).unwrap();
+        symlink(&outside, base.join("escape")).unwrap();
+
+        let mut codes = vec!();
+        for symlinks in [Symlinks::WithinBase, Symlinks::Follow, Symlinks::Deny] {
+            let mut handler = FileHandler::new(base.to_path_buf()).symlinks(symlinks);
+            for path in ["/file.txt", "/inside.txt", "/escape.txt", "/escape/secret.txt"] {
+                codes.push(respond(&mut handler, Request::get(path)).0);
+            }
+        }
+        assert_eq!(codes, vec!(200, 200, 403, 403,
+                               200, 200, 200, 200,
+                               200, 403, 403, 403));
+    }
+
+    #[test]
+    fn sniffing_does_not_consume_the_file() {
+        let base = TempDir::new("sniff");
+        std::fs::write(base.join("page"), "<html>Hello</html>").unwrap();
+        let mut content = String::new();
+        FileHandler::new(base.to_path_buf()).handle(&mut Request::get("/page"), |response| {
+            content = body(response)?;
+            Ok(())
+        }).unwrap();
+        assert_eq!(content, "<html>Hello</html>");
+    }
+}
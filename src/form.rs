@@ -0,0 +1,186 @@
+use std::borrow::Cow;
+use crate::ast::Parameters;
+
+pub fn hex(chr: u8) -> Option<u8> {
+    match chr {
+        b'0'..=b'9' => Some(chr - b'0'),
+        b'a'..=b'f' => Some(chr - b'a' + 10),
+        b'A'..=b'F' => Some(chr - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn decode(value: &str, plus_as_space: bool) -> Cow<'_, str> {
+    match decode_bytes(value, plus_as_space) {
+        Cow::Borrowed(_) => Cow::from(value),
+        Cow::Owned(decoded) => match String::from_utf8(decoded) {
+            Ok(decoded) => Cow::from(decoded),
+            Err(error) => Cow::from(String::from_utf8_lossy(error.as_bytes()).into_owned()),
+        },
+    }
+}
+
+fn decode_bytes(value: &str, plus_as_space: bool) -> Cow<'_, [u8]> {
+    if !value.bytes().any(|chr| chr == b'%' || (plus_as_space && chr == b'+')) {
+        return Cow::from(value.as_bytes());
+    }
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'%' => {
+                match (bytes.get(index + 1).cloned().and_then(hex), bytes.get(index + 2).cloned().and_then(hex)) {
+                    (Some(high), Some(low)) => {
+                        decoded.push(high << 4 | low);
+                        index += 3;
+                        continue;
+                    }
+                    _ => decoded.push(b'%'),
+                }
+            }
+            b'+' if plus_as_space => decoded.push(b' '),
+            chr => decoded.push(chr),
+        }
+        index += 1;
+    }
+    Cow::from(decoded)
+}
+
+/// Decodes `%XX` escapes, invalid escapes are left as is and invalid UTF-8 is replaced
+pub fn percent_decode(value: &str) -> Cow<'_, str> {
+    decode(value, false)
+}
+
+/// Decodes `%XX` escapes without assuming the result is UTF-8
+pub fn percent_decode_bytes(value: &str) -> Cow<'_, [u8]> {
+    decode_bytes(value, false)
+}
+
+/// Like `percent_decode` but `+` is a space as in `application/x-www-form-urlencoded`
+pub fn form_decode(value: &str) -> Cow<'_, str> {
+    decode(value, true)
+}
+
+// unreserved = ALPHA / DIGIT / "-" / "." / "_" / "~"
+pub fn is_unreserved(chr: u8) -> bool {
+    chr.is_ascii_alphanumeric() || chr == b'-' || chr == b'.' || chr == b'_' || chr == b'~'
+}
+
+fn encode(value: &str, plus_for_space: bool) -> Cow<'_, str> {
+    if value.bytes().all(is_unreserved) {
+        return Cow::from(value);
+    }
+    let mut encoded = String::with_capacity(value.len() * 3);
+    for chr in value.bytes() {
+        match chr {
+            b' ' if plus_for_space => encoded.push('+'),
+            chr if is_unreserved(chr) => encoded.push(chr as char),
+            chr => encoded.push_str(&format!("%{:02X}", chr)),
+        }
+    }
+    Cow::from(encoded)
+}
+
+/// Escapes everything apart from the RFC 3986 unreserved characters
+pub fn percent_encode(value: &str) -> Cow<'_, str> {
+    encode(value, false)
+}
+
+pub fn form_encode(value: &str) -> Cow<'_, str> {
+    encode(value, true)
+}
+
+fn parse(input: &str, plus_as_space: bool) -> Parameters<'_> {
+    let mut parameters = Parameters::new();
+    for pair in input.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = match pair.find('=') {
+            Some(index) => (&pair[..index], &pair[index + 1..]),
+            None => (pair, ""),
+        };
+        parameters.add(decode(name, plus_as_space), decode(value, plus_as_space));
+    }
+    parameters
+}
+
+/// Splits a query on `&` and `=` keeping repeated names in order
+pub fn parse_query(query: &str) -> Parameters<'_> {
+    parse(query, false)
+}
+
+/// Parses an `application/x-www-form-urlencoded` body
+pub fn parse_form(body: &str) -> Parameters<'_> {
+    parse(body, true)
+}
+
+fn serialize(parameters: &Parameters, plus_for_space: bool) -> String {
+    parameters.0.iter().
+        map(|(name, value)| format!("{}={}", encode(name, plus_for_space), encode(value, plus_for_space))).
+        collect::<Vec<_>>().
+        join("&")
+}
+
+pub fn serialize_query(parameters: &Parameters) -> String {
+    serialize(parameters, false)
+}
+
+pub fn serialize_form(parameters: &Parameters) -> String {
+    serialize(parameters, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn borrowed(value: Cow<'_, str>) -> bool {
+        matches!(value, Cow::Borrowed(_))
+    }
+
+    #[test]
+    fn percent_decodes() {
+        assert_eq!(percent_decode("caf%C3%A9%20au%2bLait"), "café au+Lait");
+        assert_eq!(percent_decode("a+b"), "a+b");
+        assert_eq!(form_decode("a+b%2B"), "a b+");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode("%FF"), "\u{FFFD}");
+        assert_eq!(percent_decode_bytes("%FF%00a"), &b"\xff\0a"[..]);
+    }
+
+    #[test]
+    fn borrows_when_nothing_to_decode() {
+        assert!(borrowed(percent_decode("plain")));
+        assert!(borrowed(percent_decode("a+b")));
+        assert!(!borrowed(form_decode("a+b")));
+        assert!(borrowed(percent_encode("plain-text_1.0~")));
+    }
+
+    #[test]
+    fn parses_query_keeping_repeated_names_in_order() {
+        let parameters = parse_query("tag=a&name=Dan%20Bodart&tag=b&empty=&flag&&q=1+1");
+        assert_eq!(parameters.get("name"), Some("Dan Bodart"));
+        assert_eq!(parameters.values("tag"), vec!("a", "b"));
+        assert_eq!(parameters.get("empty"), Some(""));
+        assert_eq!(parameters.get("flag"), Some(""));
+        assert_eq!(parameters.get("q"), Some("1+1"));
+        assert_eq!(parameters.len(), 6);
+        assert!(parameters.0.iter().take(1).all(|(name, value)| borrowed(name.clone()) && borrowed(value.clone())));
+    }
+
+    #[test]
+    fn parses_form() {
+        let parameters = parse_form("q=hello+world&x%5B%5D=1%262");
+        assert_eq!(parameters.get("q"), Some("hello world"));
+        assert_eq!(parameters.get("x[]"), Some("1&2"));
+    }
+
+    #[test]
+    fn serializes_and_round_trips() {
+        let mut parameters = Parameters::new();
+        parameters.add("q", "hello world").add("a&b", "1=1").add("q", "ü");
+        assert_eq!(serialize_query(&parameters), "q=hello%20world&a%26b=1%3D1&q=%C3%BC");
+        assert_eq!(serialize_form(&parameters), "q=hello+world&a%26b=1%3D1&q=%C3%BC");
+        assert_eq!(parse_query(&serialize_query(&parameters)), parameters);
+        assert_eq!(parse_form(&serialize_form(&parameters)), parameters);
+    }
+}
@@ -0,0 +1,427 @@
+use std::io::{self, Read, Result};
+use std::cmp::min;
+use std::{error, fmt};
+use crate::ast::{Headers, TransferCoding};
+use crate::error::{Error, Limit};
+use crate::parser::parse_all;
+use crate::method::Method;
+
+/// How forgiving message parsing is, `Server` is strict and `Client` lenient by default
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum ParseMode {
+    #[default]
+    Strict,
+    Lenient,
+}
+
+/// Ways a message can be framed ambiguously, most of which enable request smuggling
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Violation {
+    ObsFold,
+    WhitespaceBeforeColon,
+    InvalidContentLength,
+    DuplicateContentLength,
+    ConflictingContentLength,
+    ContentLengthWithTransferEncoding,
+    InvalidTransferEncoding,
+    ChunkedNotFinal,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
+        format.write_str(match *self {
+            Violation::ObsFold => "Obsolete line folding is not allowed (RFC 7230 section 3.2.4)",
+            Violation::WhitespaceBeforeColon => "Whitespace between header field name and colon (RFC 7230 section 3.2.4)",
+            Violation::InvalidContentLength => "Invalid Content-Length (RFC 7230 section 3.3.2)",
+            Violation::DuplicateContentLength => "Duplicate Content-Length (RFC 7230 section 3.3.2)",
+            Violation::ConflictingContentLength => "Conflicting Content-Length values (RFC 7230 section 3.3.3)",
+            Violation::ContentLengthWithTransferEncoding => "Both Content-Length and Transfer-Encoding are present (RFC 7230 section 3.3.3)",
+            Violation::InvalidTransferEncoding => "Invalid Transfer-Encoding (RFC 7230 section 3.3.1)",
+            Violation::ChunkedNotFinal => "Transfer-Encoding does not end with chunked (RFC 7230 section 3.3.3)",
+        })
+    }
+}
+
+impl error::Error for Violation {}
+
+impl From<Violation> for io::Error {
+    fn from(violation: Violation) -> io::Error {
+        Error::Framing(violation).into()
+    }
+}
+
+/// The violation behind an error returned while reading a message
+pub fn violation(error: &io::Error) -> Option<Violation> {
+    match Error::of(error) {
+        Some(Error::Framing(violation)) => Some(*violation),
+        _ => None,
+    }
+}
+
+/// Checks the raw header lines for things the grammar tolerates, `head` ends at the empty line
+pub fn validate_head(head: &[u8], mode: ParseMode) -> std::result::Result<(), Violation> {
+    if mode == ParseMode::Lenient {
+        return Ok(());
+    }
+    for line in head.split(|chr| *chr == b'\n').skip(1) {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        match line.first() {
+            None => break,
+            Some(b' ') | Some(b'\t') => return Err(Violation::ObsFold),
+            _ => {}
+        }
+        if let Some(colon) = line.iter().position(|chr| *chr == b':') {
+            if line[..colon].ends_with(b" ") || line[..colon].ends_with(b"\t") {
+                return Err(Violation::WhitespaceBeforeColon);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Content-Length and Transfer-Encoding rules of RFC 7230 section 3.3.3
+pub fn validate_headers(headers: &Headers, mode: ParseMode, request: bool) -> std::result::Result<(), Violation> {
+    let lengths = headers.headers("Content-Length").into_iter().
+        flat_map(|value| value.split(',')).
+        map(str::trim).
+        collect::<Vec<_>>();
+    if lengths.iter().any(|length| length.is_empty() || !length.bytes().all(|chr| chr.is_ascii_digit()) || length.parse::<u64>().is_err()) {
+        return Err(Violation::InvalidContentLength);
+    }
+    if lengths.len() > 1 {
+        if lengths.iter().any(|length| length.parse::<u64>() != lengths[0].parse::<u64>()) {
+            return Err(Violation::ConflictingContentLength);
+        }
+        if mode == ParseMode::Strict {
+            return Err(Violation::DuplicateContentLength);
+        }
+    }
+
+    if headers.headers("Transfer-Encoding").is_empty() {
+        return Ok(());
+    }
+    if !lengths.is_empty() && mode == ParseMode::Strict {
+        return Err(Violation::ContentLengthWithTransferEncoding);
+    }
+    let codings = headers.transfer_encoding();
+    if codings.is_empty() {
+        return Err(Violation::InvalidTransferEncoding);
+    }
+    let chunked = codings.iter().filter(|coding| **coding == TransferCoding::Chunked).count();
+    let final_chunked = codings.last() == Some(&TransferCoding::Chunked) && chunked == 1;
+    if !final_chunked && (request || mode == ParseMode::Strict) {
+        return Err(Violation::ChunkedNotFinal);
+    }
+    Ok(())
+}
+
+/// Once a chunked body has been decoded it must no longer claim to be chunked or carry a stale length
+pub fn remove_chunked(headers: &mut Headers) {
+    let codings = headers.headers("Transfer-Encoding").join(",");
+    let mut codings = codings.split(',').map(str::trim).filter(|coding| !coding.is_empty()).collect::<Vec<_>>();
+    codings.pop();
+    headers.remove("Content-Length");
+    if codings.is_empty() {
+        headers.remove("Transfer-Encoding");
+    } else {
+        headers.replace("Transfer-Encoding", codings.join(", "));
+    }
+}
+
+/// How the end of a message body is found
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Framing {
+    None,
+    Length(u64),
+    Chunked,
+    UntilClose,
+}
+
+impl Framing {
+    /// Transfer-Encoding takes precedence over Content-Length
+    pub fn of(headers: &Headers) -> Framing {
+        if !headers.headers("Transfer-Encoding").is_empty() {
+            return match headers.transfer_encoding().last() {
+                Some(TransferCoding::Chunked) => Framing::Chunked,
+                _ => Framing::UntilClose,
+            };
+        }
+        match headers.content_length() {
+            Some(length) if length > 0 => Framing::Length(length),
+            _ => Framing::None,
+        }
+    }
+
+    /// RFC 7230 section 3.3.3, `method` is that of the request being answered when it is known.
+    /// Responses to `HEAD`, successful `CONNECT` and 1xx, 204 and 304 never have a body and
+    /// one without a length is read until the connection closes.
+    pub fn response(method: Option<Method>, code: u16, headers: &Headers) -> Framing {
+        let connected = method == Some(Method::Connect) && (200..300).contains(&code);
+        if method == Some(Method::Head) || connected || (100..200).contains(&code) || code == 204 || code == 304 {
+            return Framing::None;
+        }
+        match Framing::of(headers) {
+            Framing::None if headers.content_length().is_none() => Framing::UntilClose,
+            framing => framing,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+enum ChunkedState {
+    Size,
+    Data(u64),
+    Trailers,
+    Done,
+}
+
+/// Decodes a chunked body without reading past its end so the next message is left intact
+pub struct ChunkedReader<R> where R: Read {
+    read: R,
+    state: ChunkedState,
+}
+
+impl<R> ChunkedReader<R> where R: Read {
+    pub fn new(read: R) -> ChunkedReader<R> {
+        ChunkedReader { read, state: ChunkedState::Size }
+    }
+
+    fn read_line(&mut self) -> Result<Vec<u8>> {
+        let mut line = vec!();
+        let mut byte = [0];
+        while !line.ends_with(b"\n") {
+            if line.len() > 4096 {
+                return Err(Error::LimitExceeded(Limit::ChunkLine).into());
+            }
+            if self.read.read(&mut byte)? == 0 {
+                return Err(Error::Incomplete.into());
+            }
+            line.push(byte[0]);
+        }
+        Ok(line)
+    }
+}
+
+impl<R> Read for ChunkedReader<R> where R: Read {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        use crate::grammar::chunk_head;
+
+        loop {
+            match self.state {
+                ChunkedState::Size => {
+                    let line = self.read_line()?;
+                    self.state = match parse_all("chunk_head", &line, chunk_head(&line))? {
+                        (0, _) => ChunkedState::Trailers,
+                        (size, _) => ChunkedState::Data(size),
+                    };
+                }
+                ChunkedState::Data(remaining) => {
+                    if buf.is_empty() {
+                        return Ok(0);
+                    }
+                    let size = min(remaining, buf.len() as u64) as usize;
+                    let read = self.read.read(&mut buf[..size])?;
+                    if read == 0 {
+                        return Err(Error::Incomplete.into());
+                    }
+                    let remaining = remaining - read as u64;
+                    self.state = ChunkedState::Data(remaining);
+                    if remaining == 0 {
+                        let mut crlf = [0; 2];
+                        self.read.read_exact(&mut crlf)?;
+                        if &crlf != b"\r\n" {
+                            return Err(Error::protocol("Chunk data is not followed by CRLF").into());
+                        }
+                        self.state = ChunkedState::Size;
+                    }
+                    return Ok(read);
+                }
+                ChunkedState::Trailers => {
+                    if self.read_line()? == b"\r\n" {
+                        self.state = ChunkedState::Done;
+                    }
+                }
+                ChunkedState::Done => return Ok(0),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{Message, Request, WriteTo};
+    use crate::ast::MessageBody;
+
+    fn read(mode: ParseMode, payload: &[u8]) -> std::result::Result<(String, usize), Option<Violation>> {
+        let mut reader = &b""[..];
+        let result = match Message::read_mode(payload, &mut reader, mode) {
+            Ok((Message::Request(request), count)) => Ok((request.entity.to_string(), count)),
+            Ok((Message::Response(response), count)) => Ok((response.entity.to_string(), count)),
+            Err(e) => Err(violation(&e)),
+        };
+        result
+    }
+
+    fn strict(payload: &[u8]) -> std::result::Result<(String, usize), Option<Violation>> {
+        read(ParseMode::Strict, payload)
+    }
+
+    fn lenient(payload: &[u8]) -> std::result::Result<(String, usize), Option<Violation>> {
+        read(ParseMode::Lenient, payload)
+    }
+
+    const CL_TE: &[u8] = b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 13\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\nSMUGGLED";
+    const TE_CL: &[u8] = b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n8\r\nSMUGGLED\r\n0\r\n\r\n";
+
+    #[test]
+    fn rejects_content_length_with_transfer_encoding() {
+        assert_eq!(strict(CL_TE), Err(Some(Violation::ContentLengthWithTransferEncoding)));
+        assert_eq!(strict(TE_CL), Err(Some(Violation::ContentLengthWithTransferEncoding)));
+    }
+
+    #[test]
+    fn lenient_mode_lets_transfer_encoding_win() {
+        assert_eq!(lenient(CL_TE), Ok((String::new(), CL_TE.len() - 8)));
+        assert_eq!(lenient(TE_CL), Ok(("SMUGGLED".to_string(), TE_CL.len())));
+    }
+
+    #[test]
+    fn rejects_obfuscated_transfer_encoding() {
+        for payload in [
+            &b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: x\r\n\r\n0\r\n\r\n"[..],
+            &b"POST / HTTP/1.1\r\nTransfer-Encoding: xchunked\r\n\r\n0\r\n\r\n"[..],
+            &b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked, identity\r\n\r\n0\r\n\r\n"[..],
+            &b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked, chunked\r\n\r\n0\r\n\r\n"[..]] {
+            assert_eq!(strict(payload), Err(Some(Violation::ChunkedNotFinal)));
+            assert_eq!(lenient(payload), Err(Some(Violation::ChunkedNotFinal)));
+        }
+        assert_eq!(strict(b"POST / HTTP/1.1\r\nTransfer-Encoding: \r\n\r\n"), Err(Some(Violation::InvalidTransferEncoding)));
+    }
+
+    #[test]
+    fn rejects_whitespace_before_colon() {
+        let payload = b"POST / HTTP/1.1\r\nTransfer-Encoding : chunked\r\n\r\n0\r\n\r\n";
+        assert_eq!(strict(payload), Err(Some(Violation::WhitespaceBeforeColon)));
+        assert_eq!(strict(b"POST / HTTP/1.1\r\nContent-Length\t: 0\r\n\r\n"), Err(Some(Violation::WhitespaceBeforeColon)));
+        assert_eq!(lenient(payload), Ok((String::new(), payload.len())));
+    }
+
+    #[test]
+    fn rejects_obs_fold() {
+        let payload = b"POST / HTTP/1.1\r\nX-Padding: a\r\n\tb\r\nContent-Length: 0\r\n\r\n";
+        assert_eq!(strict(payload), Err(Some(Violation::ObsFold)));
+        assert_eq!(strict(b"POST / HTTP/1.1\r\nTransfer-Encoding:\r\n chunked\r\n\r\n0\r\n\r\n"), Err(Some(Violation::ObsFold)));
+        assert!(lenient(payload).is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_content_length() {
+        let duplicate = b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\nhello";
+        assert_eq!(strict(duplicate), Err(Some(Violation::DuplicateContentLength)));
+        assert_eq!(lenient(duplicate), Ok(("hello".to_string(), duplicate.len())));
+        assert_eq!(lenient(b"POST / HTTP/1.1\r\nContent-Length: 5, 5\r\n\r\nhello"), Ok(("hello".to_string(), 46)));
+
+        for payload in [
+            &b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\nhello!"[..],
+            &b"POST / HTTP/1.1\r\nContent-Length: 5, 6\r\n\r\nhello!"[..]] {
+            assert_eq!(strict(payload), Err(Some(Violation::ConflictingContentLength)));
+            assert_eq!(lenient(payload), Err(Some(Violation::ConflictingContentLength)));
+        }
+        for value in ["+5", "-1", "0x5", "5 5", "", "99999999999999999999"] {
+            let payload = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\nhello", value);
+            assert_eq!(lenient(payload.as_bytes()), Err(Some(Violation::InvalidContentLength)), "Content-Length: {}", value);
+        }
+    }
+
+    #[test]
+    fn accepts_well_formed_messages() {
+        let chunked = b"POST / HTTP/1.1\r\nTransfer-Encoding:\tchunked\r\n\r\n4\r\nWiki\r\n5;ext=1\r\npedia\r\n0\r\nTrailer: x\r\n\r\nGET /next HTTP/1.1\r\n\r\n";
+        assert_eq!(strict(chunked), Ok(("Wikipedia".to_string(), chunked.len() - 22)));
+        assert_eq!(strict(b"POST / HTTP/1.1\r\nContent-Length: 005\r\n\r\nhello"), Ok(("hello".to_string(), 45)));
+        assert_eq!(strict(b"POST / HTTP/1.1\r\nTransfer-Encoding: Chunked\r\n\r\n0\r\n\r\n"), Ok((String::new(), 52)));
+        assert_eq!(strict(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, chunked\r\n\r\n0\r\n\r\n"), Ok((String::new(), 58)));
+    }
+
+    #[test]
+    fn responses_can_be_read_until_close_when_lenient() {
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip\r\n\r\ncompressed";
+        assert_eq!(strict(response), Err(Some(Violation::ChunkedNotFinal)));
+        let mut reader = &b" and more"[..];
+        let (message, _) = Message::read_mode(response, &mut reader, ParseMode::Lenient).unwrap();
+        if let Message::Response(mut response) = message {
+            let mut body = String::new();
+            if let MessageBody::Reader(ref mut read) = response.entity {
+                read.read_to_string(&mut body).unwrap();
+            }
+            assert_eq!(body, "compressed and more");
+        } else {
+            panic!("Should be a response");
+        }
+    }
+
+    fn response(method: Option<Method>, payload: &[u8], rest: &[u8]) -> (String, usize) {
+        let mut reader = rest;
+        let mut body = Vec::new();
+        let count = match Message::read_for(payload, &mut reader, ParseMode::Strict, method).unwrap() {
+            (Message::Response(mut response), count) => {
+                response.entity.write_to(&mut body).unwrap();
+                count
+            }
+            _ => panic!("Should be a response"),
+        };
+        (String::from_utf8(body).unwrap(), count)
+    }
+
+    #[test]
+    fn responses_without_a_body_ignore_their_length() {
+        let head = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n";
+        assert_eq!(response(Some(Method::Head), head, b"HTTP/1.1 204 No Content\r\n\r\n"), (String::new(), head.len()));
+        assert_eq!(response(Some(Method::Get), head, b"Hello"), ("Hello".to_string(), head.len()));
+        for status in [&b"HTTP/1.1 204 No Content\r\nContent-Length: 5\r\n\r\n"[..], b"HTTP/1.1 304 Not Modified\r\nContent-Length: 5\r\n\r\n", b"HTTP/1.1 100 Continue\r\n\r\n"] {
+            assert_eq!(response(Some(Method::Get), status, b"HTTP/1.1 200 OK\r\n"), (String::new(), status.len()));
+        }
+        let established = b"HTTP/1.1 200 Connection established\r\n\r\n";
+        assert_eq!(response(Some(Method::Connect), established, b"tunnelled"), (String::new(), established.len()));
+    }
+
+    #[test]
+    fn responses_without_a_length_are_read_until_close() {
+        let head = b"HTTP/1.1 200 OK\r\n\r\nHello";
+        assert_eq!(response(Some(Method::Get), head, b" World"), ("Hello World".to_string(), head.len()));
+        assert_eq!(response(None, b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n", b"next"), (String::new(), 38));
+        assert_eq!(strict(b"POST / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n"), Ok((String::new(), 19)));
+    }
+
+    #[test]
+    fn decodes_chunked_bodies_that_do_not_fit_in_the_buffer() {
+        let head = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nWi";
+        let mut rest = &b"ki\r\n5\r\npedia\r\n0\r\n\r\nGET /next HTTP/1.1\r\n\r\n"[..];
+        {
+            let (message, count) = Message::read_mode(head, &mut rest, ParseMode::Strict).unwrap();
+            assert_eq!(count, head.len());
+            if let Message::Request(Request { mut entity, headers, .. }) = message {
+                assert_eq!(headers.get("Transfer-Encoding"), None);
+                let mut body = String::new();
+                if let MessageBody::Reader(ref mut read) = entity {
+                    read.read_to_string(&mut body).unwrap();
+                }
+                assert_eq!(body, "Wikipedia");
+            }
+        }
+        assert_eq!(rest, &b"GET /next HTTP/1.1\r\n\r\n"[..]);
+    }
+
+    #[test]
+    fn chunked_reader_rejects_malformed_chunks() {
+        let mut body = String::new();
+        let error = ChunkedReader::new(&b"5z\r\n"[..]).read_to_string(&mut body).unwrap_err();
+        assert!(matches!(Error::of(&error), Some(Error::Parse { ref rule, offset: 1 }) if rule == "chunk_head"), "{:?}", error);
+        let error = ChunkedReader::new(&b"4\r\nWikiX\r\n"[..]).read_to_string(&mut body).unwrap_err();
+        assert!(matches!(Error::of(&error), Some(Error::Protocol(_))), "{:?}", error);
+        let error = ChunkedReader::new(&b"4\r\nWi"[..]).read_to_string(&mut body).unwrap_err();
+        assert!(matches!(Error::of(&error), Some(Error::Incomplete)), "{:?}", error);
+        let error = ChunkedReader::new(&[b'1'; 5000][..]).read_to_string(&mut body).unwrap_err();
+        assert!(matches!(Error::of(&error), Some(Error::LimitExceeded(Limit::ChunkLine))), "{:?}", error);
+    }
+}
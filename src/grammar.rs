@@ -2,15 +2,23 @@ extern crate nom;
 
 use std::borrow::Cow;
 use std::str;
+use std::time::{Duration, SystemTime};
+use std::net::{Ipv4Addr, Ipv6Addr};
 
-use nom::bytes::complete::{tag, take};
-use nom::character::{complete, is_alphabetic, is_digit, is_hex_digit};
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take, take_while, take_while1, take_while_m_n};
+use nom::combinator::{map, map_res, opt, recognize, value, verify};
+use nom::character::{complete, is_alphabetic, is_alphanumeric, is_digit, is_hex_digit};
 use nom::error::ErrorKind;
 use nom::IResult;
-use nom::multi::{many0, separated_nonempty_list};
-use nom::sequence::delimited;
+use nom::multi::{many0, many1, separated_nonempty_list};
+use nom::sequence::{delimited, preceded, terminated, tuple};
 
 use crate::ast::*;
+use crate::date::{full_year, timestamp};
+use crate::form::is_unreserved;
+use crate::uri::{Authority, Host};
+use crate::header::{MediaType, Quality, EntityTag, EntityTags, Protocol, RetryAfter, ByteRange, IfRange};
 use crate::misc::*;
 use crate::predicates::*;
 
@@ -24,6 +32,8 @@ pub use token as method;
 pub use token as field_name;
 // chunk-ext-name = token
 pub use token as chunk_ext_name;
+// delay-seconds = 1*DIGIT
+pub use number as delay_seconds;
 
 // HTTP-name     = %x48.54.54.50 ; "HTTP", case-sensitive
 pub fn http_name(i: &[u8]) -> IResult<&[u8], &[u8], (&[u8], ErrorKind)> {
@@ -74,8 +84,11 @@ named!(pub quoted_pair, preceded!(char!('\\'), alt!(htab | space | vchar | obs_t
 // quoted-string  = DQUOTE *( qdtext / quoted-pair ) DQUOTE
 named!(pub quoted_string <Cow<str>>, delimited!(double_quote, map_res!(many0!(complete!(alt!(quoted_text | quoted_pair))), to_cow_str), double_quote));
 
-// TODO: full impl
-named!(pub request_target <&str>, map_res!(is_not!(" "), str::from_utf8));
+// request-target = origin-form / absolute-form / authority-form / asterisk-form
+// Which form is allowed depends on the method, see `request_line`
+pub fn request_target(i: &[u8]) -> IResult<&[u8], &str> {
+    map_res(alt((origin_form, absolute_form, asterisk_form, authority_form)), str::from_utf8)(i)
+}
 
 
 // tchar = "!" / "#" / "$" / "%" / "&" / "'" / "*" / "+" / "-" / "." / "^" / "_" / "`" / "|" / "~" / DIGIT / ALPHA
@@ -85,10 +98,18 @@ named!(pub tchar, char_predicate!(or!(among("!#$%&'*+-.^_`|~"), is_digit, is_alp
 named!(pub token <&str>, map_res!(map_res!(many1!(complete!(tchar)), join_vec), str::from_utf8));
 
 //request-line   = method SP request-target SP HTTP-version CRLF
-named!(pub request_line <RequestLine>, do_parse!(
-    method: method >> space >> request_target: request_target >> space >> version: http_version >> crlf >>
-    (RequestLine { method: method, request_target: request_target, version: version })
-  ));
+pub fn request_line(i: &[u8]) -> IResult<&[u8], RequestLine<'_>> {
+    let (i, method) = method(i)?;
+    let (i, _) = space(i)?;
+    let (remainder, request_target) = request_target(i)?;
+    if TargetForm::parse(method, request_target).is_none() {
+        return Err(nom::Err::Error((i, ErrorKind::Verify)));
+    }
+    let (i, _) = space(remainder)?;
+    let (i, version) = http_version(i)?;
+    let (i, _) = crlf(i)?;
+    Ok((i, RequestLine { method, request_target, version }))
+}
 
 //status-code    = 3DIGIT
 named!(pub status_code <u16>, map_res!(map_res!(map_res!(many_m_n!(3,3, complete!(digit)), join_vec), str::from_utf8), parse_u16));
@@ -99,7 +120,7 @@ named!(pub reason_phrase <&str>, map_res!(map_res!(many0!(complete!(alt!(htab |
 // status-line = HTTP-version SP status-code SP reason-phrase CRLF
 named!(pub status_line <StatusLine>, do_parse!(
     version: http_version >> space >> status: status_code >> space >> reason_phrase:reason_phrase >> crlf >>
-    (StatusLine { version: version, code: status, description: reason_phrase })
+    (StatusLine { version, code: status, description: reason_phrase })
   ));
 
 
@@ -112,13 +133,11 @@ named!(pub field_vchar, alt!(vchar | obs_text));
 named!(pub spaces, map_res!(many1!(complete!(alt!(space | htab))), join_vec));
 
 // field-content  = field-vchar [ 1*( SP / HTAB ) field-vchar ]
+// repeated as per erratum 4189 otherwise a one character word like "a b c" ends the value
 named!(pub field_content, do_parse!(
     chr:field_vchar >>
-    optional: opt!(complete!(map_res!(pair!( spaces, field_vchar), join_pair))) >>
-    (match optional {
-        Some(other) => join_slice(chr, other).unwrap(),
-        None => chr,
-    })
+    rest: many0!(complete!(map_res!(pair!( spaces, field_vchar), join_pair))) >>
+    (rest.into_iter().fold(chr, |content, other| join_slice(content, other).unwrap()))
   ));
 
 // obs-fold       = CRLF 1*( SP / HTAB ) ; obsolete line folding
@@ -128,8 +147,9 @@ named!(pub obs_fold, do_parse!( crlf >> spaces >> (Default::default()) ));
 named!(pub field_value <Cow<str>>, map_res!(many0!(complete!(alt!(field_content | obs_fold))), to_cow_str));
 
 // header-field   = field-name ":" OWS field-value OWS
+// whitespace before the colon is tolerated here and rejected by strict validation
 named!(pub header_field <Header>, do_parse!(
-    name:field_name >> tag!(":") >> ows >> value:field_value >> ows >>
+    name:field_name >> ows >> tag!(":") >> ows >> value:field_value >> ows >>
     (Header::new(name, value))
   ));
 
@@ -147,7 +167,7 @@ named!(pub headers <Headers>, map!(many0!(complete!(terminated!(header_field, cr
 
 named!(pub message_head <MessageHead> , do_parse!(
     start_line:start_line >> headers:headers >> crlf >>
-    (MessageHead { start_line:start_line, headers:headers})
+    (MessageHead { start_line, headers})
   ));
 
 macro_rules! apply (
@@ -157,7 +177,7 @@ macro_rules! apply (
 // HTTP-message = start-line *( header-field CRLF ) CRLF [ message-body ]
 named!(pub http_message <HttpMessage> , do_parse!(
     head:message_head >> body:apply!(message_body, &head.headers) >>
-    (HttpMessage { start_line:head.start_line, headers:head.headers, body:body})
+    (HttpMessage { start_line:head.start_line, headers:head.headers, body})
   ));
 
 // chunk-size     = 1*HEXDIG
@@ -174,7 +194,7 @@ named!(pub chunk_ext <ChunkExtensions>, map!(many0!(complete!(do_parse!(
 
 // chunk-data     = 1*OCTET ; a sequence of chunk-size octets
 // chunk          = chunk-size [ chunk-ext ] CRLF chunk-data CRLF
-pub fn chunk(i: &[u8]) -> nom::IResult<&[u8], Chunk, (&[u8], nom::error::ErrorKind)> {
+pub fn chunk(i: &[u8]) -> nom::IResult<&[u8], Chunk<'_>, (&[u8], nom::error::ErrorKind)> {
     let (i, size) = chunk_size(i)?;
     if size == 0 { return Err(nom::Err::Error((i, ErrorKind::Complete))); };
     let (i, extensions) = chunk_ext(i)?;
@@ -196,7 +216,7 @@ named!(pub last_chunk <ChunkExtensions>, do_parse!(
 ));
 
 // chunked-body   = *chunk last-chunk trailer-part CRLF
-pub fn chunked_body(i: &[u8]) -> nom::IResult<&[u8], ChunkedBody, (&[u8], nom::error::ErrorKind)> {
+pub fn chunked_body(i: &[u8]) -> nom::IResult<&[u8], ChunkedBody<'_>, (&[u8], nom::error::ErrorKind)> {
     let (i, chunks) = many0(chunk)(i)?;
     let (i, last) = last_chunk(i)?;
     let (i, trailers) = trailer_part(i)?;
@@ -218,24 +238,336 @@ named!(pub transfer_extension <TransferCoding>, do_parse!(
 
 // transfer-coding    = "chunked" / "compress" / "deflate" / "gzip" / transfer-extension
 named!(pub transfer_coding <TransferCoding>, alt!(
-    value!(TransferCoding::Chunked, tag!("chunked")) |
-    value!(TransferCoding::Compress, tag!("compress")) |
-    value!(TransferCoding::Deflate, tag!("deflate")) |
-    value!(TransferCoding::Gzip, tag!("gzip")) |
+    value!(TransferCoding::Chunked, tag_no_case!("chunked")) |
+    value!(TransferCoding::Compress, tag_no_case!("compress")) |
+    value!(TransferCoding::Deflate, tag_no_case!("deflate")) |
+    value!(TransferCoding::Gzip, tag_no_case!("gzip")) |
     transfer_extension
 ));
 
 //  Transfer-Encoding = 1#transfer-coding
 //  #rule: 1#element => element *( OWS "," OWS element )
-pub fn transfer_encoding(i: &[u8]) -> nom::IResult<&[u8], Vec<TransferCoding>, (&[u8], nom::error::ErrorKind)> {
+pub fn transfer_encoding(i: &[u8]) -> nom::IResult<&[u8], Vec<TransferCoding<'_>>, (&[u8], nom::error::ErrorKind)> {
     separated_nonempty_list(delimited(ows, complete::char(','), ows), transfer_coding)(i)
 }
 
+// parameter = token "=" ( token / quoted-string )
+named!(pub parameter <(&str, Cow<str>)>, do_parse!(
+    name:token >> char!('=') >> value:alt!(map!(token, Cow::from) | quoted_string) >>
+    ((name, value))
+));
+
+// *( OWS ";" OWS parameter )
+named!(pub parameters <Vec<(&str, Cow<str>)>>, many0!(complete!(do_parse!(ows >> char!(';') >> ows >> param:parameter >> (param)))));
+
+// sub-delims = "!" / "$" / "&" / "'" / "(" / ")" / "*" / "+" / "," / ";" / "="
+pub fn is_sub_delim(chr: u8) -> bool {
+    b"!$&'()*+,;=".contains(&chr)
+}
+
+// pct-encoded = "%" HEXDIG HEXDIG
+fn pct_encoded(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(tuple((complete::char('%'), take_while_m_n(2, 2, is_hex_digit))))(i)
+}
+
+// reg-name = *( unreserved / pct-encoded / sub-delims )
+pub fn reg_name(i: &[u8]) -> IResult<&[u8], &str> {
+    map_res(recognize(many0(alt((take_while1(|chr| is_unreserved(chr) || is_sub_delim(chr)), pct_encoded)))), str::from_utf8)(i)
+}
+
+// userinfo = *( unreserved / pct-encoded / sub-delims / ":" )
+pub fn user_info(i: &[u8]) -> IResult<&[u8], &str> {
+    map_res(recognize(many0(alt((take_while1(|chr| is_unreserved(chr) || is_sub_delim(chr) || chr == b':'), pct_encoded)))), str::from_utf8)(i)
+}
+
+// IP-literal = "[" IPv6address "]", IPvFuture is not supported
+pub fn ip_literal(i: &[u8]) -> IResult<&[u8], Ipv6Addr> {
+    delimited(complete::char('['),
+              map_res(map_res(take_while1(|chr| is_hex_digit(chr) || chr == b':' || chr == b'.'), str::from_utf8), str::parse),
+              complete::char(']'))(i)
+}
+
+// host = IP-literal / IPv4address / reg-name
+pub fn uri_host(i: &[u8]) -> IResult<&[u8], Host<'_>> {
+    alt((map(ip_literal, Host::Ipv6),
+         map(reg_name, |name| name.parse::<Ipv4Addr>().map(Host::Ipv4).unwrap_or(Host::RegName(name)))))(i)
+}
+
+// port = *DIGIT
+pub fn port(i: &[u8]) -> IResult<&[u8], Option<u16>> {
+    map_res(take_while(is_digit), |digits: &[u8]| match digits {
+        [] => Ok(None),
+        digits => str::from_utf8(digits).map_err(|_| ()).and_then(|digits| digits.parse().map(Some).map_err(|_| ())),
+    })(i)
+}
+
+// authority = [ userinfo "@" ] host [ ":" port ]
+pub fn authority(i: &[u8]) -> IResult<&[u8], Authority<'_>> {
+    map(tuple((opt(terminated(user_info, complete::char('@'))), uri_host, opt(preceded(complete::char(':'), port)))),
+        |(user_info, host, port)| Authority { user_info, host, port: port.flatten() })(i)
+}
+
+// pchar = unreserved / pct-encoded / sub-delims / ":" / "@"
+fn is_pchar(chr: u8) -> bool {
+    is_unreserved(chr) || is_sub_delim(chr) || chr == b':' || chr == b'@'
+}
+
+// scheme = ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )
+pub fn scheme(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(tuple((take_while_m_n(1, 1, is_alphabetic), take_while(|chr| is_alphanumeric(chr) || chr == b'+' || chr == b'-' || chr == b'.'))))(i)
+}
+
+// segment = *pchar
+pub fn segment(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(many0(alt((take_while1(is_pchar), pct_encoded))))(i)
+}
+
+// segment-nz = 1*pchar
+pub fn segment_nz(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(many1(alt((take_while1(is_pchar), pct_encoded))))(i)
+}
+
+// query = *( pchar / "/" / "?" )
+pub fn query(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(many0(alt((take_while1(|chr| is_pchar(chr) || chr == b'/' || chr == b'?'), pct_encoded))))(i)
+}
+
+// absolute-path = 1*( "/" segment )
+pub fn absolute_path(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(many1(preceded(complete::char('/'), segment)))(i)
+}
+
+// path-abempty = *( "/" segment )
+pub fn path_abempty(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(many0(preceded(complete::char('/'), segment)))(i)
+}
+
+// path-rootless = segment-nz *( "/" segment )
+pub fn path_rootless(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(tuple((segment_nz, path_abempty)))(i)
+}
+
+// path-absolute = "/" [ segment-nz *( "/" segment ) ]
+pub fn path_absolute(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(tuple((complete::char('/'), opt(path_rootless))))(i)
+}
+
+// hier-part = "//" authority path-abempty / path-absolute / path-rootless / path-empty
+pub fn hier_part(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(opt(alt((recognize(tuple((tag("//"), authority, path_abempty))), path_absolute, path_rootless))))(i)
+}
+
+// origin-form = absolute-path [ "?" query ]
+pub fn origin_form(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(tuple((absolute_path, opt(preceded(complete::char('?'), query)))))(i)
+}
+
+// absolute-form = absolute-URI = scheme ":" hier-part [ "?" query ]
+pub fn absolute_form(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(tuple((scheme, complete::char(':'), hier_part, opt(preceded(complete::char('?'), query)))))(i)
+}
+
+// authority-form = uri-host ":" port, RFC 7230 section 5.3.3 has no userinfo and a tunnel needs a host and port
+pub fn authority_form(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(verify(tuple((uri_host, complete::char(':'), port)),
+                     |(host, _, port)| port.is_some() && *host != Host::RegName("")))(i)
+}
+
+// asterisk-form = "*"
+pub fn asterisk_form(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    tag("*")(i)
+}
+
+// Host = uri-host [ ":" port ]
+pub fn host(i: &[u8]) -> IResult<&[u8], Authority<'_>> {
+    map(tuple((uri_host, opt(preceded(complete::char(':'), port)))),
+        |(host, port)| Authority { user_info: None, host, port: port.flatten() })(i)
+}
+
+fn digits(count: usize) -> impl Fn(&[u8]) -> IResult<&[u8], u32> {
+    move |i| map_res(map_res(take_while_m_n(count, count, is_digit), str::from_utf8), |d: &str| d.parse::<u32>())(i)
+}
+
+// day-name     = %x4D.6F.6E ; "Mon", case-sensitive / ...
+pub fn day_name(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    alt((tag("Mon"), tag("Tue"), tag("Wed"), tag("Thu"), tag("Fri"), tag("Sat"), tag("Sun")))(i)
+}
+
+// day-name-l   = %x4D.6F.6E.64.61.79 ; "Monday", case-sensitive / ...
+pub fn day_name_l(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    alt((tag("Monday"), tag("Tuesday"), tag("Wednesday"), tag("Thursday"), tag("Friday"), tag("Saturday"), tag("Sunday")))(i)
+}
+
+// month        = %x4A.61.6E ; "Jan", case-sensitive / ...
+pub fn month(i: &[u8]) -> IResult<&[u8], u32> {
+    alt((value(1, tag("Jan")), value(2, tag("Feb")), value(3, tag("Mar")), value(4, tag("Apr")),
+         value(5, tag("May")), value(6, tag("Jun")), value(7, tag("Jul")), value(8, tag("Aug")),
+         value(9, tag("Sep")), value(10, tag("Oct")), value(11, tag("Nov")), value(12, tag("Dec"))))(i)
+}
+
+// time-of-day  = hour ":" minute ":" second
+pub fn time_of_day(i: &[u8]) -> IResult<&[u8], (u32, u32, u32)> {
+    let (i, (hour, _, minute, _, second)) = tuple((digits(2), tag(":"), digits(2), tag(":"), digits(2)))(i)?;
+    Ok((i, (hour, minute, second)))
+}
+
+fn to_time(i: &[u8], year: i64, month: u32, day: u32, (hour, minute, second): (u32, u32, u32)) -> IResult<&[u8], SystemTime> {
+    match timestamp(year, month, day, hour, minute, second) {
+        Some(time) => Ok((i, time)),
+        None => Err(nom::Err::Error((i, ErrorKind::Verify))),
+    }
+}
+
+// IMF-fixdate  = day-name "," SP date1 SP time-of-day SP GMT
+// date1        = day SP month SP year ; e.g., 02 Jun 1982
+pub fn imf_fixdate(i: &[u8]) -> IResult<&[u8], SystemTime> {
+    let (i, (_, _, _, day, _, month, _, year, _, time, _, _)) = tuple((day_name, tag(","), space, digits(2), space, month, space, digits(4), space, time_of_day, space, tag("GMT")))(i)?;
+    to_time(i, i64::from(year), month, day, time)
+}
+
+// rfc850-date  = day-name-l "," SP date2 SP time-of-day SP GMT
+// date2        = day "-" month "-" 2DIGIT ; e.g., 02-Jun-82
+pub fn rfc850_date(i: &[u8]) -> IResult<&[u8], SystemTime> {
+    let (i, (_, _, _, day, _, month, _, year, _, time, _, _)) = tuple((day_name_l, tag(","), space, digits(2), tag("-"), month, tag("-"), digits(2), space, time_of_day, space, tag("GMT")))(i)?;
+    to_time(i, full_year(year, SystemTime::now()), month, day, time)
+}
+
+// asctime-date = day-name SP date3 SP time-of-day SP year
+// date3        = month SP ( 2DIGIT / ( SP 1DIGIT )) ; e.g., Jun  2
+pub fn asctime_date(i: &[u8]) -> IResult<&[u8], SystemTime> {
+    let (i, (_, _, month, _, day, _, time, _, year)) = tuple((day_name, space, month, space, alt((digits(2), preceded(space, digits(1)))), space, time_of_day, space, digits(4)))(i)?;
+    to_time(i, i64::from(year), month, day, time)
+}
+
+// HTTP-date    = IMF-fixdate / obs-date
+// obs-date     = rfc850-date / asctime-date
+pub fn http_date(i: &[u8]) -> IResult<&[u8], SystemTime> {
+    alt((imf_fixdate, rfc850_date, asctime_date))(i)
+}
+
+// #rule as per RFC 7230 section 7, empty elements are accepted
+// 1#element => *( "," OWS ) element *( OWS "," [ OWS element ] )
+pub fn list1<'a, O, F>(element: F) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], Vec<O>>
+    where F: Fn(&'a [u8]) -> IResult<&'a [u8], O> {
+    move |i| {
+        let (i, _) = many0(tuple((complete::char(','), ows)))(i)?;
+        let (i, first) = element(i)?;
+        let (i, rest) = many0(preceded(many1(tuple((ows, complete::char(','), ows))), &element))(i)?;
+        let (i, _) = many0(tuple((ows, complete::char(','))))(i)?;
+        let mut elements = vec!(first);
+        elements.extend(rest);
+        Ok((i, elements))
+    }
+}
+
+// #element => [ 1#element ]
+pub fn list<'a, O, F>(element: F) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], Vec<O>>
+    where F: Fn(&'a [u8]) -> IResult<&'a [u8], O> {
+    let list = list1(element);
+    move |i| map(opt(&list), Option::unwrap_or_default)(i)
+}
+
+// media-type = type "/" subtype *( OWS ";" OWS parameter )
+pub fn media_type(i: &[u8]) -> IResult<&[u8], MediaType<'_>> {
+    map(tuple((token, complete::char('/'), token, parameters)),
+        |(main_type, _, subtype, parameters)| MediaType { main_type, subtype, parameters })(i)
+}
+
+// qvalue = ( "0" [ "." 0*3DIGIT ] ) / ( "1" [ "." 0*3("0") ] )
+pub fn qvalue(i: &[u8]) -> IResult<&[u8], u16> {
+    let thousandths = |digits: &[u8]| digits.iter().chain(b"000").take(3).fold(0, |value, digit| value * 10 + u16::from(digit - b'0'));
+    alt((
+        map(preceded(tag("0"), opt(preceded(tag("."), take_while_m_n(0, 3, is_digit)))), move |digits| digits.map_or(0, thousandths)),
+        value(1000, preceded(tag("1"), opt(preceded(tag("."), take_while_m_n(0, 3, |chr| chr == b'0'))))),
+    ))(i)
+}
+
+// weight = OWS ";" OWS "q=" qvalue
+pub fn weight(i: &[u8]) -> IResult<&[u8], u16> {
+    preceded(tuple((ows, complete::char(';'), ows, alt((tag("q="), tag("Q="))))), qvalue)(i)
+}
+
+// Accept = #( media-range [ accept-params ] )
+// media-range = ( "*/*" / ( type "/*" ) / ( type "/" subtype ) ) *( OWS ";" OWS parameter )
+// accept-params = weight *( accept-ext ), so parameters after q are extensions and are dropped
+pub fn accept(i: &[u8]) -> IResult<&[u8], Vec<Quality<MediaType<'_>>>> {
+    list(|i| {
+        let (rest, mut media) = media_type(i)?;
+        let mut quality = 1000;
+        if let Some(index) = media.parameters.iter().position(|(name, _)| name.eq_ignore_ascii_case("q")) {
+            quality = match qvalue(media.parameters[index].1.as_bytes()) {
+                Ok(([], quality)) => quality,
+                _ => return Err(nom::Err::Error((i, ErrorKind::Verify))),
+            };
+            media.parameters.truncate(index);
+        }
+        Ok((rest, Quality { value: media, quality }))
+    })(i)
+}
+
+// Accept-Charset, Accept-Encoding and Accept-Language = #( token [ weight ] )
+pub fn accept_tokens(i: &[u8]) -> IResult<&[u8], Vec<Quality<&str>>> {
+    list(map(tuple((token, opt(weight))), |(value, quality)| Quality { value, quality: quality.unwrap_or(1000) }))(i)
+}
+
+// cache-directive = token [ "=" ( token / quoted-string ) ]
+pub fn cache_directive(i: &[u8]) -> IResult<&[u8], (&str, Option<Cow<'_, str>>)> {
+    tuple((token, opt(preceded(complete::char('='), alt((map(token, Cow::from), quoted_string))))))(i)
+}
+
+// entity-tag = [ weak ] opaque-tag
+// weak       = %x57.2F ; "W/", case-sensitive
+// opaque-tag = DQUOTE *etagc DQUOTE
+// etagc      = %x21 / %x23-7E / obs-text
+pub fn entity_tag(i: &[u8]) -> IResult<&[u8], EntityTag<'_>> {
+    map(tuple((opt(tag("W/")), delimited(tag("\""), map_res(take_while(|chr| chr == 0x21 || chr >= 0x23 && chr != 0x7F), str::from_utf8), tag("\"")))),
+        |(weak, tag)| EntityTag { weak: weak.is_some(), tag })(i)
+}
+
+// If-Match = "*" / 1#entity-tag
+pub fn entity_tags(i: &[u8]) -> IResult<&[u8], EntityTags<'_>> {
+    alt((value(EntityTags::Any, tag("*")), map(list1(entity_tag), EntityTags::Tags)))(i)
+}
+
+// protocol = protocol-name [ "/" protocol-version ]
+pub fn protocol(i: &[u8]) -> IResult<&[u8], Protocol<'_>> {
+    map(tuple((token, opt(preceded(complete::char('/'), token)))), |(name, version)| Protocol { name, version })(i)
+}
+
+// 1*DIGIT as a number, for lengths and positions as well as delays
+pub fn number(i: &[u8]) -> IResult<&[u8], u64> {
+    map_res(map_res(take_while1(is_digit), str::from_utf8), str::parse)(i)
+}
+
+// Retry-After = HTTP-date / delay-seconds
+pub fn retry_after(i: &[u8]) -> IResult<&[u8], RetryAfter> {
+    alt((map(http_date, RetryAfter::Date), map(delay_seconds, |seconds| RetryAfter::Delay(Duration::from_secs(seconds)))))(i)
+}
+
+// byte-range-spec = first-byte-pos "-" [ last-byte-pos ], suffix-byte-range-spec = "-" suffix-length
+pub fn byte_range_spec(i: &[u8]) -> IResult<&[u8], ByteRange> {
+    alt((map(verify(tuple((number, complete::char('-'), opt(number))), |(first, _, last)| last.is_none_or(|last| last >= *first)),
+             |(first, _, last)| ByteRange::Bytes(first, last)),
+         map(preceded(complete::char('-'), number), ByteRange::Suffix)))(i)
+}
+
+// byte-ranges-specifier = bytes-unit "=" byte-range-set
+pub fn byte_ranges_specifier(i: &[u8]) -> IResult<&[u8], Vec<ByteRange>> {
+    preceded(tag("bytes="), list1(byte_range_spec))(i)
+}
+
+// If-Range = entity-tag / HTTP-date
+pub fn if_range(i: &[u8]) -> IResult<&[u8], IfRange<'_>> {
+    alt((map(entity_tag, IfRange::Tag), map(http_date, IfRange::Date)))(i)
+}
+
 #[cfg(test)]
 mod tests {
     use std::borrow::Cow;
+    use std::net::{Ipv4Addr, Ipv6Addr};
 
     use crate::ast::*;
+    use crate::uri::{Authority, Host};
 
     #[test]
     fn http_name() {
@@ -253,6 +585,27 @@ mod tests {
         assert_eq!(super::request_target(&b"http://www.example.org/pub/WWW/TheProject.html "[..]), Ok((&b" "[..], "http://www.example.org/pub/WWW/TheProject.html")));
         assert_eq!(super::request_target(&b"www.example.com:80 "[..]), Ok((&b" "[..], "www.example.com:80")));
         assert_eq!(super::request_target(&b"* "[..]), Ok((&b" "[..], "*")));
+        assert_eq!(super::request_target(&b"[::1]:443 "[..]), Ok((&b" "[..], "[::1]:443")));
+        assert_eq!(super::request_target(&b"/a<b> "[..]), Ok((&b"<b> "[..], "/a")));
+        assert_eq!(super::request_target(&b"/caf%C3%A9?x=%2 "[..]), Ok((&b"%2 "[..], "/caf%C3%A9?x=")));
+    }
+
+    #[test]
+    fn request_line_ties_target_form_to_method() {
+        assert!(super::request_line(&b"CONNECT www.example.com:80 HTTP/1.1\r\n"[..]).is_ok());
+        assert!(super::request_line(&b"CONNECT [::1]:443 HTTP/1.1\r\n"[..]).is_ok());
+        assert!(super::request_line(&b"CONNECT /path HTTP/1.1\r\n"[..]).is_err());
+        assert!(super::request_line(&b"CONNECT :80 HTTP/1.1\r\n"[..]).is_err());
+        assert!(super::request_line(&b"CONNECT www.example.com HTTP/1.1\r\n"[..]).is_err());
+        assert!(super::request_line(&b"CONNECT www.example.com: HTTP/1.1\r\n"[..]).is_err());
+        assert!(super::request_line(&b"CONNECT user@www.example.com:443 HTTP/1.1\r\n"[..]).is_err());
+        assert!(super::request_line(&b"OPTIONS * HTTP/1.1\r\n"[..]).is_ok());
+        assert!(super::request_line(&b"GET * HTTP/1.1\r\n"[..]).is_err());
+        assert!(super::request_line(&b"GET [::1]:443 HTTP/1.1\r\n"[..]).is_err());
+        assert!(super::request_line(&b"GET http://example.com/a?b HTTP/1.1\r\n"[..]).is_ok());
+        assert!(super::request_line(&b"GET /a\"b HTTP/1.1\r\n"[..]).is_err());
+        assert!(super::request_line(&b"GET /a#fragment HTTP/1.1\r\n"[..]).is_err());
+        assert!(super::request_line(&b"GET relative HTTP/1.1\r\n"[..]).is_err());
     }
 
     #[test]
@@ -307,6 +660,7 @@ mod tests {
         assert_eq!(super::field_content(&b"a  b"[..]), Ok((&b""[..], &b"a  b"[..])));
         assert_eq!(super::field_content(&b"a b"[..]), Ok((&b""[..], &b"a b"[..])));
         assert_eq!(super::field_content(&b"a"[..]), Ok((&b""[..], &b"a"[..])));
+        assert_eq!(super::field_content(&b"a b\tc "[..]), Ok((&b" "[..], &b"a b\tc"[..])));
     }
 
     #[test]
@@ -322,6 +676,8 @@ mod tests {
         assert_eq!(super::header_field(&b"Content-Type: plain/text"[..]), Ok((&b""[..], Header::new("Content-Type", "plain/text"))));
         assert_eq!(super::header_field(&b"Content-Type: plain/text "[..]), Ok((&b""[..], Header::new("Content-Type", "plain/text"))));
         assert_eq!(super::header_field(&b"Content-Type: plain/\r\n text "[..]), Ok((&b""[..], Header::new("Content-Type", "plain/text"))));
+        assert_eq!(super::header_field(&b"X-Words: of a real header"[..]), Ok((&b""[..], Header::new("X-Words", "of a real header"))));
+        assert_eq!(super::header_field(&b"Content-Type : plain/text"[..]), Ok((&b""[..], Header::new("Content-Type", "plain/text"))));
     }
 
     #[test]
@@ -396,6 +752,7 @@ mod tests {
         assert_eq!(super::transfer_coding(&b"compress"[..]), Ok((&b""[..], TransferCoding::Compress)));
         assert_eq!(super::transfer_coding(&b"deflate"[..]), Ok((&b""[..], TransferCoding::Deflate)));
         assert_eq!(super::transfer_coding(&b"gzip"[..]), Ok((&b""[..], TransferCoding::Gzip)));
+        assert_eq!(super::transfer_coding(&b"Chunked"[..]), Ok((&b""[..], TransferCoding::Chunked)));
         assert_eq!(super::transfer_coding(&b"cat ; foo=bar"[..]), Ok((&b""[..], TransferCoding::Extension("cat", vec![TransferParameter::new("foo", Some("bar"))]))));
     }
 
@@ -404,4 +761,45 @@ mod tests {
         assert_eq!(super::transfer_encoding(&b"gzip, chunked"[..]), Ok((&b""[..], vec![TransferCoding::Gzip, TransferCoding::Chunked])));
         assert_eq!(super::transfer_encoding(&b"chunked"[..]), Ok((&b""[..], vec![TransferCoding::Chunked])));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn parameters() {
+        assert_eq!(super::parameters(&b"; charset=utf-8 ;boundary=\"a b;c\""[..]), Ok((&b""[..], vec!(("charset", Cow::from("utf-8")), ("boundary", Cow::from("a b;c"))))));
+        assert_eq!(super::parameters(&b""[..]), Ok((&b""[..], vec!())));
+    }
+
+    #[test]
+    fn authority() {
+        assert_eq!(super::authority(&b"dan:secret@Example.com:8080"[..]), Ok((&b""[..], Authority { user_info: Some("dan:secret"), host: Host::RegName("Example.com"), port: Some(8080) })));
+        assert_eq!(super::authority(&b"[::1]:8080"[..]), Ok((&b""[..], Authority { user_info: None, host: Host::Ipv6(Ipv6Addr::LOCALHOST), port: Some(8080) })));
+        assert_eq!(super::authority(&b"127.0.0.1:"[..]), Ok((&b""[..], Authority { user_info: None, host: Host::Ipv4(Ipv4Addr::LOCALHOST), port: None })));
+        assert_eq!(super::authority(&b"1.2.3.4.example"[..]), Ok((&b""[..], Authority { user_info: None, host: Host::RegName("1.2.3.4.example"), port: None })));
+        assert_eq!(super::authority(&b"caf%C3%A9"[..]), Ok((&b""[..], Authority { user_info: None, host: Host::RegName("caf%C3%A9"), port: None })));
+        assert_eq!(super::authority(&b"[::1"[..]).map(|(i, _)| i), Ok(&b"[::1"[..]));
+        assert!(super::port(&b"65536"[..]).is_err());
+    }
+
+    #[test]
+    fn host() {
+        assert_eq!(super::host(&b"localhost:80"[..]), Ok((&b""[..], Authority { user_info: None, host: Host::RegName("localhost"), port: Some(80) })));
+        assert_eq!(super::host(&b"user@localhost"[..]), Ok((&b"@localhost"[..], Authority { user_info: None, host: Host::RegName("user"), port: None })));
+    }
+
+    #[test]
+    fn http_date() {
+        use crate::date::seconds;
+
+        assert_eq!(super::http_date(&b"Sun, 06 Nov 1994 08:49:37 GMT"[..]).map(|(i, time)| (i, seconds(time))), Ok((&b""[..], 784_111_777)));
+        assert_eq!(super::http_date(&b"Sunday, 06-Nov-94 08:49:37 GMT"[..]).map(|(i, time)| (i, seconds(time))), Ok((&b""[..], 784_111_777)));
+        assert_eq!(super::http_date(&b"Sun Nov  6 08:49:37 1994"[..]).map(|(i, time)| (i, seconds(time))), Ok((&b""[..], 784_111_777)));
+        assert!(super::http_date(&b"Sun, 31 Nov 1994 08:49:37 GMT"[..]).is_err());
+        assert!(super::http_date(&b"sun, 06 Nov 1994 08:49:37 GMT"[..]).is_err());
+    }
+
+    #[test]
+    fn number() {
+        assert_eq!(super::number(&b"0123-"[..]), Ok((&b"-"[..], 123)));
+        assert!(super::number(&b"-1"[..]).is_err());
+        assert!(super::number(&b"18446744073709551616"[..]).is_err());
+    }
+}
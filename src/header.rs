@@ -0,0 +1,713 @@
+use std::borrow::Cow;
+use std::{fmt, ops};
+use std::io::Result;
+use std::time::{Duration, SystemTime};
+use crate::date::format_http_date;
+use crate::grammar;
+use crate::method::Method;
+use crate::parser::parse_all;
+use crate::uri::{Authority, Uri};
+
+/// A header field with a typed value, `parse` gets every field line with the name in order
+pub trait TypedHeader<'a>: Sized {
+    const NAME: &'static str;
+
+    fn parse(values: &[&'a str]) -> Result<Self>;
+
+    fn format(&self) -> String;
+}
+
+/// Fields that may only appear once use the first line
+fn single<'a, O, F>(name: &str, values: &[&'a str], parser: F) -> Result<O>
+    where F: Fn(&'a [u8]) -> nom::IResult<&'a [u8], O> {
+    let value = values.first().map(|value| value.trim()).unwrap_or("");
+    parse_all(name, value.as_bytes(), parser(value.as_bytes()))
+}
+
+/// List fields may be split across lines, RFC 7230 section 3.2.2
+fn list<'a, O, F>(name: &str, values: &[&'a str], parser: F) -> Result<Vec<O>>
+    where F: Fn(&'a [u8]) -> nom::IResult<&'a [u8], Vec<O>> {
+    let mut result = Vec::new();
+    for value in values {
+        let value = value.trim();
+        result.extend(parse_all(name, value.as_bytes(), parser(value.as_bytes()))?);
+    }
+    Ok(result)
+}
+
+fn join<T>(values: &[T]) -> String where T: fmt::Display {
+    values.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
+fn is_token(value: &str) -> bool {
+    matches!(grammar::token(value.as_bytes()), Ok(([], _)))
+}
+
+/// A token is written as is, anything else as a quoted-string
+pub fn quote(value: &str) -> Cow<'_, str> {
+    if is_token(value) {
+        return Cow::from(value);
+    }
+    Cow::from(format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")))
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct MediaType<'a> {
+    pub main_type: &'a str,
+    pub subtype: &'a str,
+    pub parameters: Vec<(&'a str, Cow<'a, str>)>,
+}
+
+impl<'a> MediaType<'a> {
+    pub fn new(main_type: &'a str, subtype: &'a str) -> MediaType<'a> {
+        MediaType { main_type, subtype, parameters: vec!() }
+    }
+
+    pub fn parse(value: &'a str) -> Result<MediaType<'a>> {
+        single("media-type", &[value], grammar::media_type)
+    }
+
+    pub fn parameter<V>(mut self, name: &'a str, value: V) -> MediaType<'a> where V: Into<Cow<'a, str>> {
+        self.parameters.retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        self.parameters.push((name, value.into()));
+        self
+    }
+
+    /// The structured syntax suffix of the subtype, `json` for `application/problem+json`
+    pub fn suffix(&self) -> Option<&'a str> {
+        self.subtype.rfind('+').map(|index| &self.subtype[index + 1..])
+    }
+
+    /// Parameter names are case-insensitive
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.parameters.iter().
+            find(|(key, _)| key.eq_ignore_ascii_case(name)).
+            map(|(_, value)| value.as_ref())
+    }
+
+    /// Compares type and subtype ignoring case and parameters, e.g. `media_type.is("text/html")`
+    pub fn is(&self, essence: &str) -> bool {
+        match essence.find('/') {
+            Some(index) => self.main_type.eq_ignore_ascii_case(&essence[..index]) && self.subtype.eq_ignore_ascii_case(&essence[index + 1..]),
+            None => false,
+        }
+    }
+
+    /// True when this media range, such as `text/*` or `*/*`, includes `other`
+    pub fn includes(&self, other: &MediaType) -> bool {
+        (self.main_type == "*" || self.main_type.eq_ignore_ascii_case(other.main_type)) &&
+            (self.subtype == "*" || self.subtype.eq_ignore_ascii_case(other.subtype)) &&
+            self.parameters.iter().all(|(name, value)| other.get(name).is_some_and(|other| other.eq_ignore_ascii_case(value)))
+    }
+}
+
+impl<'a> fmt::Display for MediaType<'a> {
+    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
+        write!(format, "{}/{}", self.main_type, self.subtype)?;
+        for (name, value) in &self.parameters {
+            write!(format, "; {}={}", name, quote(value))?;
+        }
+        Ok(())
+    }
+}
+
+/// A value with a weight in thousandths, RFC 7231 section 5.3.1
+#[derive(PartialEq, Debug, Clone)]
+pub struct Quality<T> {
+    pub value: T,
+    pub quality: u16,
+}
+
+impl<T> Quality<T> {
+    pub fn new(value: T, quality: u16) -> Quality<T> {
+        Quality { value, quality }
+    }
+}
+
+impl<T> fmt::Display for Quality<T> where T: fmt::Display {
+    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
+        write!(format, "{}", self.value)?;
+        match self.quality {
+            1000 => Ok(()),
+            0 => format.write_str(";q=0"),
+            quality => write!(format, ";q={}", format!("0.{:03}", quality).trim_end_matches('0')),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct EntityTag<'a> {
+    pub weak: bool,
+    pub tag: &'a str,
+}
+
+impl<'a> EntityTag<'a> {
+    pub fn strong(tag: &'a str) -> EntityTag<'a> {
+        EntityTag { weak: false, tag }
+    }
+
+    pub fn weak(tag: &'a str) -> EntityTag<'a> {
+        EntityTag { weak: true, tag }
+    }
+
+    pub fn parse(value: &'a str) -> Result<EntityTag<'a>> {
+        single("entity-tag", &[value], grammar::entity_tag)
+    }
+
+    /// RFC 7232 section 2.3.2, both must be strong
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    pub fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.tag == other.tag
+    }
+}
+
+impl<'a> fmt::Display for EntityTag<'a> {
+    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
+        write!(format, "{}\"{}\"", if self.weak { "W/" } else { "" }, self.tag)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum EntityTags<'a> {
+    Any,
+    Tags(Vec<EntityTag<'a>>),
+}
+
+impl<'a> fmt::Display for EntityTags<'a> {
+    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EntityTags::Any => format.write_str("*"),
+            EntityTags::Tags(ref tags) => format.write_str(&join(tags)),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Protocol<'a> {
+    pub name: &'a str,
+    pub version: Option<&'a str>,
+}
+
+impl<'a> fmt::Display for Protocol<'a> {
+    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
+        match self.version {
+            Some(version) => write!(format, "{}/{}", self.name, version),
+            None => format.write_str(self.name),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ContentType<'a>(pub MediaType<'a>);
+
+impl<'a> TypedHeader<'a> for ContentType<'a> {
+    const NAME: &'static str = "Content-Type";
+
+    fn parse(values: &[&'a str]) -> Result<Self> {
+        single(Self::NAME, values, grammar::media_type).map(ContentType)
+    }
+
+    fn format(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct ContentLength(pub u64);
+
+impl<'a> TypedHeader<'a> for ContentLength {
+    const NAME: &'static str = "Content-Length";
+
+    fn parse(values: &[&'a str]) -> Result<Self> {
+        single(Self::NAME, values, grammar::number).map(ContentLength)
+    }
+
+    fn format(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Host<'a>(pub Authority<'a>);
+
+impl<'a> TypedHeader<'a> for Host<'a> {
+    const NAME: &'static str = "Host";
+
+    fn parse(values: &[&'a str]) -> Result<Self> {
+        single(Self::NAME, values, grammar::host).map(Host)
+    }
+
+    fn format(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+macro_rules! date_header {
+    ($header:ident, $name:expr) => {
+        #[derive(PartialEq, Debug, Clone, Copy)]
+        pub struct $header(pub SystemTime);
+
+        impl<'a> TypedHeader<'a> for $header {
+            const NAME: &'static str = $name;
+
+            fn parse(values: &[&'a str]) -> Result<Self> {
+                single(Self::NAME, values, grammar::http_date).map($header)
+            }
+
+            fn format(&self) -> String {
+                format_http_date(self.0)
+            }
+        }
+    };
+}
+
+date_header!(Date, "Date");
+date_header!(LastModified, "Last-Modified");
+date_header!(IfModifiedSince, "If-Modified-Since");
+date_header!(IfUnmodifiedSince, "If-Unmodified-Since");
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Accept<'a>(pub Vec<Quality<MediaType<'a>>>);
+
+impl<'a> TypedHeader<'a> for Accept<'a> {
+    const NAME: &'static str = "Accept";
+
+    fn parse(values: &[&'a str]) -> Result<Self> {
+        list(Self::NAME, values, grammar::accept).map(Accept)
+    }
+
+    fn format(&self) -> String {
+        join(&self.0)
+    }
+}
+
+macro_rules! accept_tokens {
+    ($(#[$meta:meta])* $header:ident, $name:expr) => {
+        $(#[$meta])*
+        #[derive(PartialEq, Debug, Clone, Default)]
+        pub struct $header<'a>(pub Vec<Quality<&'a str>>);
+
+        impl<'a> TypedHeader<'a> for $header<'a> {
+            const NAME: &'static str = $name;
+
+            fn parse(values: &[&'a str]) -> Result<Self> {
+                list(Self::NAME, values, grammar::accept_tokens).map($header)
+            }
+
+            fn format(&self) -> String {
+                join(&self.0)
+            }
+        }
+    };
+}
+
+accept_tokens!(AcceptCharset, "Accept-Charset");
+accept_tokens!(AcceptEncoding, "Accept-Encoding");
+accept_tokens!(
+    /// Language ranges are kept as tokens, matching is left to content negotiation
+    AcceptLanguage, "Accept-Language");
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct CacheControl<'a>(pub Vec<(&'a str, Option<Cow<'a, str>>)>);
+
+impl<'a> CacheControl<'a> {
+    pub fn directive<V>(mut self, name: &'a str, value: Option<V>) -> CacheControl<'a> where V: Into<Cow<'a, str>> {
+        self.0.push((name, value.map(Into::into)));
+        self
+    }
+
+    /// Directive names are case-insensitive
+    pub fn has(&self, name: &str) -> bool {
+        self.0.iter().any(|(key, _)| key.eq_ignore_ascii_case(name))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.iter().
+            find(|(key, _)| key.eq_ignore_ascii_case(name)).
+            and_then(|(_, value)| value.as_ref().map(|value| value.as_ref()))
+    }
+
+    pub fn max_age(&self) -> Option<Duration> {
+        self.get("max-age").and_then(|value| value.parse().ok()).map(Duration::from_secs)
+    }
+}
+
+impl<'a> TypedHeader<'a> for CacheControl<'a> {
+    const NAME: &'static str = "Cache-Control";
+
+    fn parse(values: &[&'a str]) -> Result<Self> {
+        list(Self::NAME, values, grammar::list1(grammar::cache_directive)).map(CacheControl)
+    }
+
+    fn format(&self) -> String {
+        self.0.iter().map(|(name, value)| match value {
+            Some(value) => format!("{}={}", name, quote(value)),
+            None => name.to_string(),
+        }).collect::<Vec<_>>().join(", ")
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ETag<'a>(pub EntityTag<'a>);
+
+impl<'a> TypedHeader<'a> for ETag<'a> {
+    const NAME: &'static str = "ETag";
+
+    fn parse(values: &[&'a str]) -> Result<Self> {
+        single(Self::NAME, values, grammar::entity_tag).map(ETag)
+    }
+
+    fn format(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+macro_rules! entity_tags {
+    ($header:ident, $name:expr) => {
+        #[derive(PartialEq, Debug, Clone)]
+        pub struct $header<'a>(pub EntityTags<'a>);
+
+        impl<'a> TypedHeader<'a> for $header<'a> {
+            const NAME: &'static str = $name;
+
+            fn parse(values: &[&'a str]) -> Result<Self> {
+                if values.len() == 1 {
+                    return single(Self::NAME, values, grammar::entity_tags).map($header);
+                }
+                list(Self::NAME, values, grammar::list1(grammar::entity_tag)).map(|tags| $header(EntityTags::Tags(tags)))
+            }
+
+            fn format(&self) -> String {
+                self.0.to_string()
+            }
+        }
+    };
+}
+
+entity_tags!(IfMatch, "If-Match");
+entity_tags!(IfNoneMatch, "If-None-Match");
+
+#[derive(PartialEq, Debug)]
+pub struct Location<'a>(pub Uri<'a>);
+
+impl<'a> TypedHeader<'a> for Location<'a> {
+    const NAME: &'static str = "Location";
+
+    fn parse(values: &[&'a str]) -> Result<Self> {
+        Ok(Location(Uri::parse(values.first().map(|value| value.trim()).unwrap_or(""))))
+    }
+
+    fn format(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Allow<'a>(pub Vec<Method<'a>>);
+
+impl<'a> TypedHeader<'a> for Allow<'a> {
+    const NAME: &'static str = "Allow";
+
+    fn parse(values: &[&'a str]) -> Result<Self> {
+        list(Self::NAME, values, grammar::list(grammar::method)).
+            map(|methods| Allow(methods.into_iter().map(Method::from).collect()))
+    }
+
+    fn format(&self) -> String {
+        join(&self.0)
+    }
+}
+
+/// `*` is kept as a field name
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Vary<'a>(pub Vec<&'a str>);
+
+impl<'a> Vary<'a> {
+    pub fn has(&self, name: &str) -> bool {
+        self.0.iter().any(|field| *field == "*" || field.eq_ignore_ascii_case(name))
+    }
+}
+
+impl<'a> TypedHeader<'a> for Vary<'a> {
+    const NAME: &'static str = "Vary";
+
+    fn parse(values: &[&'a str]) -> Result<Self> {
+        list(Self::NAME, values, grammar::list1(grammar::field_name)).map(Vary)
+    }
+
+    fn format(&self) -> String {
+        self.0.join(", ")
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Connection<'a>(pub Vec<&'a str>);
+
+impl<'a> Connection<'a> {
+    /// Connection options are case-insensitive
+    pub fn has(&self, option: &str) -> bool {
+        self.0.iter().any(|value| value.eq_ignore_ascii_case(option))
+    }
+}
+
+impl<'a> TypedHeader<'a> for Connection<'a> {
+    const NAME: &'static str = "Connection";
+
+    fn parse(values: &[&'a str]) -> Result<Self> {
+        list(Self::NAME, values, grammar::list1(grammar::token)).map(Connection)
+    }
+
+    fn format(&self) -> String {
+        self.0.join(", ")
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Upgrade<'a>(pub Vec<Protocol<'a>>);
+
+impl<'a> TypedHeader<'a> for Upgrade<'a> {
+    const NAME: &'static str = "Upgrade";
+
+    fn parse(values: &[&'a str]) -> Result<Self> {
+        list(Self::NAME, values, grammar::list1(grammar::protocol)).map(Upgrade)
+    }
+
+    fn format(&self) -> String {
+        join(&self.0)
+    }
+}
+
+/// A byte-range-spec, positions are inclusive as written on the wire
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ByteRange {
+    Bytes(u64, Option<u64>),
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// The half-open range of a representation of `length` bytes, `None` when unsatisfiable, RFC 7233 section 2.1
+    pub fn resolve(&self, length: u64) -> Option<ops::Range<u64>> {
+        match *self {
+            ByteRange::Bytes(first, last) if first < length => Some(first..last.map_or(length, |last| last.saturating_add(1).min(length))),
+            ByteRange::Suffix(suffix) if suffix > 0 && length > 0 => Some(length.saturating_sub(suffix)..length),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ByteRange {
+    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ByteRange::Bytes(first, Some(last)) => write!(format, "{}-{}", first, last),
+            ByteRange::Bytes(first, None) => write!(format, "{}-", first),
+            ByteRange::Suffix(suffix) => write!(format, "-{}", suffix),
+        }
+    }
+}
+
+/// Only the `bytes` unit is understood, anything else fails to parse and should be ignored
+#[derive(PartialEq, Debug, Clone)]
+pub struct Range(pub Vec<ByteRange>);
+
+impl<'a> TypedHeader<'a> for Range {
+    const NAME: &'static str = "Range";
+
+    fn parse(values: &[&'a str]) -> Result<Self> {
+        single(Self::NAME, values, grammar::byte_ranges_specifier).map(Range)
+    }
+
+    fn format(&self) -> String {
+        format!("bytes={}", join(&self.0))
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum IfRange<'a> {
+    Tag(EntityTag<'a>),
+    Date(SystemTime),
+}
+
+impl<'a> TypedHeader<'a> for IfRange<'a> {
+    const NAME: &'static str = "If-Range";
+
+    fn parse(values: &[&'a str]) -> Result<Self> {
+        single(Self::NAME, values, grammar::if_range)
+    }
+
+    fn format(&self) -> String {
+        match *self {
+            IfRange::Tag(ref tag) => tag.to_string(),
+            IfRange::Date(date) => format_http_date(date),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum RetryAfter {
+    Date(SystemTime),
+    Delay(Duration),
+}
+
+impl RetryAfter {
+    /// How long to wait from now, a date in the past means no wait
+    pub fn delay(&self) -> Duration {
+        match *self {
+            RetryAfter::Date(date) => date.duration_since(SystemTime::now()).unwrap_or_default(),
+            RetryAfter::Delay(delay) => delay,
+        }
+    }
+}
+
+impl<'a> TypedHeader<'a> for RetryAfter {
+    const NAME: &'static str = "Retry-After";
+
+    fn parse(values: &[&'a str]) -> Result<Self> {
+        single(Self::NAME, values, grammar::retry_after)
+    }
+
+    fn format(&self) -> String {
+        match *self {
+            RetryAfter::Date(date) => format_http_date(date),
+            RetryAfter::Delay(delay) => delay.as_secs().to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Headers;
+    use crate::date::from_seconds;
+    use crate::uri;
+
+    fn round_trip<'a, H>(value: &'a str) -> String where H: TypedHeader<'a> {
+        H::parse(&[value]).unwrap().format()
+    }
+
+    #[test]
+    fn parses_content_type() {
+        let ContentType(media_type) = ContentType::parse(&["text/html; charset=\"UTF-8\";q=x"]).unwrap();
+        assert_eq!((media_type.main_type, media_type.subtype), ("text", "html"));
+        assert_eq!(media_type.get("Charset"), Some("UTF-8"));
+        assert!(media_type.is("TEXT/html"));
+        assert_eq!(media_type.suffix(), None);
+        assert_eq!(MediaType::parse("application/vnd.api+json").unwrap().suffix(), Some("json"));
+        assert_eq!(round_trip::<ContentType>("multipart/form-data;boundary=\"a b\""), "multipart/form-data; boundary=\"a b\"");
+        assert!(ContentType::parse(&["text"]).is_err());
+        assert!(ContentType::parse(&[]).is_err());
+    }
+
+    #[test]
+    fn media_ranges_include_media_types() {
+        let html = MediaType::parse("text/html; level=1").unwrap();
+        assert!(MediaType::parse("*/*").unwrap().includes(&html));
+        assert!(MediaType::parse("text/*").unwrap().includes(&html));
+        assert!(MediaType::parse("text/html;level=1").unwrap().includes(&html));
+        assert!(!MediaType::parse("text/html;level=2").unwrap().includes(&html));
+        assert!(!MediaType::parse("image/*").unwrap().includes(&html));
+    }
+
+    #[test]
+    fn parses_accept() {
+        let Accept(ranges) = Accept::parse(&["text/*;q=0.3, text/html;q=0.7, text/html;level=1,", "*/*;q=0.5;ext=1"]).unwrap();
+        assert_eq!(ranges.iter().map(|range| (range.value.to_string(), range.quality)).collect::<Vec<_>>(),
+                   vec!(("text/*".to_string(), 300), ("text/html".to_string(), 700), ("text/html; level=1".to_string(), 1000), ("*/*".to_string(), 500)));
+        assert_eq!(Accept::parse(&[""]).unwrap(), Accept(vec!()));
+        assert!(Accept::parse(&["text/html;q=2"]).is_err());
+        assert_eq!(round_trip::<Accept>("text/html;q=0.250, */*;q=0"), "text/html;q=0.25, */*;q=0");
+    }
+
+    #[test]
+    fn parses_accept_tokens() {
+        assert_eq!(AcceptEncoding::parse(&["gzip;q=1.0, identity; q=0.5, *;q=0"]).unwrap().0,
+                   vec!(Quality::new("gzip", 1000), Quality::new("identity", 500), Quality::new("*", 0)));
+        assert_eq!(AcceptLanguage::parse(&["da, en-gb;q=0.8, en;q=0.7"]).unwrap().0[1], Quality::new("en-gb", 800));
+        assert_eq!(AcceptCharset::parse(&["utf-8"]).unwrap().format(), "utf-8");
+        assert!(AcceptEncoding::parse(&["gzip;q=0.1234"]).is_err());
+    }
+
+    #[test]
+    fn parses_cache_control() {
+        let cache_control = CacheControl::parse(&["no-cache, max-age=60", "private=\"Set-Cookie\""]).unwrap();
+        assert!(cache_control.has("No-Cache"));
+        assert_eq!(cache_control.max_age(), Some(Duration::from_secs(60)));
+        assert_eq!(cache_control.get("private"), Some("Set-Cookie"));
+        assert_eq!(cache_control.format(), "no-cache, max-age=60, private=Set-Cookie");
+        assert_eq!(CacheControl::default().directive("public", None::<&str>).format(), "public");
+    }
+
+    #[test]
+    fn parses_entity_tags() {
+        assert_eq!(ETag::parse(&["W/\"xyzzy\""]).unwrap(), ETag(EntityTag::weak("xyzzy")));
+        assert_eq!(round_trip::<ETag>("\"\""), "\"\"");
+        assert!(ETag::parse(&["xyzzy"]).is_err());
+        assert_eq!(IfMatch::parse(&["*"]).unwrap(), IfMatch(EntityTags::Any));
+        assert_eq!(IfNoneMatch::parse(&["\"a\", W/\"b\"", "\"c\""]).unwrap(),
+                   IfNoneMatch(EntityTags::Tags(vec!(EntityTag::strong("a"), EntityTag::weak("b"), EntityTag::strong("c")))));
+        assert!(EntityTag::weak("1").weak_eq(&EntityTag::strong("1")));
+        assert!(!EntityTag::weak("1").strong_eq(&EntityTag::strong("1")));
+        assert!(EntityTag::strong("1").strong_eq(&EntityTag::strong("1")));
+    }
+
+    #[test]
+    fn parses_list_headers() {
+        assert_eq!(Allow::parse(&["GET, HEAD", "PROPFIND"]).unwrap(), Allow(vec!(Method::Get, Method::Head, Method::Extension("PROPFIND"))));
+        assert_eq!(Allow::parse(&[""]).unwrap(), Allow(vec!()));
+        assert!(Vary::parse(&["Accept-Encoding, *"]).unwrap().has("Cookie"));
+        assert!(Connection::parse(&["keep-alive, Upgrade"]).unwrap().has("upgrade"));
+        assert_eq!(Upgrade::parse(&["HTTP/2.0, websocket"]).unwrap().format(), "HTTP/2.0, websocket");
+        assert!(Connection::parse(&["a b"]).is_err());
+    }
+
+    #[test]
+    fn parses_simple_headers() {
+        assert_eq!(ContentLength::parse(&["42"]).unwrap(), ContentLength(42));
+        assert!(ContentLength::parse(&["+42"]).is_err());
+        assert_eq!(Host::parse(&["example.com:8080"]).unwrap().0.port, Some(8080));
+        assert_eq!(Host::parse(&["[::1]"]).unwrap().0.host, uri::Host::Ipv6("::1".parse().unwrap()));
+        assert_eq!(Date::parse(&["Sun, 06 Nov 1994 08:49:37 GMT"]).unwrap(), Date(from_seconds(784_111_777)));
+        assert_eq!(Date(from_seconds(784_111_777)).format(), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(IfModifiedSince::parse(&["Sunday, 06-Nov-94 08:49:37 GMT"]).unwrap(), IfModifiedSince(from_seconds(784_111_777)));
+        assert!(LastModified::parse(&["yesterday"]).is_err());
+        assert_eq!(Location::parse(&["/pub/WWW/People.html#tim"]).unwrap().0.fragment, Some("tim"));
+        assert_eq!(RetryAfter::parse(&["120"]).unwrap(), RetryAfter::Delay(Duration::from_secs(120)));
+        assert_eq!(RetryAfter::parse(&["Fri, 31 Dec 1999 23:59:59 GMT"]).unwrap().delay(), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(Range::parse(&["bytes=0-499, 500-, -200"]).unwrap(),
+                   Range(vec!(ByteRange::Bytes(0, Some(499)), ByteRange::Bytes(500, None), ByteRange::Suffix(200))));
+        assert_eq!(round_trip::<Range>("bytes=1-1,-5"), "bytes=1-1, -5");
+        assert!(Range::parse(&["items=0-5"]).is_err());
+        assert!(Range::parse(&["bytes="]).is_err());
+        assert!(Range::parse(&["bytes=-"]).is_err());
+        assert!(Range::parse(&["bytes=5-4"]).is_err());
+        assert_eq!(IfRange::parse(&["W/\"a\""]).unwrap(), IfRange::Tag(EntityTag::weak("a")));
+        assert_eq!(IfRange::parse(&["Sun, 06 Nov 1994 08:49:37 GMT"]).unwrap(), IfRange::Date(from_seconds(784_111_777)));
+    }
+
+    #[test]
+    fn resolves_byte_ranges() {
+        assert_eq!(ByteRange::Bytes(0, Some(499)).resolve(10000), Some(0..500));
+        assert_eq!(ByteRange::Bytes(9500, None).resolve(10000), Some(9500..10000));
+        assert_eq!(ByteRange::Bytes(9500, Some(20000)).resolve(10000), Some(9500..10000));
+        assert_eq!(ByteRange::Suffix(500).resolve(10000), Some(9500..10000));
+        assert_eq!(ByteRange::Suffix(20000).resolve(10000), Some(0..10000));
+        assert_eq!(ByteRange::Bytes(10000, None).resolve(10000), None);
+        assert_eq!(ByteRange::Suffix(0).resolve(10000), None);
+        assert_eq!(ByteRange::Suffix(5).resolve(0), None);
+    }
+
+    #[test]
+    fn can_get_and_set_typed_headers() {
+        let mut headers = Headers::new();
+        headers.set_typed(&ContentLength(3)).set_typed(&ContentType(MediaType::new("text", "plain").parameter("charset", "utf-8")));
+        assert_eq!(headers.get("Content-Type"), Some("text/plain; charset=utf-8"));
+        assert_eq!(headers.get_typed::<ContentLength>().unwrap().unwrap(), ContentLength(3));
+        assert!(headers.get_typed::<ETag>().is_none());
+    }
+}
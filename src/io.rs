@@ -1,7 +1,7 @@
-use std::usize;
-use std::io::{Read, BufRead, Write, Result, Error, ErrorKind};
+use std::io::{Read, BufRead, Write, Result};
 use std::cmp::min;
-use std::fmt::{Debug, Display};
+use std::fmt::Debug;
+use crate::error::Error;
 
 pub trait ReadFrom {
     fn read_from<F>(&mut self, fun: F) -> Result<usize>
@@ -43,6 +43,14 @@ impl<B> Buffer<B> where B: AsMut<[u8]> {
         self.write_position += value;
     }
 
+    /// Moves unread data to the start to make room for writing
+    pub fn compact(&mut self) {
+        let (read, write) = (self.read_position, self.write_position);
+        self.value.as_mut().copy_within(read..write, 0);
+        self.read_position = 0;
+        self.write_position = write - read;
+    }
+
     pub fn fill<R>(&mut self, read: &mut R) -> Result<usize>
         where R: Read + Sized {
         self.write_into(|slice| read.read(slice))
@@ -52,7 +60,7 @@ impl<B> Buffer<B> where B: AsMut<[u8]> {
 impl<B> From<B> for Buffer<B> where B: AsRef<[u8]> {
     fn from(value: B) -> Self {
         Buffer {
-            value: value,
+            value,
             read_position: 0,
             write_position: 0,
         }
@@ -61,9 +69,7 @@ impl<B> From<B> for Buffer<B> where B: AsRef<[u8]> {
 
 impl Buffer<Vec<u8>> {
     pub fn with_capacity(capacity: usize) -> Buffer<Vec<u8>> {
-        let mut value = Vec::with_capacity(capacity);
-        unsafe { value.set_len(capacity) }
-        Buffer::from(value)
+        Buffer::from(vec![0; capacity])
     }
 }
 
@@ -112,22 +118,6 @@ impl<B> ReadFrom for Buffer<B> where B: AsRef<[u8]> {
     }
 }
 
-pub struct SimpleError;
-
-impl SimpleError {
-    pub fn debug<V>(value: V) -> Error where V: Debug {
-        SimpleError::error(format!("{:?}", value))
-    }
-
-    pub fn display<V>(value: V) -> Error where V: Display {
-        SimpleError::error(format!("{}", value))
-    }
-
-    pub fn error<V>(value: V) -> Error where V: AsRef<str> {
-        Error::new(ErrorKind::Other, value.as_ref())
-    }
-}
-
 #[allow(unused_variables)]
 pub fn unit(result: Result<usize>) -> Result<()> {
     result.map(|ignore| ())
@@ -136,7 +126,7 @@ pub fn unit(result: Result<usize>) -> Result<()> {
 pub fn consume(result: Result<usize>) -> Result<()> {
     match result {
         Ok(value) if value > 0 => Ok(()),
-        Ok(_) => Err(SimpleError::error("No data consumed")),
+        Ok(_) => Err(Error::Incomplete.into()),
         Err(e) => Err(e),
     }
 }
@@ -173,7 +163,7 @@ pub struct BufferedRead<T, B> {
 impl<T> BufferedRead<T, Vec<u8>> where T: Read + Sized {
     pub fn new(inner: T) -> BufferedRead<T, Vec<u8>> {
         BufferedRead {
-            inner: inner,
+            inner,
             buffer: Buffer::with_capacity(4096),
         }
     }
@@ -345,6 +335,17 @@ mod tests {
         assert_eq!(buffer.write_position, 0);
     }
 
+    #[test]
+    fn compact_moves_unread_data_to_the_start() {
+        let mut buffer = Buffer::with_capacity(4);
+        buffer.write_all(b"1234").unwrap();
+        buffer.increment_read(3);
+        assert_eq!(buffer.as_write().len(), 0);
+        buffer.compact();
+        assert_eq!(buffer.as_read(), &b"4"[..]);
+        assert_eq!(buffer.as_write().len(), 3);
+    }
+
     #[test]
     fn split_read_with_buffer() {
         let mut data = Fragmented::new(&b"1234567890"[..], 5);
@@ -9,6 +9,26 @@ extern crate reduce;
 #[allow(dead_code)] pub mod grammar;
 pub mod ast;
 pub mod api;
+pub mod method;
+pub mod status;
+pub mod uri;
 pub mod process;
 pub mod server;
+pub mod proxy;
+pub mod date;
+pub mod cookie;
+pub mod retry;
+pub mod router;
+pub mod form;
+pub mod multipart;
 pub mod io;
+pub mod error;
+pub mod framing;
+pub mod header;
+pub mod negotiation;
+pub mod mime;
+pub mod conditional;
+pub mod range;
+pub mod listing;
+pub mod file;
+pub mod sendfile;
@@ -0,0 +1,153 @@
+use std::fs::read_dir;
+use std::io::Result;
+use std::path::Path;
+use std::time::SystemTime;
+use crate::date::format_http_date;
+use crate::uri::{encode, Component};
+
+/// One file or directory in a listing, names are kept as they are on disk and escaped when rendered
+#[derive(PartialEq, Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub directory: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// Sorted by name, names that are not UTF-8 are skipped as they can not be linked to
+pub fn entries<P>(directory: P, hidden: bool) -> Result<Vec<Entry>> where P: AsRef<Path> {
+    let mut entries = vec!();
+    for entry in read_dir(directory)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        if !hidden && name.starts_with('.') {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        entries.push(Entry { name, directory: metadata.is_dir(), size: metadata.len(), modified: metadata.modified().ok() });
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+pub fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for chr in value.chars() {
+        match chr {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            chr => escaped.push(chr),
+        }
+    }
+    escaped
+}
+
+pub fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for chr in value.chars() {
+        match chr {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            chr if (chr as u32) < 0x20 || chr == '\u{2028}' || chr == '\u{2029}' => escaped.push_str(&format!("\\u{:04x}", chr as u32)),
+            chr => escaped.push(chr),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Relative to the directory and starting with `./` so a name like `javascript:x` can not become a scheme
+fn href(entry: &Entry) -> String {
+    format!("./{}{}", encode(&entry.name, Component::Segment), if entry.directory { "/" } else { "" })
+}
+
+fn display_name(entry: &Entry) -> String {
+    format!("{}{}", entry.name, if entry.directory { "/" } else { "" })
+}
+
+/// `path` is the decoded request path of the directory
+pub fn html(path: &str, entries: &[Entry]) -> String {
+    let title = escape_html(&format!("Index of {}", path));
+    let mut html = format!("<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{0}</title></head>\n<body>\n<h1>{0}</h1>\n<table>\n\
+        <tr><th>Name</th><th>Size</th><th>Last modified</th></tr>\n", title);
+    if path != "/" {
+        html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+    for entry in entries {
+        html.push_str(&format!("<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td></tr>\n",
+                               escape_html(&href(entry)),
+                               escape_html(&display_name(entry)),
+                               if entry.directory { String::new() } else { entry.size.to_string() },
+                               entry.modified.map(format_http_date).unwrap_or_default()));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+pub fn json(entries: &[Entry]) -> String {
+    let entries: Vec<String> = entries.iter().map(|entry| format!("{{\"name\":{},\"type\":\"{}\",\"size\":{},\"modified\":{}}}",
+        escape_json(&entry.name),
+        if entry.directory { "directory" } else { "file" },
+        entry.size,
+        entry.modified.map(|modified| escape_json(&format_http_date(modified))).unwrap_or_else(|| "null".to_string()))).collect();
+    format!("[{}]", entries.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date::from_seconds;
+    use crate::misc::TempDir;
+
+    fn entry(name: &str, directory: bool) -> Entry {
+        Entry { name: name.to_string(), directory, size: 5, modified: Some(from_seconds(784_111_777)) }
+    }
+
+    #[test]
+    fn escapes_names() {
+        assert_eq!(escape_html("<a href='x'>&\"</a>"), "&lt;a href=&#39;x&#39;&gt;&amp;&quot;&lt;/a&gt;");
+        assert_eq!(escape_json("a\"b\\c\nd\u{1}\u{2028}"), "\"a\\\"b\\\\c\\nd\\u0001\\u2028\"");
+        assert_eq!(href(&entry("javascript:alert(1)", false)), "./javascript:alert(1)");
+        assert_eq!(href(&entry("a b?#%", true)), "./a%20b%3F%23%25/");
+    }
+
+    #[test]
+    fn renders_html() {
+        let html = html("/docs/<x>/", &[entry("<img src=x onerror=alert(1)>", false), entry("sub", true)]);
+        assert!(html.contains("<title>Index of /docs/&lt;x&gt;/</title>"));
+        assert!(html.contains("<a href=\"../\">../</a>"));
+        assert!(html.contains("<a href=\"./%3Cimg%20src=x%20onerror=alert(1)%3E\">&lt;img src=x onerror=alert(1)&gt;</a></td><td>5</td><td>Sun, 06 Nov 1994 08:49:37 GMT</td>"));
+        assert!(html.contains("<a href=\"./sub/\">sub/</a></td><td></td>"));
+        assert!(!super::html("/", &[]).contains("../"));
+    }
+
+    #[test]
+    fn renders_json() {
+        assert_eq!(json(&[entry("a\"b", false), Entry { modified: None, ..entry("dir", true) }]),
+                   "[{\"name\":\"a\\\"b\",\"type\":\"file\",\"size\":5,\"modified\":\"Sun, 06 Nov 1994 08:49:37 GMT\"},\
+                   {\"name\":\"dir\",\"type\":\"directory\",\"size\":5,\"modified\":null}]");
+        assert_eq!(json(&[]), "[]");
+    }
+
+    #[test]
+    fn lists_directories() {
+        let base = TempDir::new("listing");
+        std::fs::create_dir_all(base.join("sub")).unwrap();
+        std::fs::write(base.join("b.txt"), "Hello").unwrap();
+        std::fs::write(base.join(".hidden"), "").unwrap();
+        let visible: Vec<_> = entries(&base, false).unwrap().into_iter().map(|entry| (entry.name, entry.directory)).collect();
+        let all = entries(&base, true).unwrap().len();
+        assert_eq!(visible, vec!(("b.txt".to_string(), false), ("sub".to_string(), true)));
+        assert_eq!(all, 3);
+    }
+}
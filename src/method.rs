@@ -0,0 +1,109 @@
+use std::fmt;
+
+/// Request method, names are case-sensitive so `get` is an extension method
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum Method<'a> {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+    Extension(&'a str),
+}
+
+impl<'a> Method<'a> {
+    pub fn as_str(&self) -> &'a str {
+        match *self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Connect => "CONNECT",
+            Method::Options => "OPTIONS",
+            Method::Trace => "TRACE",
+            Method::Patch => "PATCH",
+            Method::Extension(method) => method,
+        }
+    }
+
+    /// RFC 7231 section 4.2.1, the request is essentially read-only
+    pub fn is_safe(&self) -> bool {
+        matches!(*self, Method::Get | Method::Head | Method::Options | Method::Trace)
+    }
+
+    /// RFC 7231 section 4.2.2, repeating the request has the same effect as sending it once
+    pub fn is_idempotent(&self) -> bool {
+        self.is_safe() || matches!(*self, Method::Put | Method::Delete)
+    }
+
+    /// RFC 7231 section 4.2.3, POST is left out as it is only cacheable with explicit freshness
+    pub fn is_cacheable(&self) -> bool {
+        matches!(*self, Method::Get | Method::Head)
+    }
+}
+
+impl<'a> From<&'a str> for Method<'a> {
+    fn from(method: &'a str) -> Method<'a> {
+        match method {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "CONNECT" => Method::Connect,
+            "OPTIONS" => Method::Options,
+            "TRACE" => Method::Trace,
+            "PATCH" => Method::Patch,
+            method => Method::Extension(method),
+        }
+    }
+}
+
+impl<'a> fmt::Display for Method<'a> {
+    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
+        format.write_str(self.as_str())
+    }
+}
+
+impl<'a> PartialEq<str> for Method<'a> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<'a, 'b> PartialEq<&'b str> for Method<'a> {
+    fn eq(&self, other: &&'b str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_standard_and_extension_methods() {
+        assert_eq!(Method::from("GET"), Method::Get);
+        assert_eq!(Method::from("OPTIONS"), Method::Options);
+        assert_eq!(Method::from("PROPFIND"), Method::Extension("PROPFIND"));
+        assert_eq!(Method::from("get"), Method::Extension("get"));
+        assert_eq!(Method::Patch.to_string(), "PATCH");
+        assert_eq!(Method::Extension("PROPFIND").as_str(), "PROPFIND");
+        assert_eq!(Method::Delete, "DELETE");
+    }
+
+    #[test]
+    fn has_properties() {
+        assert!(Method::Get.is_safe() && Method::Get.is_idempotent() && Method::Get.is_cacheable());
+        assert!(!Method::Put.is_safe() && Method::Put.is_idempotent() && !Method::Put.is_cacheable());
+        assert!(!Method::Post.is_safe() && !Method::Post.is_idempotent() && !Method::Post.is_cacheable());
+        assert!(Method::Trace.is_safe() && !Method::Trace.is_cacheable());
+        assert!(!Method::Connect.is_idempotent() && !Method::Patch.is_idempotent());
+        assert!(!Method::Extension("LOCK").is_idempotent());
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Result};
+use std::path::Path;
+
+const BUILT_IN: &[(&str, &str)] = &[
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("mjs", "text/javascript"),
+    ("txt", "text/plain"),
+    ("csv", "text/csv"),
+    ("md", "text/markdown"),
+    ("xml", "application/xml"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("webmanifest", "application/manifest+json"),
+    ("wasm", "application/wasm"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("svg", "image/svg+xml"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("bmp", "image/bmp"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+];
+
+/// Maps file extensions to media types, extensions compare case-insensitively
+#[derive(Debug, Clone)]
+pub struct MimeTypes {
+    types: HashMap<String, String>,
+}
+
+impl MimeTypes {
+    /// No mappings at all, see `Default` for the built-in table
+    pub fn new() -> MimeTypes {
+        MimeTypes { types: HashMap::new() }
+    }
+
+    pub fn insert(mut self, extension: &str, media_type: &str) -> MimeTypes {
+        self.types.insert(extension.to_ascii_lowercase(), media_type.to_string());
+        self
+    }
+
+    /// Reads the `mime.types` format, a media type followed by its extensions with `#` comments
+    pub fn parse<R>(mut self, reader: R) -> Result<MimeTypes> where R: BufRead {
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.split('#').next().unwrap_or("");
+            let mut fields = line.split_whitespace();
+            if let Some(media_type) = fields.next() {
+                for extension in fields {
+                    self = self.insert(extension, media_type);
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn load<P>(self, path: P) -> Result<MimeTypes> where P: AsRef<Path> {
+        self.parse(BufReader::new(File::open(path)?))
+    }
+
+    pub fn get(&self, extension: &str) -> Option<&str> {
+        self.types.get(&extension.to_ascii_lowercase()).map(String::as_str)
+    }
+
+    pub fn lookup<P>(&self, path: P) -> Option<&str> where P: AsRef<Path> {
+        path.as_ref().extension().and_then(|extension| extension.to_str()).and_then(|extension| self.get(extension))
+    }
+}
+
+impl Default for MimeTypes {
+    fn default() -> Self {
+        BUILT_IN.iter().fold(MimeTypes::new(), |types, (extension, media_type)| types.insert(extension, media_type))
+    }
+}
+
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b\x08", "application/gzip"),
+    (b"\0asm", "application/wasm"),
+    (b"wOFF", "font/woff"),
+    (b"wOF2", "font/woff2"),
+    (b"OggS\0", "audio/ogg"),
+    (b"ID3", "audio/mpeg"),
+    (b"\x1a\x45\xdf\xa3", "video/webm"),
+    (b"\xef\xbb\xbf", "text/plain"),
+];
+
+const HTML: &[&str] = &["<!doctype html", "<html", "<head", "<body", "<script", "<!--"];
+
+/// How many leading bytes `sniff` looks at
+pub const SNIFF_LENGTH: usize = 512;
+
+/// Guesses a media type from the leading bytes of a file, a cut down
+/// version of the WHATWG MIME Sniffing Standard that never guesses a scriptable type from binary
+pub fn sniff(bytes: &[u8]) -> &'static str {
+    let bytes = &bytes[..bytes.len().min(SNIFF_LENGTH)];
+    if let Some((_, media_type)) = SIGNATURES.iter().find(|(signature, _)| bytes.starts_with(signature)) {
+        return media_type;
+    }
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+    if bytes.len() >= 12 && &bytes[4..8] == b"ftyp" {
+        return "video/mp4";
+    }
+    if bytes.iter().any(|byte| matches!(byte, 0..=8 | 0x0b | 0x0e..=0x1a | 0x1c..=0x1f)) {
+        return "application/octet-stream";
+    }
+    let start = bytes.iter().position(|byte| !byte.is_ascii_whitespace()).unwrap_or(bytes.len());
+    let text = &bytes[start..];
+    if HTML.iter().any(|tag| text.len() >= tag.len() && text[..tag.len()].eq_ignore_ascii_case(tag.as_bytes())) {
+        return "text/html";
+    }
+    if text.starts_with(b"<?xml") {
+        return "application/xml";
+    }
+    "text/plain"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_extensions() {
+        let types = MimeTypes::default();
+        assert_eq!(types.lookup("site/index.HTML"), Some("text/html"));
+        assert_eq!(types.lookup("logo.svg"), Some("image/svg+xml"));
+        assert_eq!(types.lookup("README"), None);
+        assert_eq!(types.lookup("archive.unknown"), None);
+        assert_eq!(types.insert("html", "application/xhtml+xml").lookup("a.html"), Some("application/xhtml+xml"));
+    }
+
+    #[test]
+    fn parses_mime_types_files() {
+        let types = MimeTypes::new().parse(&b"# comment\ntext/x-rust\trs\n\napplication/toml toml tml # TOML\n"[..]).unwrap();
+        assert_eq!(types.get("rs"), Some("text/x-rust"));
+        assert_eq!(types.get("TML"), Some("application/toml"));
+        assert_eq!(types.get("html"), None);
+    }
+
+    #[test]
+    fn sniffs_magic_bytes() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), "image/png");
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(sniff(b"\0\0\0\x18ftypmp42"), "video/mp4");
+        assert_eq!(sniff(b"  <!DOCTYPE html><title>"), "text/html");
+        assert_eq!(sniff(b"<?xml version=\"1.0\"?>"), "application/xml");
+        assert_eq!(sniff(b"Hello\r\n\tWorld"), "text/plain");
+        assert_eq!(sniff(b""), "text/plain");
+        assert_eq!(sniff(b"<html\0"), "application/octet-stream");
+    }
+}
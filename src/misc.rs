@@ -1,6 +1,9 @@
 use std::{slice, num, string, fmt, str};
 use std::error::Error;
 use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::SystemTime;
 
 #[derive(PartialEq, Debug)]
 pub enum SliceError {
@@ -19,7 +22,7 @@ impl Error for SliceError {
     }
 }
 
-impl<'a> fmt::Display for SliceError {
+impl fmt::Display for SliceError {
     fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
         format.write_str(match *self {
             SliceError::NotAdjacent => "Can not join slices that are not next to each other",
@@ -53,7 +56,7 @@ pub fn reduce_slice<'a>(slice1: &'a [u8], slice2: &'a [u8]) -> Result<&'a [u8],
 
 pub fn is_adjacent<'a>(slice1: &'a [u8], slice2: &'a [u8]) -> bool {
     unsafe {
-        slice2.as_ptr() == slice1.as_ptr().offset(slice1.len() as isize)
+        slice2.as_ptr() == slice1.as_ptr().add(slice1.len())
     }
 }
 
@@ -61,13 +64,11 @@ pub fn join_vec(vec: Vec<&[u8]>) -> Result<&[u8], SliceError> {
     if vec.is_empty() {
         return Ok(Default::default());
     }
-    vec.into_iter().fold(Ok(Default::default()), |a, slice2| {
-        if let Ok(slice1) = a { return join_slice(slice1, slice2) }
-        a
-    })
+    vec.into_iter().try_fold(Default::default(), join_slice)
 }
 
 
+#[allow(clippy::manual_try_fold)]
 pub fn reduce_vec(vec: Vec<&[u8]>) -> Result<&[u8], Vec<&[u8]>> {
     if vec.is_empty() {
         return Ok(Default::default());
@@ -113,7 +114,7 @@ pub fn to_string(vec: Vec<&[u8]>) -> Result<String, string::FromUtf8Error> {
     String::from_utf8(vec.concat())
 }
 
-pub fn to_cow_str(vec: Vec<&[u8]>) -> Result<Cow<str>, str::Utf8Error> {
+pub fn to_cow_str(vec: Vec<&[u8]>) -> Result<Cow<'_, str>, str::Utf8Error> {
     match reduce_vec(vec) {
         Ok(slice) => str::from_utf8(slice).map(Cow::from),
         Err(vec) => match String::from_utf8(vec.concat()) {
@@ -123,6 +124,66 @@ pub fn to_cow_str(vec: Vec<&[u8]>) -> Result<Cow<str>, str::Utf8Error> {
     }
 }
 
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut result = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for group in bytes.chunks(3) {
+        let b = [group[0], *group.get(1).unwrap_or(&0), *group.get(2).unwrap_or(&0)];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= group.len() {
+                result.push(ALPHABET[(n >> (18 - i * 6)) as usize & 0x3F] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+/// Not cryptographically secure, good enough for jitter and multipart boundaries
+pub fn random() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos());
+    hasher.finish()
+}
+
+/// A directory for tests under the system temp directory, removed again when dropped so failing tests clean up too
+#[cfg(test)]
+pub struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path.canonicalize().unwrap())
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDir {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<std::path::Path> for TempDir {
+    fn as_ref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -174,4 +235,13 @@ mod tests {
         }
 
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn base64() {
+        assert_eq!(super::base64(b""), "");
+        assert_eq!(super::base64(b"f"), "Zg==");
+        assert_eq!(super::base64(b"fo"), "Zm8=");
+        assert_eq!(super::base64(b"foo"), "Zm9v");
+        assert_eq!(super::base64(b"Aladdin:open sesame"), "QWxhZGRpbjpvcGVuIHNlc2FtZQ==");
+    }
+}
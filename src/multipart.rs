@@ -0,0 +1,419 @@
+use std::io::{Read, Write, Seek, SeekFrom, Result, Cursor, empty};
+use std::borrow::Cow;
+use std::cmp::min;
+use std::mem;
+use nom::sequence::terminated;
+use crate::api::Request;
+use crate::ast::{Header, Headers, MessageBody};
+use crate::grammar::{headers, crlf, parameters};
+use crate::io::{Buffer, Streamer};
+use crate::error::{Error, Limit};
+use crate::misc::random;
+
+/// The `boundary` parameter of a `multipart/*` media type
+pub fn boundary(content_type: &str) -> Option<Cow<'_, str>> {
+    let index = content_type.find(';')?;
+    if !content_type[..index].trim().to_ascii_lowercase().starts_with("multipart/") {
+        return None;
+    }
+    parameter(&content_type[index..], "boundary")
+}
+
+fn parameter<'a>(value: &'a str, name: &str) -> Option<Cow<'a, str>> {
+    let (_, parameters) = parameters(value.as_bytes()).ok()?;
+    parameters.into_iter().
+        find(|(key, _)| key.eq_ignore_ascii_case(name)).
+        map(|(_, value)| value)
+}
+
+/// Names are escaped like browsers do for form-data, a quote or line break could otherwise end the header early
+fn escape(value: &str) -> String {
+    value.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+#[derive(PartialEq, Debug)]
+enum MultipartState {
+    Body,
+    Delimiter,
+    Finished,
+}
+
+/// Streams the parts of a multipart body, only the part headers and a window of the content are buffered
+pub struct Multipart<R> where R: Read {
+    read: R,
+    buffer: Buffer<Vec<u8>>,
+    delimiter: Vec<u8>,
+    state: MultipartState,
+}
+
+impl<R> Multipart<R> where R: Read {
+    pub fn new(read: R, boundary: &str) -> Multipart<R> {
+        let mut buffer = Buffer::with_capacity(8192);
+        // Treats the preamble as a part so the first delimiter needs no special case
+        buffer.write_all(b"\r\n").expect("buffer should have capacity");
+        Multipart { read, buffer, delimiter: format!("\r\n--{}", boundary).into_bytes(), state: MultipartState::Body }
+    }
+
+    fn fill(&mut self) -> Result<usize> {
+        if self.buffer.as_write().is_empty() {
+            self.buffer.compact();
+            if self.buffer.as_write().is_empty() {
+                return Err(Error::LimitExceeded(Limit::PartHead).into());
+            }
+        }
+        self.buffer.fill(&mut self.read)
+    }
+
+    fn ensure(&mut self, count: usize) -> Result<()> {
+        while self.buffer.as_read().len() < count {
+            if self.fill()? == 0 {
+                return Err(Error::Incomplete.into());
+            }
+        }
+        Ok(())
+    }
+
+    fn read_body(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.state != MultipartState::Body {
+            return Ok(0);
+        }
+        loop {
+            let data = self.buffer.as_read();
+            let found = data.windows(self.delimiter.len()).position(|window| window == self.delimiter.as_slice());
+            let available = found.unwrap_or_else(|| {
+                let partial = (1..min(self.delimiter.len(), data.len() + 1)).rev().
+                    find(|&length| data.ends_with(&self.delimiter[..length])).
+                    unwrap_or(0);
+                data.len() - partial
+            });
+            if available > 0 {
+                let size = min(available, buf.len());
+                buf[..size].copy_from_slice(&data[..size]);
+                self.buffer.increment_read(size);
+                return Ok(size);
+            }
+            if found.is_some() {
+                self.state = MultipartState::Delimiter;
+                return Ok(0);
+            }
+            self.ensure(data.len() + 1)?;
+        }
+    }
+
+    fn advance(&mut self) -> Result<Option<Headers<'static>>> {
+        let mut skip = [0; 1024];
+        while self.read_body(&mut skip)? > 0 {}
+        self.buffer.increment_read(self.delimiter.len());
+
+        self.ensure(2)?;
+        if self.buffer.as_read().starts_with(b"--") {
+            self.state = MultipartState::Finished;
+            return Ok(None);
+        }
+        loop {
+            self.ensure(1)?;
+            match self.buffer.as_read()[0] {
+                b' ' | b'\t' => self.buffer.increment_read(1),
+                _ => break,
+            }
+        }
+        self.ensure(2)?;
+        if !self.buffer.as_read().starts_with(b"\r\n") {
+            return Err(Error::protocol("Invalid multipart delimiter").into());
+        }
+        self.buffer.increment_read(2);
+
+        loop {
+            let data = self.buffer.as_read();
+            let parsed = terminated(headers, crlf)(data).ok().map(|(remainder, headers)| {
+                (data.len() - remainder.len(), Headers(headers.0.into_iter().
+                    map(|header| Header::new(header.name.into_owned(), header.value.into_owned())).
+                    collect()))
+            });
+            match parsed {
+                Some((consumed, headers)) => {
+                    self.buffer.increment_read(consumed);
+                    self.state = MultipartState::Body;
+                    return Ok(Some(headers));
+                }
+                None => {
+                    let length = data.len();
+                    self.ensure(length + 1)?;
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Multipart<Box<dyn Read + 'a>> {
+    /// Takes the body of a `multipart/*` request
+    pub fn from_request(request: &mut Request<'a>) -> Result<Multipart<Box<dyn Read + 'a>>> {
+        let boundary = request.get_header("Content-Type").
+            and_then(boundary).
+            map(Cow::into_owned).
+            ok_or_else(|| Error::protocol("Request is not multipart"))?;
+        let read: Box<dyn Read + 'a> = match mem::replace(&mut request.entity, MessageBody::None) {
+            MessageBody::Slice(slice) => Box::new(slice),
+            MessageBody::Owned(ref mut vec) => Box::new(Cursor::new(mem::take(vec))),
+            MessageBody::Reader(ref mut reader) => mem::replace(reader, Box::new(empty())),
+            MessageBody::File(ref mut file, ref range) => {
+                file.seek(SeekFrom::Start(range.start))?;
+                Box::new(file.try_clone()?.take(range.end - range.start))
+            }
+            MessageBody::None => Box::new(empty()),
+        };
+        Ok(Multipart::new(read, &boundary))
+    }
+}
+
+impl<'a, R> Streamer<'a> for Multipart<R> where R: Read + 'a {
+    type Item = Result<Part<'a, R>>;
+
+    fn next(&'a mut self) -> Option<Self::Item> {
+        if self.state == MultipartState::Finished {
+            return None;
+        }
+        match self.advance() {
+            Ok(Some(headers)) => Some(Ok(Part { headers, multipart: self })),
+            Ok(None) => None,
+            Err(e) => {
+                self.state = MultipartState::Finished;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// A single part, reading it streams the content up to the next boundary
+pub struct Part<'a, R> where R: Read {
+    pub headers: Headers<'static>,
+    multipart: &'a mut Multipart<R>,
+}
+
+impl<'a, R> Part<'a, R> where R: Read {
+    fn disposition(&self, name: &str) -> Option<Cow<'_, str>> {
+        let value = self.headers.get("Content-Disposition")?;
+        parameter(&value[value.find(';')?..], name)
+    }
+
+    /// The form field name from `Content-Disposition`
+    pub fn name(&self) -> Option<Cow<'_, str>> {
+        self.disposition("name")
+    }
+
+    pub fn filename(&self) -> Option<Cow<'_, str>> {
+        self.disposition("filename")
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers.get("Content-Type")
+    }
+}
+
+impl<'a, R> Read for Part<'a, R> where R: Read {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.multipart.read_body(buf)
+    }
+}
+
+/// Builds a `multipart/form-data` body with a known length so it can be sent without chunking
+pub struct FormData<'a> {
+    boundary: String,
+    parts: Vec<(String, Box<dyn Read + 'a>, u64)>,
+}
+
+impl<'a> Default for FormData<'a> {
+    fn default() -> Self {
+        FormData::new()
+    }
+}
+
+impl<'a> FormData<'a> {
+    pub fn new() -> FormData<'a> {
+        FormData { boundary: format!("{:016x}{:016x}", random(), random()), parts: vec!() }
+    }
+
+    pub fn boundary<B>(mut self, boundary: B) -> FormData<'a> where B: Into<String> {
+        self.boundary = boundary.into();
+        self
+    }
+
+    pub fn field(self, name: &str, value: &str) -> FormData<'a> {
+        let headers = Headers(vec!(Header::new("Content-Disposition", format!("form-data; name=\"{}\"", escape(name)))));
+        let value = value.as_bytes().to_vec();
+        let length = value.len() as u64;
+        self.part(headers, Cursor::new(value), length)
+    }
+
+    pub fn file<C>(self, name: &str, filename: &str, content_type: &str, content: C, length: u64) -> FormData<'a>
+        where C: Read + 'a {
+        let headers = Headers(vec!(
+            Header::new("Content-Disposition", format!("form-data; name=\"{}\"; filename=\"{}\"", escape(name), escape(filename))),
+            Header::new("Content-Type", content_type.to_string())));
+        self.part(headers, content, length)
+    }
+
+    /// `length` must be the exact number of bytes `content` will produce
+    pub fn part<C>(mut self, headers: Headers, content: C, length: u64) -> FormData<'a>
+        where C: Read + 'a {
+        let head = format!("--{}\r\n{}\r\n", self.boundary, headers);
+        self.parts.push((head, Box::new(content), length));
+        self
+    }
+
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    fn close(&self) -> String {
+        format!("--{}--\r\n", self.boundary)
+    }
+
+    pub fn content_length(&self) -> u64 {
+        self.parts.iter().
+            map(|(head, _, length)| head.len() as u64 + length + 2).
+            sum::<u64>() + self.close().len() as u64
+    }
+
+    pub fn into_reader(self) -> Box<dyn Read + 'a> {
+        let close = self.close();
+        let body = self.parts.into_iter().fold(Box::new(empty()) as Box<dyn Read + 'a>, |body, (head, content, _)| {
+            Box::new(body.chain(Cursor::new(head)).chain(content).chain(&b"\r\n"[..]))
+        });
+        Box::new(body.chain(Cursor::new(close)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out one byte at a time to exercise every buffer boundary
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    type Parts = Vec<(Option<String>, Option<String>, Vec<u8>)>;
+
+    fn parts<R>(mut multipart: Multipart<R>) -> Result<Parts> where R: Read {
+        let mut result = vec!();
+        while let Some(part) = multipart.next() {
+            let mut part = part?;
+            let mut content = vec!();
+            part.read_to_end(&mut content)?;
+            result.push((part.name().map(Cow::into_owned), part.filename().map(Cow::into_owned), content));
+        }
+        Ok(result)
+    }
+
+    const BODY: &[u8] = b"preamble\r\n--AaB03x\r\n\
+        Content-Disposition: form-data; name=\"submit-name\"\r\n\r\n\
+        Larry\r\n\
+        --AaB03x  \r\n\
+        Content-Disposition: form-data; name=\"files\"; filename=\"file1.txt\"\r\n\
+        Content-Type: text/plain\r\n\r\n\
+        ... contents of file1.txt ...\r\n--AaB03\r\n\
+        --AaB03x--\r\nepilogue";
+
+    #[test]
+    fn parses_boundary() {
+        assert_eq!(boundary("multipart/form-data; boundary=AaB03x"), Some(Cow::from("AaB03x")));
+        assert_eq!(boundary("Multipart/Mixed; charset=utf-8; Boundary=\"gc0p4Jq0M2Yt08j34c0p\""), Some(Cow::from("gc0p4Jq0M2Yt08j34c0p")));
+        assert_eq!(boundary("text/plain; boundary=AaB03x"), None);
+        assert_eq!(boundary("multipart/form-data"), None);
+    }
+
+    #[test]
+    fn streams_parts() {
+        assert_eq!(parts(Multipart::new(BODY, "AaB03x")).unwrap(), vec!(
+            (Some("submit-name".to_string()), None, b"Larry".to_vec()),
+            (Some("files".to_string()), Some("file1.txt".to_string()), b"... contents of file1.txt ...\r\n--AaB03".to_vec())));
+    }
+
+    #[test]
+    fn streams_parts_from_fragmented_input() {
+        assert_eq!(parts(Multipart::new(Trickle(BODY), "AaB03x")).unwrap(), parts(Multipart::new(BODY, "AaB03x")).unwrap());
+    }
+
+    #[test]
+    fn skips_unread_content_and_exposes_headers() {
+        let mut multipart = Multipart::new(Trickle(BODY), "AaB03x");
+        multipart.next().unwrap().unwrap();
+        let part = multipart.next().unwrap().unwrap();
+        assert_eq!(part.content_type(), Some("text/plain"));
+        assert!(multipart.next().is_none());
+        assert!(multipart.next().is_none());
+    }
+
+    #[test]
+    fn errors_when_closing_boundary_is_missing() {
+        let mut multipart = Multipart::new(&b"--b\r\nContent-Type: text/plain\r\n\r\nunfinished"[..], "b");
+        let mut part = multipart.next().unwrap().unwrap();
+        assert_eq!(part.read_to_end(&mut vec!()).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn does_not_buffer_whole_content() {
+        let content = vec![b'x'; 100_000];
+        let form = FormData::new().file("upload", "big.bin", "application/octet-stream", content.as_slice(), content.len() as u64);
+        let boundary = form.boundary.clone();
+        let mut body = vec!();
+        form.into_reader().read_to_end(&mut body).unwrap();
+
+        let mut multipart = Multipart::new(body.as_slice(), &boundary);
+        let mut part = multipart.next().unwrap().unwrap();
+        let mut chunk = [0; 100_000];
+        let read = part.read(&mut chunk).unwrap();
+        assert!(read > 0 && read <= 8192);
+        let mut rest = vec!();
+        part.read_to_end(&mut rest).unwrap();
+        assert_eq!(read + rest.len(), content.len());
+    }
+
+    #[test]
+    fn writes_form_data_that_can_be_read_back() {
+        let form = FormData::new().boundary("XyZ").
+            field("title", "Holiday \"snaps\"").
+            file("photo", "beach.jpg", "image/jpeg", &b"\xFF\xD8binary\r\n--XyY"[..], 15);
+        assert_eq!(form.content_type(), "multipart/form-data; boundary=XyZ");
+        let length = form.content_length();
+        let mut body = vec!();
+        form.into_reader().read_to_end(&mut body).unwrap();
+        assert_eq!(body.len() as u64, length);
+        assert_eq!(parts(Multipart::new(body.as_slice(), "XyZ")).unwrap(), vec!(
+            (Some("title".to_string()), None, b"Holiday \"snaps\"".to_vec()),
+            (Some("photo".to_string()), Some("beach.jpg".to_string()), b"\xFF\xD8binary\r\n--XyY".to_vec())));
+    }
+
+    #[test]
+    fn escapes_quotes_and_line_breaks_in_names() {
+        let form = FormData::new().boundary("XyZ").
+            file("a\"b", "x.txt\"\r\nContent-Type: text/html", "text/plain", &b"x"[..], 1);
+        let mut body = vec!();
+        form.into_reader().read_to_end(&mut body).unwrap();
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.contains("Content-Disposition: form-data; name=\"a%22b\"; filename=\"x.txt%22%0D%0AContent-Type: text/html\"\r\n"), "{}", body);
+        assert_eq!(body.matches("Content-Type").count(), 2);
+    }
+
+    #[test]
+    fn can_read_from_request() {
+        let form = FormData::new().field("a", "1");
+        let mut request = Request::post("/upload").multipart(form);
+        let mut multipart = Multipart::from_request(&mut request).unwrap();
+        let mut part = multipart.next().unwrap().unwrap();
+        let mut value = String::new();
+        part.read_to_string(&mut value).unwrap();
+        assert_eq!((part.name(), value.as_str()), (Some(Cow::from("a")), "1"));
+        assert!(Multipart::from_request(&mut Request::post("/")).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use crate::api::{Request, Response};
+use crate::ast::{Headers, MessageBody};
+use crate::header::{Accept, AcceptCharset, AcceptEncoding, AcceptLanguage, MediaType, Quality, TypedHeader};
+
+/// RFC 7231 section 5.3, a missing or invalid field accepts anything
+fn field<'a, H>(headers: &'a Headers<'a>) -> Option<H> where H: TypedHeader<'a> {
+    headers.get_typed::<H>().and_then(|field| field.ok())
+}
+
+/// The first of the best qualities wins so `available` is in order of preference
+fn choose<T, F>(available: &[T], quality: F) -> Option<&T> where F: Fn(&T) -> u16 {
+    available.iter().
+        map(|value| (quality(value), value)).
+        filter(|(quality, _)| *quality > 0).
+        fold(None, |best: Option<(u16, &T)>, (quality, value)| match best {
+            Some((best_quality, _)) if best_quality >= quality => best,
+            _ => Some((quality, value)),
+        }).
+        map(|(_, value)| value)
+}
+
+fn specificity(range: &MediaType) -> (bool, bool, usize) {
+    (range.main_type != "*", range.subtype != "*", range.parameters.len())
+}
+
+/// The weight of the most specific range that includes `media_type`, RFC 7231 section 5.3.2
+pub fn media_type_quality(accept: &[Quality<MediaType>], media_type: &MediaType) -> u16 {
+    accept.iter().
+        filter(|range| range.value.includes(media_type)).
+        max_by_key(|range| specificity(&range.value)).
+        map_or(0, |range| range.quality)
+}
+
+/// Tokens compare case-insensitively and `*` matches anything not listed
+pub fn token_quality(accept: &[Quality<&str>], token: &str) -> u16 {
+    accept.iter().find(|value| value.value.eq_ignore_ascii_case(token)).
+        or_else(|| accept.iter().find(|value| value.value == "*")).
+        map_or(0, |value| value.quality)
+}
+
+/// RFC 7231 section 5.3.4, identity is acceptable unless it is excluded
+pub fn encoding_quality(accept: &[Quality<&str>], coding: &str) -> u16 {
+    let listed = accept.iter().any(|value| value.value.eq_ignore_ascii_case(coding) || value.value == "*");
+    if !listed && coding.eq_ignore_ascii_case("identity") {
+        return 1000;
+    }
+    token_quality(accept, coding)
+}
+
+/// Basic filtering, RFC 4647 section 3.3.1, the longest matching range wins
+pub fn language_quality(accept: &[Quality<&str>], tag: &str) -> u16 {
+    accept.iter().
+        filter(|range| range.value == "*" || tag.eq_ignore_ascii_case(range.value) ||
+            (tag.len() > range.value.len() && tag.as_bytes()[range.value.len()] == b'-' &&
+                tag[..range.value.len()].eq_ignore_ascii_case(range.value))).
+        max_by_key(|range| if range.value == "*" { 0 } else { range.value.len() }).
+        map_or(0, |range| range.quality)
+}
+
+impl<'a> Request<'a> {
+    /// Picks what to send from `available`, `None` means the response should be `406 Not Acceptable`
+    pub fn negotiate_media_type<'b, 'm>(&self, available: &'b [MediaType<'m>]) -> Option<&'b MediaType<'m>> {
+        match field::<Accept>(&self.headers) {
+            Some(Accept(accept)) => choose(available, |media_type| media_type_quality(&accept, media_type)),
+            None => available.first(),
+        }
+    }
+
+    pub fn negotiate_charset<'b>(&self, available: &[&'b str]) -> Option<&'b str> {
+        match field::<AcceptCharset>(&self.headers) {
+            Some(AcceptCharset(accept)) => choose(available, |charset| token_quality(&accept, charset)),
+            None => available.first(),
+        }.copied()
+    }
+
+    pub fn negotiate_encoding<'b>(&self, available: &[&'b str]) -> Option<&'b str> {
+        match field::<AcceptEncoding>(&self.headers) {
+            Some(AcceptEncoding(accept)) => choose(available, |coding| encoding_quality(&accept, coding)),
+            None => available.first(),
+        }.copied()
+    }
+
+    pub fn negotiate_language<'b>(&self, available: &[&'b str]) -> Option<&'b str> {
+        match field::<AcceptLanguage>(&self.headers) {
+            Some(AcceptLanguage(accept)) => choose(available, |tag| language_quality(&accept, tag)),
+            None => available.first(),
+        }.copied()
+    }
+}
+
+/// `406 Not Acceptable` listing what could have been sent
+pub fn not_acceptable<'a>(available: &[MediaType]) -> Response<'a> {
+    let mut body = String::from("Available media types:\n");
+    for media_type in available {
+        body.push_str(&media_type.to_string());
+        body.push('\n');
+    }
+    Response::not_acceptable().
+        content_type("text/plain").
+        entity(MessageBody::Owned(body.into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(name: &'static str, value: &'static str) -> Request<'static> {
+        Request::get("/").header(name, value)
+    }
+
+    #[test]
+    fn ranks_media_ranges_by_specificity() {
+        let Accept(accept) = Accept::parse(&["text/*;q=0.3, text/html;q=0.7, text/html;level=1, text/html;level=2;q=0.4, */*;q=0.5"]).unwrap();
+        for (media_type, quality) in [("text/html;level=1", 1000), ("text/html", 700), ("text/plain", 300),
+                                      ("image/jpeg", 500), ("text/html;level=2", 400), ("text/html;level=3", 700)] {
+            assert_eq!(media_type_quality(&accept, &MediaType::parse(media_type).unwrap()), quality, "{}", media_type);
+        }
+    }
+
+    #[test]
+    fn negotiates_media_type() {
+        let available = [MediaType::new("application", "json"), MediaType::new("text", "html")];
+        assert_eq!(request("Accept", "text/html, application/json;q=0.9").negotiate_media_type(&available), Some(&available[1]));
+        assert_eq!(request("Accept", "*/*").negotiate_media_type(&available), Some(&available[0]));
+        assert_eq!(request("Accept", "image/*").negotiate_media_type(&available), None);
+        assert_eq!(request("Accept", "text/html;q=0, */*").negotiate_media_type(&available[1..]), None);
+        assert_eq!(Request::get("/").negotiate_media_type(&available), Some(&available[0]));
+        assert_eq!(request("Accept", "text/html;q=5").negotiate_media_type(&available), Some(&available[0]));
+    }
+
+    #[test]
+    fn negotiates_charset_and_language() {
+        assert_eq!(request("Accept-Charset", "iso-8859-5, UTF-8;q=0.8").negotiate_charset(&["utf-8", "iso-8859-5"]), Some("iso-8859-5"));
+        assert_eq!(request("Accept-Charset", "iso-8859-5").negotiate_charset(&["utf-8"]), None);
+        assert_eq!(request("Accept-Language", "da, en-gb;q=0.8, en;q=0.7").negotiate_language(&["en-US", "en-GB"]), Some("en-GB"));
+        assert_eq!(request("Accept-Language", "en;q=0.5, *;q=0.1").negotiate_language(&["fr", "en-US"]), Some("en-US"));
+        assert_eq!(request("Accept-Language", "en").negotiate_language(&["eng"]), None);
+    }
+
+    #[test]
+    fn negotiates_encoding_with_identity_by_default() {
+        assert_eq!(request("Accept-Encoding", "gzip").negotiate_encoding(&["br", "gzip", "identity"]), Some("gzip"));
+        assert_eq!(request("Accept-Encoding", "br;q=0.5").negotiate_encoding(&["br", "identity"]), Some("identity"));
+        assert_eq!(request("Accept-Encoding", "*;q=0").negotiate_encoding(&["identity"]), None);
+        assert_eq!(request("Accept-Encoding", "").negotiate_encoding(&["gzip", "identity"]), Some("identity"));
+    }
+
+    #[test]
+    fn not_acceptable_lists_available_media_types() {
+        let response = not_acceptable(&[MediaType::new("text", "html").parameter("charset", "utf-8")]);
+        assert_eq!(response.code, 406);
+        assert_eq!(response.to_string(), "HTTP/1.1 406 Not Acceptable\r\nContent-Type: text/plain\r\nContent-Length: 48\r\n\r\nAvailable media types:\ntext/html; charset=utf-8\n");
+    }
+}
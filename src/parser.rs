@@ -1,9 +1,9 @@
 extern crate nom;
 
 use std::io::{Result};
-use std::fmt;
 use nom::IResult;
-use crate::io::SimpleError;
+use crate::error::{Error};
+use crate::grammar::{request_line, status_line};
 
 #[macro_export] macro_rules! char_predicate {
     ($i:expr, $c: expr) => {
@@ -21,19 +21,92 @@ use crate::io::SimpleError;
     };
 }
 
-pub fn result<I, O>(result: IResult<I, O>) -> Result<(O, I)> where I: fmt::Debug {
+/// Converts a nom result, errors record `rule` and how far into `input` the parser got
+pub fn result<'a, O>(rule: &str, input: &'a [u8], result: IResult<&'a [u8], O>) -> Result<(O, &'a [u8])> {
     match result {
         Ok((remainder, output)) => {
             Ok((output, remainder))
         },
-        Err(nom::Err::Incomplete(needed)) => {
-            Err(SimpleError::debug(needed))
+        Err(nom::Err::Incomplete(_)) => {
+            Err(Error::Incomplete.into())
         },
-        Err(nom::Err::Error(err)) => {
-            Err(SimpleError::debug(err))
+        Err(nom::Err::Error((remainder, _))) | Err(nom::Err::Failure((remainder, _))) => {
+            Err(Error::parse(rule, input.len() - remainder.len()).into())
         },
-        Err(nom::Err::Failure(f)) => {
-            Err(SimpleError::debug(f))
+    }
+}
+
+/// Like `result` but all of `input` must be consumed
+pub fn parse_all<'a, O>(rule: &str, input: &'a [u8], result: IResult<&'a [u8], O>) -> Result<O> {
+    match self::result(rule, input, result)? {
+        (output, []) => Ok(output),
+        (_, remainder) => Err(Error::parse(rule, input.len() - remainder.len()).into()),
+    }
+}
+
+/// Finds the end of a message head as bytes arrive without rescanning what it has already seen.
+/// The start line is checked as soon as it is complete, the head is then parsed once by `message_head`.
+#[derive(Debug, Default)]
+pub struct HeadParser {
+    scanned: usize,
+    start_line: bool,
+    headers: usize,
+}
+
+impl HeadParser {
+    pub fn new() -> HeadParser {
+        HeadParser::default()
+    }
+
+    /// Number of header lines seen so far including obs-fold continuations
+    pub fn headers(&self) -> usize {
+        self.headers
+    }
+
+    /// `slice` must start at the same place on every call and only grow, returns the length of the head once complete
+    pub fn parse(&mut self, slice: &[u8]) -> Result<Option<usize>> {
+        while let Some(index) = slice[self.scanned..].iter().position(|chr| *chr == b'\n') {
+            let (start, end) = (self.scanned, self.scanned + index + 1);
+            self.scanned = end;
+            if !self.start_line {
+                let line = &slice[..end];
+                if line.starts_with(b"HTTP/") {
+                    parse_all("status_line", line, status_line(line))?;
+                } else {
+                    parse_all("request_line", line, request_line(line))?;
+                }
+                self.start_line = true;
+            } else if &slice[start..end] == b"\r\n" {
+                return Ok(Some(end));
+            } else {
+                self.headers += 1;
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_head_across_fragments() {
+        let head = b"GET / HTTP/1.1\r\nHost: a\r\nAccept: */*\r\n\r\nbody";
+        let mut parser = HeadParser::new();
+        for end in 0..40 {
+            assert_eq!(parser.parse(&head[..end]).unwrap(), None);
         }
+        assert_eq!(parser.headers(), 2);
+        assert_eq!(parser.parse(&head[..]).unwrap(), Some(40));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn rejects_invalid_start_line_before_the_head_is_complete() {
+        let mut parser = HeadParser::new();
+        assert_eq!(parser.parse(b"GET / HTTP/1.1").unwrap(), None);
+        assert!(HeadParser::new().parse(b"NOT HTTP\r\nHost: a").is_err());
+        let error = HeadParser::new().parse(b"GET * HTTP/1.1\r\n").unwrap_err();
+        assert!(matches!(Error::of(&error), Some(Error::Parse { ref rule, offset: 4 }) if rule == "request_line"), "{:?}", error);
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom, Result};
+use std::ops;
+use std::time::SystemTime;
+use crate::api::{Request, Response, Method};
+use crate::ast::MessageBody;
+use crate::date::{from_seconds, seconds};
+use crate::error::Error;
+use crate::header::{EntityTag, ETag, IfRange, LastModified, Range};
+use crate::misc::random;
+
+/// More ranges than this are treated as no `Range` at all, a client asking for that many is more likely an attack than a download
+const MAX_RANGES: usize = 64;
+
+/// What to send in answer to a `Range` request, RFC 7233 section 3.1
+#[derive(PartialEq, Debug)]
+pub enum Ranges {
+    Full,
+    Partial(Vec<ops::Range<u64>>),
+    Unsatisfiable,
+}
+
+/// Overlapping and adjacent ranges are merged in order so a representation is never sent more than once, RFC 7233 section 6.1
+fn coalesce(mut ranges: Vec<ops::Range<u64>>) -> Vec<ops::Range<u64>> {
+    let overlaps = ranges.iter().enumerate().any(|(index, range)|
+        ranges[index + 1..].iter().any(|other| range.start <= other.end && other.start <= range.end));
+    if !overlaps {
+        return ranges;
+    }
+    ranges.sort_by_key(|range| range.start);
+    ranges.into_iter().fold(Vec::new(), |mut merged: Vec<ops::Range<u64>>, range| {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+        merged
+    })
+}
+
+impl<'a> Request<'a> {
+    /// Only GET has ranges, `If-Range` has to match strongly or the whole representation is sent
+    pub fn ranges(&self, length: u64, etag: Option<&EntityTag>, last_modified: Option<SystemTime>) -> Ranges {
+        if self.method != Method::Get {
+            return Ranges::Full;
+        }
+        let ranges = match self.headers.get_typed::<Range>() {
+            Some(Ok(Range(ranges))) if ranges.len() <= MAX_RANGES => ranges,
+            _ => return Ranges::Full,
+        };
+        match self.headers.get_typed::<IfRange>() {
+            None => {},
+            Some(Ok(IfRange::Tag(tag))) if etag.is_some_and(|etag| tag.strong_eq(etag)) => {},
+            Some(Ok(IfRange::Date(date))) if last_modified.is_some_and(|modified| from_seconds(seconds(modified)) == date) => {},
+            _ => return Ranges::Full,
+        }
+        let ranges: Vec<_> = ranges.iter().filter_map(|range| range.resolve(length)).collect();
+        if ranges.is_empty() {
+            return Ranges::Unsatisfiable;
+        }
+        Ranges::Partial(coalesce(ranges))
+    }
+}
+
+enum Part {
+    Bytes(Cursor<Vec<u8>>),
+    Range(ops::Range<u64>),
+}
+
+/// Reads literal bytes and ranges of a seekable reader in turn, seeking only when a range is read
+struct Parts<R> {
+    reader: R,
+    parts: VecDeque<Part>,
+}
+
+impl<R> Read for Parts<R> where R: Read + Seek {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        while let Some(part) = self.parts.front_mut() {
+            let read = match *part {
+                Part::Bytes(ref mut cursor) => cursor.read(buf)?,
+                Part::Range(ref mut range) if range.is_empty() => 0,
+                Part::Range(ref mut range) => {
+                    self.reader.seek(SeekFrom::Start(range.start))?;
+                    let limit = (buf.len() as u64).min(range.end - range.start) as usize;
+                    let read = self.reader.read(&mut buf[..limit])?;
+                    if read == 0 {
+                        return Err(Error::Incomplete.into());
+                    }
+                    range.start += read as u64;
+                    read
+                }
+            };
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            self.parts.pop_front();
+        }
+        Ok(0)
+    }
+}
+
+/// A representation that can be sent one range at a time, files become `MessageBody::File` so they can skip userspace
+pub trait Seekable: Read + Seek + Sized {
+    fn range<'r>(self, range: ops::Range<u64>) -> MessageBody<'r> where Self: 'r {
+        MessageBody::Reader(Box::new(Parts { reader: self, parts: VecDeque::from(vec!(Part::Range(range))) }))
+    }
+}
+
+impl Seekable for File {
+    fn range<'r>(self, range: ops::Range<u64>) -> MessageBody<'r> {
+        MessageBody::File(self, range)
+    }
+}
+
+impl<T> Seekable for Cursor<T> where T: AsRef<[u8]> {}
+
+/// Sends `reader` as the body of a `200 OK` with a `Content-Length`, or just the ranges the request asked for.
+/// Several ranges are sent as `multipart/byteranges`.
+pub fn ranged<'r, R>(request: &Request, response: Response<'r>, reader: R) -> Response<'r> where R: Seekable + 'r {
+    let length = match response.headers.content_length() {
+        Some(length) if response.code == 200 => length,
+        _ => return response.entity(MessageBody::Reader(Box::new(reader))),
+    };
+    let ranges = {
+        let etag = response.headers.get_typed::<ETag>().and_then(|etag| etag.ok());
+        let last_modified = response.headers.get_typed::<LastModified>().and_then(|date| date.ok());
+        request.ranges(length, etag.as_ref().map(|ETag(etag)| etag), last_modified.map(|LastModified(date)| date))
+    };
+    let response = response.header("Accept-Ranges", "bytes".to_string());
+    match ranges {
+        Ranges::Full => response.entity(reader.range(0..length)),
+        Ranges::Unsatisfiable => Response::range_not_satisfiable().
+            header("Content-Range", format!("bytes */{}", length)),
+        Ranges::Partial(ranges) => partial(response, reader, length, ranges),
+    }
+}
+
+fn partial<'r, R>(response: Response<'r>, reader: R, length: u64, ranges: Vec<ops::Range<u64>>) -> Response<'r> where R: Seekable + 'r {
+    let response = response.code(206).description("Partial Content");
+    if let [ref range] = ranges[..] {
+        return response.
+            header("Content-Range", format!("bytes {}-{}/{}", range.start, range.end - 1, length)).
+            content_length(range.end - range.start).
+            entity(reader.range(range.clone()));
+    }
+    let boundary = format!("{:016x}{:016x}", random(), random());
+    let content_type = response.get_header("Content-Type").map(|content_type| format!("Content-Type: {}\r\n", content_type)).unwrap_or_default();
+    let mut parts = VecDeque::new();
+    let mut total = 0;
+    for range in ranges {
+        let head = format!("--{}\r\n{}Content-Range: bytes {}-{}/{}\r\n\r\n", boundary, content_type, range.start, range.end - 1, length);
+        total += head.len() as u64 + (range.end - range.start) + 2;
+        parts.push_back(Part::Bytes(Cursor::new(head.into_bytes())));
+        parts.push_back(Part::Range(range));
+        parts.push_back(Part::Bytes(Cursor::new(b"\r\n".to_vec())));
+    }
+    let close = format!("--{}--\r\n", boundary);
+    total += close.len() as u64;
+    parts.push_back(Part::Bytes(Cursor::new(close.into_bytes())));
+    response.
+        content_type(format!("multipart/byteranges; boundary={}", boundary)).
+        content_length(total).
+        entity(MessageBody::Reader(Box::new(Parts { reader, parts })))
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+    use crate::multipart::Multipart;
+    use crate::io::Streamer;
+
+    const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+
+    fn ranges(value: &'static str) -> Ranges {
+        Request::get("/").header("Range", value).ranges(26, None, None)
+    }
+
+    fn body(response: &mut Response) -> String {
+        let mut body = String::new();
+        if let MessageBody::Reader(ref mut reader) = response.entity {
+            reader.read_to_string(&mut body).unwrap();
+        }
+        body
+    }
+
+    fn respond(request: Request<'static>) -> Response<'static> {
+        let response = Response::ok().
+            content_type("text/plain").
+            header("ETag", "\"abc\"".to_string()).
+            content_length(26);
+        ranged(&request, response, Cursor::new(ALPHABET))
+    }
+
+    #[test]
+    fn resolves_ranges() {
+        assert_eq!(ranges("bytes=0-4"), Ranges::Partial(vec!(0..5)));
+        assert_eq!(ranges("bytes=-3, 0-0"), Ranges::Partial(vec!(23..26, 0..1)));
+        assert_eq!(ranges("bytes=30-, 5-9"), Ranges::Partial(vec!(5..10)));
+        assert_eq!(ranges("bytes=30-"), Ranges::Unsatisfiable);
+        assert_eq!(ranges("bytes=5-4"), Ranges::Full);
+        assert_eq!(ranges("lines=1-2"), Ranges::Full);
+        assert_eq!(Request::head("/").header("Range", "bytes=0-4").ranges(26, None, None), Ranges::Full);
+    }
+
+    #[test]
+    fn coalesces_overlapping_ranges() {
+        assert_eq!(ranges("bytes=10-15, 0-4, 12-20, 21-21"), Ranges::Partial(vec!(0..5, 10..22)));
+        assert_eq!(ranges("bytes=0-, 0-"), Ranges::Partial(vec!(0..26)));
+        let many = format!("bytes={}", vec!("0-0"; MAX_RANGES + 1).join(","));
+        assert_eq!(Request::get("/").header("Range", many).ranges(26, None, None), Ranges::Full);
+    }
+
+    #[test]
+    fn if_range_must_match_strongly() {
+        let modified = from_seconds(784_111_777);
+        let request = |value: &'static str| Request::get("/").header("Range", "bytes=0-0").header("If-Range", value);
+        assert_eq!(request("\"a\"").ranges(26, Some(&EntityTag::strong("a")), None), Ranges::Partial(vec!(0..1)));
+        assert_eq!(request("\"a\"").ranges(26, Some(&EntityTag::weak("a")), None), Ranges::Full);
+        assert_eq!(request("W/\"a\"").ranges(26, Some(&EntityTag::weak("a")), None), Ranges::Full);
+        assert_eq!(request("Sun, 06 Nov 1994 08:49:37 GMT").ranges(26, None, Some(modified)), Ranges::Partial(vec!(0..1)));
+        assert_eq!(request("Sun, 06 Nov 1994 08:49:38 GMT").ranges(26, None, Some(modified)), Ranges::Full);
+    }
+
+    #[test]
+    fn sends_a_single_range() {
+        let mut response = respond(Request::get("/").header("Range", "bytes=-3"));
+        assert_eq!(response.code, 206);
+        assert_eq!(response.get_header("Content-Range"), Some("bytes 23-25/26"));
+        assert_eq!(response.get_header("Content-Length"), Some("3"));
+        assert_eq!(response.get_header("Accept-Ranges"), Some("bytes"));
+        assert_eq!(body(&mut response), "xyz");
+
+        let mut response = respond(Request::get("/").header("Range", "bytes=0-1").header("If-Range", "\"other\""));
+        assert_eq!(response.code, 200);
+        assert_eq!(body(&mut response), "abcdefghijklmnopqrstuvwxyz");
+    }
+
+    #[test]
+    fn sends_multiple_ranges_as_multipart_byteranges() {
+        let mut response = respond(Request::get("/").header("Range", "bytes=0-2, 24-"));
+        assert_eq!(response.code, 206);
+        let content_type = response.get_header("Content-Type").unwrap().to_string();
+        assert!(content_type.starts_with("multipart/byteranges; boundary="));
+        let length: usize = response.get_header("Content-Length").unwrap().parse().unwrap();
+        let body = body(&mut response);
+        assert_eq!(body.len(), length);
+
+        let boundary = crate::multipart::boundary(&content_type).unwrap().into_owned();
+        let mut multipart = Multipart::new(body.as_bytes(), &boundary);
+        let mut parts = vec!();
+        while let Some(part) = multipart.next() {
+            let mut part = part.unwrap();
+            let mut content = String::new();
+            part.read_to_string(&mut content).unwrap();
+            parts.push((part.headers.get("Content-Range").unwrap().to_string(), part.content_type().unwrap().to_string(), content));
+        }
+        assert_eq!(parts, vec!(("bytes 0-2/26".to_string(), "text/plain".to_string(), "abc".to_string()),
+                               ("bytes 24-25/26".to_string(), "text/plain".to_string(), "yz".to_string())));
+    }
+
+    #[test]
+    fn rejects_unsatisfiable_ranges() {
+        let response = respond(Request::get("/").header("Range", "bytes=26-"));
+        assert_eq!(response.to_string(), "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Length: 0\r\nContent-Range: bytes */26\r\n\r\n");
+    }
+}
@@ -0,0 +1,267 @@
+use std::io::{Result, ErrorKind};
+use std::time::Duration;
+use std::cmp::min;
+use std::thread;
+use crate::api::*;
+use crate::header::{TypedHeader, RetryAfter};
+use crate::misc::random;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum RetryReason {
+    Error(ErrorKind),
+    Status(u16),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Attempt {
+    pub number: u32,
+    pub delay: Duration,
+    pub reason: Option<RetryReason>,
+}
+
+pub fn is_transient(kind: ErrorKind) -> bool {
+    matches!(kind, ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::ConnectionRefused |
+        ErrorKind::BrokenPipe | ErrorKind::TimedOut | ErrorKind::UnexpectedEof | ErrorKind::Interrupted)
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP-date
+pub fn retry_after(value: &str) -> Option<Duration> {
+    RetryAfter::parse(&[value]).ok().map(|retry_after| retry_after.delay())
+}
+
+/// Retries transient failures of the wrapped handler with exponential backoff and full jitter.
+/// A `Retry-After` is honoured as given, one longer than the maximum delay is passed on instead of retried.
+pub struct RetryHandler<H> where H: HttpHandler {
+    handler: H,
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    statuses: Vec<u16>,
+    all_methods: bool,
+    observer: Box<dyn FnMut(&Attempt)>,
+    sleeper: Box<dyn FnMut(Duration)>,
+}
+
+impl<H> RetryHandler<H> where H: HttpHandler {
+    pub fn new(handler: H) -> RetryHandler<H> {
+        RetryHandler {
+            handler,
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            statuses: vec!(429, 502, 503, 504),
+            all_methods: false,
+            observer: Box::new(|_| {}),
+            sleeper: Box::new(thread::sleep),
+        }
+    }
+
+    pub fn max_attempts(mut self, max_attempts: u32) -> RetryHandler<H> {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn backoff(mut self, base_delay: Duration, max_delay: Duration) -> RetryHandler<H> {
+        self.base_delay = base_delay;
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn statuses(mut self, statuses: Vec<u16>) -> RetryHandler<H> {
+        self.statuses = statuses;
+        self
+    }
+
+    /// Also retry methods that are not idempotent such as `POST`
+    pub fn all_methods(mut self, all_methods: bool) -> RetryHandler<H> {
+        self.all_methods = all_methods;
+        self
+    }
+
+    /// Called before every attempt, including the first
+    pub fn on_attempt<F>(mut self, observer: F) -> RetryHandler<H> where F: FnMut(&Attempt) + 'static {
+        self.observer = Box::new(observer);
+        self
+    }
+
+    pub fn sleeper<F>(mut self, sleeper: F) -> RetryHandler<H> where F: FnMut(Duration) + 'static {
+        self.sleeper = Box::new(sleeper);
+        self
+    }
+
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponential = self.base_delay.checked_mul(1 << min(attempt.saturating_sub(1), 31)).unwrap_or(self.max_delay);
+        let capped = min(exponential, self.max_delay).as_nanos() as u64;
+        Duration::from_nanos(if capped == 0 { 0 } else { random() % (capped + 1) })
+    }
+}
+
+impl<H> HttpHandler for RetryHandler<H> where H: HttpHandler {
+    fn handle<F>(&mut self, request: &mut Request, mut fun: F) -> Result<()>
+        where F: FnMut(&mut Response) -> Result<()> + Sized {
+        let retryable = (self.all_methods || request.method.is_idempotent()) && request.entity.is_replayable();
+        let mut attempt = Attempt { number: 1, delay: Duration::default(), reason: None };
+        loop {
+            (self.observer)(&attempt);
+            let can_retry = retryable && attempt.number < self.max_attempts;
+            let (statuses, max_delay) = (&self.statuses, self.max_delay);
+            let mut retry = None;
+
+            let result = self.handler.handle(request, |response| {
+                let after = response.get_header("Retry-After").and_then(retry_after);
+                // waiting less than the server asked for would only earn another refusal
+                if can_retry && statuses.contains(&response.code) && after.is_none_or(|after| after <= max_delay) {
+                    retry = Some((RetryReason::Status(response.code), after));
+                    return Ok(());
+                }
+                fun(response)
+            });
+
+            let (reason, after) = match (result, retry) {
+                (Ok(()), Some(retry)) => retry,
+                (Ok(()), None) => return Ok(()),
+                (Err(e), _) if can_retry && is_transient(e.kind()) => (RetryReason::Error(e.kind()), None),
+                (Err(e), _) => return Err(e),
+            };
+            let delay = after.unwrap_or_else(|| self.delay(attempt.number));
+            (self.sleeper)(delay);
+            attempt = Attempt { number: attempt.number + 1, delay, reason: Some(reason) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use std::io::Error;
+    use crate::ast::MessageBody;
+
+    type Script = Vec<std::result::Result<(u16, Option<&'static str>), ErrorKind>>;
+    type Log<T> = Rc<RefCell<Vec<T>>>;
+
+    struct Scripted {
+        results: Script,
+        requests: Log<String>,
+    }
+
+    impl HttpHandler for Scripted {
+        fn handle<F>(&mut self, request: &mut Request, mut fun: F) -> Result<()>
+            where F: FnMut(&mut Response) -> Result<()> + Sized {
+            self.requests.borrow_mut().push(format!("{} {}", request.method, request.entity));
+            match self.results.remove(0) {
+                Ok((code, retry_after)) => {
+                    let mut response = Response::response(code, "Scripted");
+                    if let Some(retry_after) = retry_after {
+                        response = response.header("Retry-After", retry_after.to_string());
+                    }
+                    fun(&mut response)
+                },
+                Err(kind) => Err(Error::from(kind)),
+            }
+        }
+    }
+
+    fn handler(results: Script) -> (RetryHandler<Scripted>, Log<String>, Log<Attempt>) {
+        let requests = Rc::new(RefCell::new(vec!()));
+        let attempts = Rc::new(RefCell::new(vec!()));
+        let observed = attempts.clone();
+        let retry = RetryHandler::new(Scripted { results, requests: requests.clone() }).
+            backoff(Duration::from_millis(0), Duration::from_secs(5)).
+            sleeper(|_| {}).
+            on_attempt(move |attempt| observed.borrow_mut().push(attempt.clone()));
+        (retry, requests, attempts)
+    }
+
+    #[test]
+    fn retries_transient_failures_of_idempotent_requests() {
+        let (mut retry, requests, attempts) = handler(vec!(Err(ErrorKind::ConnectionReset), Ok((503, None)), Ok((200, None))));
+        let mut code = 0;
+        retry.handle(&mut Request::get("/"), |response| {
+            code = response.code;
+            Ok(())
+        }).unwrap();
+
+        assert_eq!(code, 200);
+        assert_eq!(requests.borrow().len(), 3);
+        assert_eq!(attempts.borrow().iter().map(|a| (a.number, a.reason.clone())).collect::<Vec<_>>(), vec!(
+            (1, None),
+            (2, Some(RetryReason::Error(ErrorKind::ConnectionReset))),
+            (3, Some(RetryReason::Status(503)))));
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts_and_passes_on_last_response() {
+        let (retry, requests, _) = handler(vec!(Ok((502, None)), Ok((502, None)), Ok((502, None))));
+        let mut code = 0;
+        retry.max_attempts(3).handle(&mut Request::get("/"), |response| {
+            code = response.code;
+            Ok(())
+        }).unwrap();
+
+        assert_eq!(code, 502);
+        assert_eq!(requests.borrow().len(), 3);
+    }
+
+    #[test]
+    fn does_not_retry_non_idempotent_or_streaming_requests() {
+        let (mut retry, requests, _) = handler(vec!(Ok((503, None))));
+        retry.handle(&mut Request::post("/"), |_| Ok(())).unwrap();
+        assert_eq!(requests.borrow().len(), 1);
+
+        let (mut retry, requests, _) = handler(vec!(Err(ErrorKind::ConnectionReset)));
+        let mut request = Request::put("/");
+        request.entity = MessageBody::Reader(Box::new(&b"streamed"[..]));
+        assert!(retry.handle(&mut request, |_| Ok(())).is_err());
+        assert_eq!(requests.borrow().len(), 1);
+    }
+
+    #[test]
+    fn replays_slice_and_owned_bodies() {
+        let (retry, requests, _) = handler(vec!(Ok((503, None)), Ok((200, None))));
+        let mut request = Request::post("/");
+        request.entity = MessageBody::Owned(b"form".to_vec());
+        retry.all_methods(true).handle(&mut request, |_| Ok(())).unwrap();
+        assert_eq!(*requests.borrow(), vec!("POST form".to_string(), "POST form".to_string()));
+    }
+
+    #[test]
+    fn honours_retry_after() {
+        let (mut retry, _, attempts) = handler(vec!(Ok((429, Some("3"))), Ok((503, Some("Fri, 31 Dec 1999 23:59:59 GMT"))), Ok((200, None))));
+        retry.handle(&mut Request::get("/"), |_| Ok(())).unwrap();
+        assert_eq!(attempts.borrow()[1].delay, Duration::from_secs(3));
+        assert_eq!(attempts.borrow()[2].delay, Duration::from_secs(0));
+    }
+
+    #[test]
+    fn passes_on_responses_asking_to_wait_longer_than_max_delay() {
+        let (mut retry, requests, _) = handler(vec!(Ok((503, Some("60"))), Ok((200, None))));
+        let mut response = None;
+        retry.handle(&mut Request::get("/"), |r| {
+            response = Some((r.code, r.get_header("Retry-After").map(String::from)));
+            Ok(())
+        }).unwrap();
+        assert_eq!(response, Some((503, Some("60".to_string()))));
+        assert_eq!(requests.borrow().len(), 1);
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        assert_eq!(retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(retry_after("Fri, 31 Dec 1999 23:59:59 GMT"), Some(Duration::from_secs(0)));
+        assert!(retry_after("Fri, 31 Dec 2099 23:59:59 GMT").unwrap() > Duration::from_secs(3600));
+        assert_eq!(retry_after("soon"), None);
+    }
+
+    #[test]
+    fn backoff_is_exponential_and_capped() {
+        let retry = RetryHandler::new(Scripted { results: vec!(), requests: Rc::new(RefCell::new(vec!())) }).
+            backoff(Duration::from_millis(100), Duration::from_millis(300));
+        for _ in 0..20 {
+            assert!(retry.delay(1) <= Duration::from_millis(100));
+            assert!(retry.delay(2) <= Duration::from_millis(200));
+            assert!(retry.delay(10) <= Duration::from_millis(300));
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use std::io::Result;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::str;
+use crate::api::*;
+use crate::misc::join_slice;
+use crate::header::Allow;
+
+pub type Handler = Box<dyn FnMut(&mut Request, &mut dyn FnMut(&mut Response) -> Result<()>) -> Result<()>>;
+
+pub fn boxed<H>(mut handler: H) -> Handler where H: HttpHandler + 'static {
+    Box::new(move |request, fun| handler.handle(request, |response| fun(response)))
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Segment {
+    Literal(String),
+    Parameter(String),
+    Rest(String),
+}
+
+/// A path template such as `/users/{id}/posts/{post}*` where `{name}` captures a single segment
+/// and a trailing `{name}*` captures the remainder of the path
+#[derive(PartialEq, Debug, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Template {
+        let segments: Vec<Segment> = template.trim_start_matches('/').split('/').map(|segment| {
+            if segment.starts_with('{') && segment.ends_with("}*") {
+                Segment::Rest(segment[1..segment.len() - 2].to_string())
+            } else if segment.starts_with('{') && segment.ends_with('}') {
+                Segment::Parameter(segment[1..segment.len() - 1].to_string())
+            } else {
+                Segment::Literal(segment.to_string())
+            }
+        }).collect();
+        if let Some(index) = segments.iter().position(|segment| matches!(segment, Segment::Rest(_))) {
+            assert_eq!(index, segments.len() - 1, "'{{name}}*' must be the last segment of '{}'", template);
+        }
+        Template { segments }
+    }
+
+    pub fn matches<'a>(&self, path: &'a str) -> Option<Vec<(&str, &'a str)>> {
+        let mut captured = vec!();
+        let mut remainder = path.strip_prefix('/')?;
+        for (index, segment) in self.segments.iter().enumerate() {
+            let end = remainder.find('/').unwrap_or(remainder.len());
+            let (current, rest) = (&remainder[..end], &remainder[end..]);
+            let last = index == self.segments.len() - 1;
+            match segment {
+                Segment::Rest(name) => {
+                    captured.push((name.as_str(), remainder));
+                    return Some(captured);
+                }
+                Segment::Literal(literal) if literal == current => {}
+                Segment::Parameter(name) if !current.is_empty() => captured.push((name.as_str(), current)),
+                _ => return None,
+            }
+            if last {
+                return if rest.is_empty() { Some(captured) } else { None };
+            }
+            remainder = rest.strip_prefix('/')?;
+        }
+        Some(captured)
+    }
+}
+
+struct Route {
+    method: Method<'static>,
+    template: Template,
+    handler: Handler,
+}
+
+/// Dispatches to the first route matching the request method and path, path parameters are
+/// added to `Request::params` for the duration of the call
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router::default()
+    }
+
+    pub fn route<M, H>(mut self, method: M, template: &str, handler: H) -> Router where M: Into<Method<'static>>, H: HttpHandler + 'static {
+        self.routes.push(Route { method: method.into(), template: Template::parse(template), handler: boxed(handler) });
+        self
+    }
+
+    pub fn get<H>(self, template: &str, handler: H) -> Router where H: HttpHandler + 'static {
+        self.route(Method::Get, template, handler)
+    }
+
+    pub fn post<H>(self, template: &str, handler: H) -> Router where H: HttpHandler + 'static {
+        self.route(Method::Post, template, handler)
+    }
+
+    pub fn put<H>(self, template: &str, handler: H) -> Router where H: HttpHandler + 'static {
+        self.route(Method::Put, template, handler)
+    }
+
+    pub fn delete<H>(self, template: &str, handler: H) -> Router where H: HttpHandler + 'static {
+        self.route(Method::Delete, template, handler)
+    }
+
+    /// Methods of every route whose template matches `path`, in registration order. `HEAD` follows
+    /// `GET` as it is served by `GET` routes, and `OPTIONS` is always answered for a known path.
+    pub fn allowed(&self, path: &str) -> Vec<Method<'static>> {
+        let mut methods = vec!();
+        for route in self.routes.iter().filter(|route| route.template.matches(path).is_some()) {
+            let implied = if route.method == Method::Get { &[Method::Get, Method::Head][..] } else { &[route.method][..] };
+            for method in implied {
+                if !methods.contains(method) {
+                    methods.push(*method);
+                }
+            }
+        }
+        if !methods.is_empty() && !methods.contains(&Method::Options) {
+            methods.push(Method::Options);
+        }
+        methods
+    }
+
+    fn position(&self, method: Method, path: &str) -> Option<usize> {
+        self.routes.iter().position(|route| route.method == method && route.template.matches(path).is_some())
+    }
+}
+
+impl HttpHandler for Router {
+    fn handle<F>(&mut self, request: &mut Request, mut fun: F) -> Result<()>
+        where F: FnMut(&mut Response) -> Result<()> + Sized {
+        let path = request.uri.path;
+        let method = request.method;
+        let position = self.position(method, path).
+            or_else(|| if method == Method::Head { self.position(Method::Get, path) } else { None });
+
+        match position {
+            Some(index) => {
+                let route = &mut self.routes[index];
+                let captured: Vec<(String, &str)> = route.template.matches(path).unwrap_or_default().into_iter().map(|(name, value)| (name.to_string(), value)).collect();
+                let existing = request.params.len();
+                for (name, value) in captured {
+                    request.params.add(Cow::Owned(name), value);
+                }
+                let result = (route.handler)(request, &mut fun);
+                request.params.0.truncate(existing);
+                result
+            }
+            None => {
+                let allowed = self.allowed(path);
+                if allowed.is_empty() {
+                    return fun(&mut Response::not_found().message("Not Found"));
+                }
+                if method == Method::Options {
+                    return fun(&mut Response::ok().typed(&Allow(allowed)));
+                }
+                fun(&mut Response::method_not_allowed().typed(&Allow(allowed)))
+            }
+        }
+    }
+}
+
+/// Dispatches on path prefix, the inner handler sees the path relative to the mount point while
+/// `Request::base` and `Request::original_path` keep what was stripped
+#[derive(Default)]
+pub struct Mount {
+    mounts: Vec<(String, Handler)>,
+}
+
+impl Mount {
+    pub fn new() -> Mount {
+        Mount::default()
+    }
+
+    pub fn at<H>(mut self, prefix: &str, handler: H) -> Mount where H: HttpHandler + 'static {
+        self.mounts.push((prefix.trim_end_matches('/').to_string(), boxed(handler)));
+        self.mounts.sort_by_key(|(prefix, _)| Reverse(prefix.len()));
+        self
+    }
+}
+
+/// The remainder of `path` when it is `prefix` or continues with a new segment
+pub fn strip_prefix<'a>(prefix: &str, path: &'a str) -> Option<&'a str> {
+    let rest = path.strip_prefix(prefix)?;
+    if rest.is_empty() || rest.starts_with('/') {
+        return Some(rest);
+    }
+    None
+}
+
+impl HttpHandler for Mount {
+    fn handle<F>(&mut self, request: &mut Request, mut fun: F) -> Result<()>
+        where F: FnMut(&mut Response) -> Result<()> + Sized {
+        let (base, path) = (request.base, request.uri.path);
+        let found = self.mounts.iter_mut().find_map(|(prefix, handler)| strip_prefix(prefix, path).map(|rest| (handler, rest)));
+
+        match found {
+            Some((handler, rest)) => {
+                let mounted = &path[..path.len() - rest.len()];
+                request.base = match join_slice(base.as_bytes(), mounted.as_bytes()) {
+                    Ok(joined) => str::from_utf8(joined).unwrap(),
+                    Err(_) => mounted,
+                };
+                request.uri.path = if rest.is_empty() { "/" } else { rest };
+                let result = handler(request, &mut fun);
+                request.base = base;
+                request.uri.path = path;
+                result
+            }
+            None => fun(&mut Response::not_found().message("Not Found")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::misc::TempDir;
+
+    struct Echo(&'static str);
+
+    impl HttpHandler for Echo {
+        fn handle<F>(&mut self, request: &mut Request, mut fun: F) -> Result<()>
+            where F: FnMut(&mut Response) -> Result<()> + Sized {
+            let params: Vec<String> = request.params.0.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+            fun(&mut Response::ok().header("X-Handler", self.0.to_string()).header("X-Params", params.join("&")))
+        }
+    }
+
+    fn dispatch(router: &mut Router, mut request: Request) -> (u16, Option<String>, Option<String>, Option<String>) {
+        let mut result = (0, None, None, None);
+        router.handle(&mut request, |response| {
+            result = (response.code,
+                      response.get_header("X-Handler").map(String::from),
+                      response.get_header("X-Params").map(String::from),
+                      response.get_header("Allow").map(String::from));
+            Ok(())
+        }).unwrap();
+        assert!(request.params.is_empty());
+        result
+    }
+
+    #[test]
+    fn template_matching() {
+        let template = Template::parse("/users/{id}/posts/{post}*");
+        assert_eq!(template.matches("/users/42/posts/a/b"), Some(vec!(("id", "42"), ("post", "a/b"))));
+        assert_eq!(template.matches("/users/42/posts/"), Some(vec!(("id", "42"), ("post", ""))));
+        assert_eq!(template.matches("/users//posts/a"), None);
+        assert_eq!(template.matches("/users/42"), None);
+        assert_eq!(Template::parse("/").matches("/"), Some(vec!()));
+        assert_eq!(Template::parse("/users").matches("/users/42"), None);
+        assert_eq!(Template::parse("/users/{id}").matches("/users/42"), Some(vec!(("id", "42"))));
+    }
+
+    #[test]
+    fn dispatches_by_method_and_path() {
+        let mut router = Router::new().
+            get("/users/{id}", Echo("get-user")).
+            put("/users/{id}", Echo("put-user")).
+            get("/users/{id}/posts/{post}*", Echo("posts"));
+
+        assert_eq!(dispatch(&mut router, Request::get("/users/42")), (200, Some("get-user".to_string()), Some("id=42".to_string()), None));
+        assert_eq!(dispatch(&mut router, Request::put("/users/42")), (200, Some("put-user".to_string()), Some("id=42".to_string()), None));
+        assert_eq!(dispatch(&mut router, Request::get("/users/42/posts/2020/hello")), (200, Some("posts".to_string()), Some("id=42&post=2020/hello".to_string()), None));
+    }
+
+    #[test]
+    fn unknown_paths_are_not_found() {
+        let mut router = Router::new().get("/users/{id}", Echo("get-user"));
+        assert_eq!(dispatch(&mut router, Request::get("/accounts/42")).0, 404);
+    }
+
+    #[test]
+    fn unmatched_methods_are_not_allowed() {
+        let mut router = Router::new().
+            get("/users/{id}", Echo("get-user")).
+            put("/users/{id}", Echo("put-user")).
+            delete("/users/{id}", Echo("delete-user")).
+            post("/users", Echo("create-user"));
+        assert_eq!(dispatch(&mut router, Request::post("/users/42")), (405, None, None, Some("GET, HEAD, PUT, DELETE, OPTIONS".to_string())));
+    }
+
+    #[test]
+    fn head_uses_get_routes_and_options_lists_methods() {
+        let mut router = Router::new().
+            get("/users/{id}", Echo("get-user")).
+            put("/users/{id}", Echo("put-user"));
+        assert_eq!(dispatch(&mut router, Request::head("/users/42")), (200, Some("get-user".to_string()), Some("id=42".to_string()), None));
+        assert_eq!(dispatch(&mut router, Request::options("/users/42")), (200, None, None, Some("GET, HEAD, PUT, OPTIONS".to_string())));
+        assert_eq!(dispatch(&mut router, Request::options("/accounts")).0, 404);
+
+        let mut router = router.route(Method::Head, "/users/{id}", Echo("head-user"));
+        assert_eq!(dispatch(&mut router, Request::head("/users/42")).1, Some("head-user".to_string()));
+    }
+
+    #[test]
+    fn routers_can_be_nested() {
+        let mut router = Router::new().
+            get("/users/{id}/{rest}*", Router::new().get("/users/{user}/profile", Echo("profile")));
+        assert_eq!(dispatch(&mut router, Request::get("/users/42/profile")), (200, Some("profile".to_string()), Some("id=42&rest=profile&user=42".to_string()), None));
+    }
+
+    struct Paths;
+
+    impl HttpHandler for Paths {
+        fn handle<F>(&mut self, request: &mut Request, mut fun: F) -> Result<()>
+            where F: FnMut(&mut Response) -> Result<()> + Sized {
+            fun(&mut Response::ok().
+                header("X-Path", request.uri.path.to_string()).
+                header("X-Base", request.base.to_string()).
+                header("X-Original", request.original_path().into_owned()))
+        }
+    }
+
+    fn paths(mount: &mut Mount, path: &str) -> (u16, Option<String>, Option<String>, Option<String>) {
+        let mut request = Request::get(path);
+        let mut result = (0, None, None, None);
+        mount.handle(&mut request, |response| {
+            result = (response.code,
+                      response.get_header("X-Path").map(String::from),
+                      response.get_header("X-Base").map(String::from),
+                      response.get_header("X-Original").map(String::from));
+            Ok(())
+        }).unwrap();
+        assert_eq!(request.uri.path, path);
+        assert_eq!(request.base, "");
+        result
+    }
+
+    fn some(path: &str, base: &str, original: &str) -> (u16, Option<String>, Option<String>, Option<String>) {
+        (200, Some(path.to_string()), Some(base.to_string()), Some(original.to_string()))
+    }
+
+    #[test]
+    fn strips_mount_prefix() {
+        let mut mount = Mount::new().at("/static/", Paths).at("/api", Paths);
+        assert_eq!(paths(&mut mount, "/static/css/site.css"), some("/css/site.css", "/static", "/static/css/site.css"));
+        assert_eq!(paths(&mut mount, "/api"), some("/", "/api", "/api"));
+        assert_eq!(paths(&mut mount, "/api/"), some("/", "/api", "/api/"));
+        assert_eq!(paths(&mut mount, "/apis").0, 404);
+        assert_eq!(paths(&mut mount, "/other").0, 404);
+    }
+
+    #[test]
+    fn longest_prefix_wins_and_mounts_nest() {
+        let mut mount = Mount::new().
+            at("/", Paths).
+            at("/api", Mount::new().at("/v1", Paths));
+        assert_eq!(paths(&mut mount, "/index.html"), some("/index.html", "", "/index.html"));
+        assert_eq!(paths(&mut mount, "/api/v1/users"), some("/users", "/api/v1", "/api/v1/users"));
+        assert_eq!(paths(&mut mount, "/api/v1"), some("/", "/api/v1", "/api/v1"));
+        assert_eq!(paths(&mut mount, "/api/v2").0, 404);
+    }
+
+    #[test]
+    fn can_mount_file_handler() {
+        let base = TempDir::new("mount");
+        std::fs::write(base.join("hello.txt"), "Hello").unwrap();
+        let root = base.to_path_buf();
+
+        let mut mount = Mount::new().at("/static", FileHandler::new(root));
+        let mut code = 0;
+        mount.handle(&mut Request::get("/static/hello.txt"), |response| {
+            code = response.code;
+            Ok(())
+        }).unwrap();
+        assert_eq!(code, 200);
+    }
+}
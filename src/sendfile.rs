@@ -0,0 +1,109 @@
+use std::fs::File;
+use std::io::{Read, Write, Seek, SeekFrom, Result, copy};
+use std::net::TcpStream;
+use std::ops;
+use crate::error::Error;
+
+/// A writer that can be handed part of a file directly. Only a plain `TcpStream` on Linux uses `sendfile(2)`,
+/// anything that has to see the bytes on the way such as TLS or compression copies them with the default.
+pub trait SendFile: Write {
+    fn send_file(&mut self, file: &mut File, range: ops::Range<u64>) -> Result<u64> {
+        copy_range(file, range, self)
+    }
+}
+
+/// Copies `range` of `file` through userspace, a file shorter than the range fails rather than truncating the message
+pub fn copy_range<W>(file: &mut File, range: ops::Range<u64>, writer: &mut W) -> Result<u64> where W: Write + ?Sized {
+    let length = range.end - range.start;
+    file.seek(SeekFrom::Start(range.start))?;
+    let copied = copy(&mut file.take(length), writer)?;
+    if copied < length {
+        return Err(Error::Incomplete.into());
+    }
+    Ok(copied)
+}
+
+impl SendFile for Vec<u8> {}
+
+impl<W> SendFile for &mut W where W: SendFile + ?Sized {
+    fn send_file(&mut self, file: &mut File, range: ops::Range<u64>) -> Result<u64> {
+        (**self).send_file(file, range)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl SendFile for TcpStream {}
+
+/// Larger counts are cut down to this by the kernel anyway
+#[cfg(target_os = "linux")]
+const MAX_SEND: u64 = 0x7fff_f000;
+
+#[cfg(target_os = "linux")]
+impl SendFile for TcpStream {
+    fn send_file(&mut self, file: &mut File, range: ops::Range<u64>) -> Result<u64> {
+        use std::io;
+        use std::os::unix::io::AsRawFd;
+
+        let mut offset = range.start as libc::off_t;
+        let end = range.end as libc::off_t;
+        while offset < end {
+            let count = ((end - offset) as u64).min(MAX_SEND) as usize;
+            let sent = unsafe { libc::sendfile(self.as_raw_fd(), file.as_raw_fd(), &mut offset, count) };
+            if sent == 0 {
+                return Err(Error::Incomplete.into());
+            }
+            if sent < 0 {
+                let error = io::Error::last_os_error();
+                match error.raw_os_error() {
+                    Some(libc::EINTR) => continue,
+                    // nothing has been sent yet so the file system or socket just does not support it
+                    Some(libc::EINVAL) | Some(libc::ENOSYS) if offset == range.start as libc::off_t => return copy_range(file, range, self),
+                    _ => return Err(error),
+                }
+            }
+        }
+        Ok(range.end - range.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use crate::misc::TempDir;
+
+    fn file(name: &str) -> (TempDir, File) {
+        let dir = TempDir::new(name);
+        std::fs::write(dir.join("alphabet.txt"), "abcdefghijklmnopqrstuvwxyz").unwrap();
+        let file = File::open(dir.join("alphabet.txt")).unwrap();
+        (dir, file)
+    }
+
+    #[test]
+    fn copies_a_range_into_any_writer() {
+        let (_dir, mut file) = file("copy-range");
+        let mut written = Vec::new();
+        assert_eq!(written.send_file(&mut file, 2..5).unwrap(), 3);
+        let error = written.send_file(&mut file, 20..30).unwrap_err();
+        assert_eq!(written, b"cdeuvwxyz");
+        assert!(matches!(Error::of(&error), Some(Error::Incomplete)));
+    }
+
+    #[test]
+    fn sends_a_range_over_a_socket() {
+        let (_dir, mut file) = file("send-file");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let reader = thread::spawn(move || {
+            let mut received = String::new();
+            listener.accept().unwrap().0.read_to_string(&mut received).unwrap();
+            received
+        });
+        let mut stream = TcpStream::connect(address).unwrap();
+        assert_eq!(stream.send_file(&mut file, 23..26).unwrap(), 3);
+        assert_eq!(stream.send_file(&mut file, 0..3).unwrap(), 3);
+        drop(stream);
+        assert_eq!(reader.join().unwrap(), "xyzabc");
+    }
+}
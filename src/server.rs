@@ -1,18 +1,28 @@
 extern crate nom;
 extern crate std;
 
-use std::io::{Read, Result};
+use std::io::{self, Read, Result};
 use std::net::{TcpStream, TcpListener};
+use std::collections::HashMap;
 use std::{thread, str};
 use std::sync::Arc;
 use std::marker::{Send};
 use std::borrow::{Cow, Borrow};
 use crate::api::*;
 use crate::io::*;
+use crate::proxy::*;
+use crate::uri::{Authority, Uri, default_port};
+use crate::framing::ParseMode;
+use crate::error::{Error, Limit};
+use crate::parser::HeadParser;
+use crate::sendfile::SendFile;
+use crate::ast::TargetForm;
 
 pub struct Server<'a> {
     host: Cow<'a, str>,
     port: u16,
+    mode: ParseMode,
+    allow: Option<String>,
 }
 
 impl<'a> Server<'a> {
@@ -20,39 +30,75 @@ impl<'a> Server<'a> {
         where H: Into<Cow<'a, str>> {
         Server {
             host: host.into(),
-            port: port,
+            port,
+            mode: ParseMode::Strict,
+            allow: None,
         }
     }
 
+    pub fn mode(mut self, mode: ParseMode) -> Server<'a> {
+        self.mode = mode;
+        self
+    }
+
+    /// Answers `OPTIONS *` with these methods, otherwise it goes to the handler like any other request
+    pub fn allow(mut self, methods: &[&str]) -> Server<'a> {
+        self.allow = Some(methods.join(", "));
+        self
+    }
+
     pub fn handler<F, H>(&mut self, fun: F) -> Result<()>
         where H: HttpHandler, F: Fn() -> Result<H> + Send + Sync + 'static {
         let listener = self.listen()?;
         let fun = Arc::new(fun);
+        let mode = self.mode;
+        let allow = Arc::new(self.allow.clone());
 
         for stream in listener.incoming() {
             let fun = fun.clone();
+            let allow = allow.clone();
             thread::spawn(move || -> Result<()> {
                 let (mut reader, mut writer) = Stream::split(stream)?;
-                let mut buffer = Buffer::with_capacity(4096);
-                let mut handler = fun()?;
-                loop {
-                    match Stream::read(&mut reader, &mut buffer, |message| {
-                        if let Message::Request(ref mut request) = *message {
-                            return handler.handle(request, |response| {
-                                consume(response.write_to(&mut writer))
-                            });
-                        }
-                        Ok(())
-                    }) {
-                        Ok(()) => continue,
-                        Err(e) => return Err(e),
-                    }
-                }
+                Server::connection(&mut reader, &mut writer, &mut fun()?, mode, allow.as_deref())
             });
         }
         Ok(())
     }
 
+    /// A request that fails before it was answered gets the status of its `Error` and the connection is closed
+    fn connection<R, W, H>(reader: &mut R, writer: &mut W, handler: &mut H, mode: ParseMode, allow: Option<&str>) -> Result<()>
+        where R: Read, W: SendFile, H: HttpHandler {
+        let mut buffer = Buffer::with_capacity(4096);
+        loop {
+            let mut responded = false;
+            match Stream::read(reader, &mut buffer, mode, |message| {
+                if let Message::Request(ref mut request) = *message {
+                    if let (Method::Options, TargetForm::Asterisk, Some(allow)) = (request.method, request.form, allow) {
+                        return consume(Response::ok().header("Allow", allow.to_string()).write_to(writer));
+                    }
+                    let head = request.method == Method::Head;
+                    return handler.handle(request, |response| {
+                        responded = true;
+                        if head {
+                            return consume(response.send_head(writer));
+                        }
+                        consume(response.send(writer))
+                    });
+                }
+                Ok(())
+            }) {
+                Ok(()) => continue,
+                Err(e) => {
+                    if let (false, Some(status)) = (responded, Error::of(&e).and_then(Error::status)) {
+                        let message = e.to_string();
+                        Response::status(status).message(&message).header("Connection", "close".to_string()).write_to(writer)?;
+                    }
+                    return Err(e)
+                },
+            }
+        }
+    }
+
     fn listen(&mut self) -> Result<TcpListener> {
         let authority = (self.host.borrow(), self.port);
         let listener: TcpListener = TcpListener::bind(authority)?;
@@ -65,11 +111,27 @@ impl<'a> Server<'a> {
 pub struct Stream;
 
 impl Stream {
-    fn read<R, F>(reader: &mut R, buffer: &mut Buffer<Vec<u8>>, mut fun: F) -> Result<()>
+    fn read<R, F>(reader: &mut R, buffer: &mut Buffer<Vec<u8>>, mode: ParseMode, fun: F) -> Result<()>
+        where R: Read + Sized, F: FnMut(&mut Message) -> Result<()> {
+        Stream::read_for(reader, buffer, mode, None, fun)
+    }
+
+    fn read_for<R, F>(reader: &mut R, buffer: &mut Buffer<Vec<u8>>, mode: ParseMode, method: Option<Method>, mut fun: F) -> Result<()>
         where R: Read + Sized, F: FnMut(&mut Message) -> Result<()> {
-        consume(buffer.fill(reader))?;
+        let mut head = HeadParser::new();
+        let mut complete = head.parse(buffer.as_read())?;
+        while complete.is_none() {
+            if buffer.as_write().is_empty() {
+                if buffer.read_position == 0 {
+                    return Err(Error::LimitExceeded(Limit::Head).into());
+                }
+                buffer.compact();
+            }
+            consume(buffer.fill(reader))?;
+            complete = head.parse(buffer.as_read())?;
+        }
         unit(buffer.read_from(|slice| {
-            let (mut message, count) = Message::read(slice, reader)?;
+            let (mut message, count) = Message::read_for(slice, reader, mode, method)?;
             fun(&mut message)?;
             Ok(count)
         }))
@@ -81,20 +143,114 @@ impl Stream {
     }
 }
 
-#[derive(Default)]
-pub struct Client;
+pub struct Client {
+    proxies: Proxies,
+    mode: ParseMode,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Client { proxies: Proxies::default(), mode: ParseMode::Lenient }
+    }
+}
+
+impl Client {
+    pub fn new() -> Client {
+        Client::default()
+    }
+
+    pub fn mode(mut self, mode: ParseMode) -> Client {
+        self.mode = mode;
+        self
+    }
+
+    /// Configures proxies from `http_proxy`, `https_proxy` and `no_proxy` as passed to `Process::new`
+    pub fn from_env(env: &HashMap<String, String>) -> Client {
+        Client::new().proxies(Proxies::from_env(env))
+    }
+
+    pub fn proxy(self, proxy: Proxy) -> Client {
+        self.proxies(Proxies::all(proxy))
+    }
+
+    pub fn proxies(mut self, proxies: Proxies) -> Client {
+        self.proxies = proxies;
+        self
+    }
+
+    /// Opens a `CONNECT` tunnel through the proxy, the returned stream talks directly to `authority`
+    pub fn tunnel(proxy: &Proxy, authority: &str) -> Result<TcpStream> {
+        let (mut reader, mut writer) = Stream::split(TcpStream::connect(proxy.address()?))?;
+        let mut buffer = Buffer::with_capacity(4096);
+
+        let mut connect = Request::request("CONNECT", authority).header("Host", authority);
+        if let Some(authorization) = proxy.authorization() {
+            connect.headers.replace("Proxy-Authorization", authorization);
+        }
+        connect.write_to(&mut writer)?;
+
+        Stream::read_for(&mut reader, &mut buffer, ParseMode::Lenient, Some(Method::Connect), |message| {
+            match *message {
+                Message::Response(ref response) if response.status_code().is_success() => Ok(()),
+                Message::Response(ref response) => Err(Error::upstream(format!("Proxy refused tunnel to {}: {} {}", authority, response.code, response.description)).into()),
+                _ => Err(Error::upstream("Proxy did not respond to CONNECT").into()),
+            }
+        })?;
+        Ok(writer)
+    }
+
+    /// A stream to the origin of `uri` for a TLS layer to run over, tunnelled through the proxy for its scheme unless `no_proxy` matches
+    pub fn connect(&self, uri: &Uri) -> Result<TcpStream> {
+        let scheme = uri.scheme.unwrap_or("https").to_ascii_lowercase();
+        let target = Authority::parse(uri.authority.ok_or_else(|| Error::protocol("URI has no authority"))?)?;
+        let address = target.address(default_port(&scheme).unwrap_or(443));
+        match self.proxies.find(&scheme, &address) {
+            Some(proxy) => Client::tunnel(proxy, &address),
+            None => TcpStream::connect(address),
+        }
+    }
+}
 
 impl HttpHandler for Client {
+    /// There is no TLS in this crate so `https` is refused, a TLS layer can be run over `Client::connect` instead
     fn handle<F>(&mut self, request: &mut Request, mut fun: F) -> Result<()>
         where F: FnMut(&mut Response) -> Result<()> + Sized {
-        let stream = TcpStream::connect(request.get_header("Host").unwrap());
-
-        let (mut reader, mut writer) = Stream::split(stream)?;
+        let scheme = request.uri.scheme.unwrap_or("http");
+        if !scheme.eq_ignore_ascii_case("http") {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, format!("Client does not support {} without a TLS layer", scheme)));
+        }
+        let target = match request.uri.authority {
+            Some(authority) => Authority::parse(authority)?,
+            None => Authority::host(request.get_header("Host").ok_or_else(|| Error::protocol("Request has no Host"))?)?,
+        };
+        let authority = target.host_port().to_string();
+        let address = target.address(default_port(scheme).unwrap_or(80));
+
+        let proxy = self.proxies.find(scheme, &authority);
+
+        let (mut reader, mut writer) = Stream::split(match proxy {
+            Some(proxy) => TcpStream::connect(proxy.address()?),
+            None => TcpStream::connect(address),
+        })?;
         let mut buffer = Buffer::with_capacity(4096);
 
-        request.write_to(&mut writer)?;
+        match proxy {
+            Some(proxy) => {
+                let target = request.absolute_uri().unwrap_or_else(|| format!("http://{}{}", authority, request.uri));
+                if let Some(authorization) = proxy.authorization() {
+                    request.headers.replace("Proxy-Authorization", authorization);
+                }
+                let written = request.write_with_target(&target, &mut writer);
+                request.headers.remove("Proxy-Authorization");
+                written?;
+            }
+            None => {
+                request.write_to(&mut writer)?;
+            }
+        }
 
-        Stream::read(&mut reader, &mut buffer, |message| {
+        let method = request.method;
+        Stream::read_for(&mut reader, &mut buffer, self.mode, Some(method), |message| {
             if let Message::Response(ref mut response) = *message {
                 return fun(response)
             }
@@ -108,8 +264,11 @@ impl HttpHandler for Client {
 #[allow(unused_must_use)]
 mod tests {
     use crate::io::*;
-    
     use crate::api::*;
+    use crate::proxy::*;
+    use crate::framing::ParseMode;
+    use crate::error::{Error, Limit};
+    use super::{Client, Server};
 
     #[test]
     fn read_supports_fragmentation() {
@@ -117,7 +276,7 @@ mod tests {
         let post = "POST /foo HTTP/1.1\r\n\r\n";
         let put = "PUT /bar HTTP/1.1\r\n\r\n";
         let option = "OPTION / HTTP/1.1\r\n\r\n";
-        let index = vec!(get, post, put, option);
+        let index = [get, post, put, option];
         let requests = index.iter().fold(String::new(), |a, &v| a + v);
         let data = requests.as_bytes();
         let mut buffer = Buffer::with_capacity(data.len());
@@ -125,7 +284,7 @@ mod tests {
         let mut count = 0;
 
         while count < index.len() {
-            super::Stream::read(&mut read, &mut buffer, |message| {
+            super::Stream::read(&mut read, &mut buffer, ParseMode::Strict, |message| {
                 assert_eq!(*message, Message::parse(index[count].as_bytes()).unwrap().0);
                 count += 1;
                 Ok(())
@@ -133,17 +292,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn read_resumes_until_the_head_is_complete() {
+        let request = "POST /foo HTTP/1.1\r\nHost: a\r\nContent-Length: 3\r\n\r\nabc";
+        let pipelined = request.repeat(2);
+        let mut read = Fragmented::new(pipelined.as_bytes(), pipelined.len());
+        let mut buffer = Buffer::with_capacity(64);
+        for _ in 0..2 {
+            super::Stream::read(&mut read, &mut buffer, ParseMode::Strict, |message| {
+                let mut result = String::new();
+                unsafe { message.write_to(result.as_mut_vec()) };
+                assert_eq!(result, request);
+                Ok(())
+            }).expect("No errors");
+        }
+    }
+
+    #[test]
+    fn read_rejects_heads_larger_than_the_buffer() {
+        let mut data = &b"GET / HTTP/1.1\r\nHost: a\r\n\r\n"[..];
+        let mut buffer = Buffer::with_capacity(16);
+        let error = super::Stream::read(&mut data, &mut buffer, ParseMode::Strict, |message| Ok(())).unwrap_err();
+        assert!(matches!(Error::of(&error), Some(Error::LimitExceeded(Limit::Head))));
+    }
+
     #[test]
     fn read_handles_requests_that_fit_in_buffer() {
         let get = "GET / HTTP/1.1\r\n\r\n";
-        let index = vec!(get, get, get, get);
+        let index = [get, get, get, get];
         let requests = index.iter().fold(String::new(), |a, &v| a + v);
         let mut data = requests.as_bytes();
         let mut buffer = Buffer::with_capacity(get.len());
         let mut count = 0;
 
         while count < index.len() {
-            super::Stream::read(&mut data, &mut buffer, |message| {
+            super::Stream::read(&mut data, &mut buffer, ParseMode::Strict, |message| {
                 assert_eq!(*message, Message::parse(index[count].as_bytes()).unwrap().0);
                 count += 1;
                 Ok(())
@@ -156,13 +339,13 @@ mod tests {
         let head = "POST /where?q=now HTTP/1.1\r\nContent-Type: plain/text\r\nContent-Length: 26\r\n\r\n";
         let body = "abcdefghijklmnopqrstuvwxyz";
         let request = head.to_owned() + body;
-        let index = vec!(head, body, head, body);
+        let index = [head, body, head, body];
         let requests = index.iter().fold(String::new(), |a, &v| a + v);
         let mut data = requests.as_bytes();
         let mut buffer = Buffer::with_capacity(head.len());
         let mut count = 0;
 
-        super::Stream::read(&mut data, &mut buffer, |message| {
+        super::Stream::read(&mut data, &mut buffer, ParseMode::Strict, |message| {
             let mut result = String::new();
             unsafe { message.write_to(result.as_mut_vec()) };
             assert_eq!(result, request);
@@ -172,7 +355,7 @@ mod tests {
 
         assert_eq!(count, 1);
 
-        super::Stream::read(&mut data, &mut buffer, |message| {
+        super::Stream::read(&mut data, &mut buffer, ParseMode::Strict, |message| {
             let mut result = String::new();
             unsafe { message.write_to(result.as_mut_vec()) };
             assert_eq!(result, request);
@@ -183,7 +366,7 @@ mod tests {
         assert_eq!(count, 2);
 
 
-        assert!(super::Stream::read(&mut data, &mut buffer, |message| {
+        assert!(super::Stream::read(&mut data, &mut buffer, ParseMode::Strict, |message| {
             panic!("Should not be any more data")
         }).is_err());
     }
@@ -193,13 +376,13 @@ mod tests {
         let head = "POST /where?q=now HTTP/1.1\r\nContent-Type: plain/text\r\nContent-Length: 26\r\n\r\n";
         let body = "abcdefghijklmnopqrstuvwxyz";
         let request = head.to_owned() + body;
-        let index = vec!(head, body, head, body);
+        let index = [head, body, head, body];
         let requests = index.iter().fold(String::new(), |a, &v| a + v);
         let mut data = requests.as_bytes();
         let mut buffer = Buffer::with_capacity(head.len());
         let mut count = 0;
 
-        super::Stream::read(&mut data, &mut buffer, |message| {
+        super::Stream::read(&mut data, &mut buffer, ParseMode::Strict, |message| {
             // Ignore message so body is not consumed
             count += 1;
             Ok(())
@@ -207,7 +390,7 @@ mod tests {
 
         assert_eq!(count, 1);
 
-        super::Stream::read(&mut data, &mut buffer, |message| {
+        super::Stream::read(&mut data, &mut buffer, ParseMode::Strict, |message| {
             // Ignore message so body is not consumed
             count += 1;
             Ok(())
@@ -216,8 +399,207 @@ mod tests {
         assert_eq!(count, 2);
 
 
-        assert!(super::Stream::read(&mut data, &mut buffer, |message| {
+        assert!(super::Stream::read(&mut data, &mut buffer, ParseMode::Strict, |message| {
             panic!("Should not be any more data")
         }).is_err());
     }
-}
\ No newline at end of file
+
+    fn proxy<F>(connections: usize, mut fun: F) -> u16
+        where F: FnMut(&Request, &mut dyn std::io::Write) -> std::io::Result<()> + Send + 'static {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut reader, mut writer) = super::Stream::split(listener.accept().map(|(stream, _)| stream)).unwrap();
+            let mut buffer = Buffer::with_capacity(4096);
+            for _ in 0..connections {
+                super::Stream::read(&mut reader, &mut buffer, ParseMode::Strict, |message| {
+                    if let Message::Request(ref request) = *message {
+                        return fun(request, &mut writer);
+                    }
+                    Ok(())
+                }).unwrap();
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn client_sends_absolute_form_through_http_proxy() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let port = proxy(1, move |request, writer| {
+            sender.send((request.uri.to_string(), request.get_header("Proxy-Authorization").map(String::from))).unwrap();
+            consume(Response::ok().message("proxied").write_to(writer))
+        });
+
+        let mut client = Client::new().proxy(Proxy::new(format!("127.0.0.1:{}", port)).basic_auth("Aladdin", "open sesame"));
+        let mut request = Request::get("/ip").header("Host", "httpbin.org");
+        let mut description = String::new();
+        client.handle(&mut request, |response| {
+            description = response.description.to_string();
+            Ok(())
+        }).unwrap();
+
+        assert_eq!(description, "proxied");
+        assert_eq!(receiver.recv().unwrap(), ("http://httpbin.org/ip".to_string(), Some("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==".to_string())));
+        assert_eq!(request.get_header("Proxy-Authorization"), None);
+    }
+
+    #[test]
+    fn client_refuses_https_without_tls() {
+        let mut client = Client::new().proxies(Proxies { https: Some(Proxy::new("127.0.0.1:1")), ..Proxies::new() });
+        let error = client.handle(&mut Request::get("https://httpbin.org/ip"), |_| Ok(())).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn tunnel_connects_through_proxy() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let port = proxy(2, move |request, writer| {
+            sender.send(format!("{} {}", request.method, request.request_target())).unwrap();
+            if request.method == "CONNECT" {
+                return consume(writer.write(b"HTTP/1.1 200 Connection established\r\n\r\n"));
+            }
+            consume(Response::ok().message("tunnelled").write_to(writer))
+        });
+
+        let mut tunnel = Client::tunnel(&Proxy::new(format!("127.0.0.1:{}", port)), "httpbin.org:443").unwrap();
+        Request::get("/ip").header("Host", "httpbin.org").write_to(&mut tunnel).unwrap();
+
+        assert_eq!(receiver.recv().unwrap(), "CONNECT httpbin.org:443");
+        assert_eq!(receiver.recv().unwrap(), "GET /ip");
+    }
+
+    #[test]
+    fn connect_uses_the_proxies_from_the_environment() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let port = proxy(1, move |request, writer| {
+            sender.send(format!("{} {}", request.method, request.request_target())).unwrap();
+            consume(writer.write(b"HTTP/1.1 200 Connection established\r\n\r\n"))
+        });
+        let origin = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let origin_port = origin.local_addr().unwrap().port();
+        let env: std::collections::HashMap<String, String> = vec!(
+            ("HTTPS_PROXY".to_string(), format!("http://127.0.0.1:{}", port)),
+            ("NO_PROXY".to_string(), "127.0.0.1".to_string())).into_iter().collect();
+        let client = Client::from_env(&env);
+
+        client.connect(&Uri::parse("https://httpbin.org/ip")).unwrap();
+        assert_eq!(receiver.recv().unwrap(), "CONNECT httpbin.org:443");
+
+        let direct = client.connect(&Uri::parse(&format!("https://127.0.0.1:{}/", origin_port))).unwrap();
+        assert_eq!(origin.accept().unwrap().0.peer_addr().unwrap(), direct.local_addr().unwrap());
+    }
+
+    #[test]
+    fn tunnel_fails_when_proxy_refuses() {
+        let port = proxy(1, |_, writer| {
+            consume(Response::response(407, "Proxy Authentication Required").write_to(writer))
+        });
+
+        assert!(Client::tunnel(&Proxy::new(format!("127.0.0.1:{}", port)), "httpbin.org:443").is_err());
+    }
+
+    struct Counting(usize);
+
+    impl HttpHandler for Counting {
+        fn handle<F>(&mut self, request: &mut Request, mut fun: F) -> std::io::Result<()>
+            where F: FnMut(&mut Response) -> std::io::Result<()> + Sized {
+            self.0 += 1;
+            fun(&mut Response::ok())
+        }
+    }
+
+    struct Hello;
+
+    impl HttpHandler for Hello {
+        fn handle<F>(&mut self, _request: &mut Request, mut fun: F) -> std::io::Result<()>
+            where F: FnMut(&mut Response) -> std::io::Result<()> + Sized {
+            fun(&mut Response::ok().entity(crate::ast::MessageBody::Slice(b"Hello")))
+        }
+    }
+
+    #[test]
+    fn server_leaves_out_the_body_for_head() {
+        let mut data = &b"HEAD / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n"[..];
+        let mut written = Vec::new();
+        Server::connection(&mut data, &mut written, &mut Hello, ParseMode::Strict, None).unwrap_err();
+        assert_eq!(String::from_utf8(written).unwrap(),
+                   "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHello");
+    }
+
+    #[test]
+    fn server_answers_options_asterisk() {
+        let mut data = &b"OPTIONS * HTTP/1.1\r\n\r\n"[..];
+        let mut written = Vec::new();
+        let mut handler = Counting(0);
+        Server::connection(&mut data, &mut written, &mut handler, ParseMode::Strict, Some("GET, HEAD, OPTIONS")).unwrap_err();
+        assert_eq!(handler.0, 0);
+        assert_eq!(String::from_utf8(written).unwrap(), "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nAllow: GET, HEAD, OPTIONS\r\n\r\n");
+
+        let mut data = &b"OPTIONS * HTTP/1.1\r\n\r\n"[..];
+        Server::connection(&mut data, &mut Vec::new(), &mut handler, ParseMode::Strict, None).unwrap_err();
+        assert_eq!(handler.0, 1);
+    }
+
+    struct Unread;
+
+    impl std::io::Read for Unread {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            panic!("the body of a response to HEAD should not be read")
+        }
+    }
+
+    struct Streaming;
+
+    impl HttpHandler for Streaming {
+        fn handle<F>(&mut self, _request: &mut Request, mut fun: F) -> std::io::Result<()>
+            where F: FnMut(&mut Response) -> std::io::Result<()> + Sized {
+            fun(&mut Response::ok().content_length(5).entity(crate::ast::MessageBody::Reader(Box::new(Unread))))
+        }
+    }
+
+    #[test]
+    fn server_does_not_read_the_body_for_head() {
+        let mut data = &b"HEAD / HTTP/1.1\r\n\r\n"[..];
+        let mut written = Vec::new();
+        Server::connection(&mut data, &mut written, &mut Streaming, ParseMode::Strict, None).unwrap_err();
+        assert_eq!(String::from_utf8(written).unwrap(), "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n");
+    }
+
+    struct Failing(bool);
+
+    impl HttpHandler for Failing {
+        fn handle<F>(&mut self, request: &mut Request, mut fun: F) -> std::io::Result<()>
+            where F: FnMut(&mut Response) -> std::io::Result<()> + Sized {
+            if self.0 {
+                fun(&mut Response::ok())?;
+                return Err(Error::protocol("Failed after responding").into());
+            }
+            Err(Error::upstream("Proxy refused tunnel").into())
+        }
+    }
+
+    #[test]
+    fn server_only_reports_errors_that_have_not_been_answered() {
+        let mut written = Vec::new();
+        Server::connection(&mut &b"GET / HTTP/1.1\r\n\r\n"[..], &mut written, &mut Failing(true), ParseMode::Strict, None).unwrap_err();
+        assert_eq!(String::from_utf8(written).unwrap(), "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+
+        let mut written = Vec::new();
+        Server::connection(&mut &b"GET / HTTP/1.1\r\n\r\n"[..], &mut written, &mut Failing(false), ParseMode::Strict, None).unwrap_err();
+        assert!(String::from_utf8(written).unwrap().starts_with("HTTP/1.1 502 Proxy refused tunnel\r\n"));
+    }
+
+    #[test]
+    fn server_rejects_smuggling_attempts_with_bad_request() {
+        let mut data = &b"POST / HTTP/1.1\r\nContent-Length: 4\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\nGET /admin HTTP/1.1\r\n\r\n"[..];
+        let mut written = Vec::new();
+        let mut handler = Counting(0);
+        let error = Server::connection(&mut data, &mut written, &mut handler, ParseMode::Strict, None).unwrap_err();
+
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(handler.0, 0);
+        let response = String::from_utf8(written).unwrap();
+        assert!(response.starts_with("HTTP/1.1 400 Both Content-Length and Transfer-Encoding are present"), "{}", response);
+    }
+}
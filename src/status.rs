@@ -0,0 +1,163 @@
+use std::fmt;
+use crate::api::Response;
+
+/// A response status code, any three digit code is allowed but only registered ones have a reason phrase
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+pub struct StatusCode(pub u16);
+
+macro_rules! statuses {
+    ($(($code:expr, $constant:ident, $constructor:ident, $reason:expr)),* $(,)?) => {
+        impl StatusCode {
+            $(pub const $constant: StatusCode = StatusCode($code);)*
+
+            /// Canonical reason phrase from the IANA HTTP Status Code Registry
+            pub fn reason(&self) -> Option<&'static str> {
+                match self.0 {
+                    $($code => Some($reason),)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl<'a> Response<'a> {
+            $(pub fn $constructor() -> Response<'a> {
+                Response::status(StatusCode::$constant)
+            })*
+        }
+    };
+}
+
+statuses! {
+    (100, CONTINUE, continue_, "Continue"),
+    (101, SWITCHING_PROTOCOLS, switching_protocols, "Switching Protocols"),
+    (102, PROCESSING, processing, "Processing"),
+    (103, EARLY_HINTS, early_hints, "Early Hints"),
+    (200, OK, ok, "OK"),
+    (201, CREATED, created, "Created"),
+    (202, ACCEPTED, accepted, "Accepted"),
+    (203, NON_AUTHORITATIVE_INFORMATION, non_authoritative_information, "Non-Authoritative Information"),
+    (204, NO_CONTENT, no_content, "No Content"),
+    (205, RESET_CONTENT, reset_content, "Reset Content"),
+    (206, PARTIAL_CONTENT, partial_content, "Partial Content"),
+    (207, MULTI_STATUS, multi_status, "Multi-Status"),
+    (208, ALREADY_REPORTED, already_reported, "Already Reported"),
+    (226, IM_USED, im_used, "IM Used"),
+    (300, MULTIPLE_CHOICES, multiple_choices, "Multiple Choices"),
+    (301, MOVED_PERMANENTLY, moved_permanently, "Moved Permanently"),
+    (302, FOUND, found, "Found"),
+    (303, SEE_OTHER, see_other, "See Other"),
+    (304, NOT_MODIFIED, not_modified, "Not Modified"),
+    (305, USE_PROXY, use_proxy, "Use Proxy"),
+    (307, TEMPORARY_REDIRECT, temporary_redirect, "Temporary Redirect"),
+    (308, PERMANENT_REDIRECT, permanent_redirect, "Permanent Redirect"),
+    (400, BAD_REQUEST, bad_request, "Bad Request"),
+    (401, UNAUTHORIZED, unauthorized, "Unauthorized"),
+    (402, PAYMENT_REQUIRED, payment_required, "Payment Required"),
+    (403, FORBIDDEN, forbidden, "Forbidden"),
+    (404, NOT_FOUND, not_found, "Not Found"),
+    (405, METHOD_NOT_ALLOWED, method_not_allowed, "Method Not Allowed"),
+    (406, NOT_ACCEPTABLE, not_acceptable, "Not Acceptable"),
+    (407, PROXY_AUTHENTICATION_REQUIRED, proxy_authentication_required, "Proxy Authentication Required"),
+    (408, REQUEST_TIMEOUT, request_timeout, "Request Timeout"),
+    (409, CONFLICT, conflict, "Conflict"),
+    (410, GONE, gone, "Gone"),
+    (411, LENGTH_REQUIRED, length_required, "Length Required"),
+    (412, PRECONDITION_FAILED, precondition_failed, "Precondition Failed"),
+    (413, CONTENT_TOO_LARGE, content_too_large, "Content Too Large"),
+    (414, URI_TOO_LONG, uri_too_long, "URI Too Long"),
+    (415, UNSUPPORTED_MEDIA_TYPE, unsupported_media_type, "Unsupported Media Type"),
+    (416, RANGE_NOT_SATISFIABLE, range_not_satisfiable, "Range Not Satisfiable"),
+    (417, EXPECTATION_FAILED, expectation_failed, "Expectation Failed"),
+    (421, MISDIRECTED_REQUEST, misdirected_request, "Misdirected Request"),
+    (422, UNPROCESSABLE_CONTENT, unprocessable_content, "Unprocessable Content"),
+    (423, LOCKED, locked, "Locked"),
+    (424, FAILED_DEPENDENCY, failed_dependency, "Failed Dependency"),
+    (425, TOO_EARLY, too_early, "Too Early"),
+    (426, UPGRADE_REQUIRED, upgrade_required, "Upgrade Required"),
+    (428, PRECONDITION_REQUIRED, precondition_required, "Precondition Required"),
+    (429, TOO_MANY_REQUESTS, too_many_requests, "Too Many Requests"),
+    (431, REQUEST_HEADER_FIELDS_TOO_LARGE, request_header_fields_too_large, "Request Header Fields Too Large"),
+    (451, UNAVAILABLE_FOR_LEGAL_REASONS, unavailable_for_legal_reasons, "Unavailable For Legal Reasons"),
+    (500, INTERNAL_SERVER_ERROR, internal_server_error, "Internal Server Error"),
+    (501, NOT_IMPLEMENTED, not_implemented, "Not Implemented"),
+    (502, BAD_GATEWAY, bad_gateway, "Bad Gateway"),
+    (503, SERVICE_UNAVAILABLE, service_unavailable, "Service Unavailable"),
+    (504, GATEWAY_TIMEOUT, gateway_timeout, "Gateway Timeout"),
+    (505, HTTP_VERSION_NOT_SUPPORTED, http_version_not_supported, "HTTP Version Not Supported"),
+    (506, VARIANT_ALSO_NEGOTIATES, variant_also_negotiates, "Variant Also Negotiates"),
+    (507, INSUFFICIENT_STORAGE, insufficient_storage, "Insufficient Storage"),
+    (508, LOOP_DETECTED, loop_detected, "Loop Detected"),
+    (511, NETWORK_AUTHENTICATION_REQUIRED, network_authentication_required, "Network Authentication Required"),
+}
+
+impl StatusCode {
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.0)
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.0)
+    }
+
+    pub fn is_redirection(&self) -> bool {
+        (300..400).contains(&self.0)
+    }
+
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.0)
+    }
+
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.0)
+    }
+}
+
+impl From<u16> for StatusCode {
+    fn from(code: u16) -> StatusCode {
+        StatusCode(code)
+    }
+}
+
+impl From<StatusCode> for u16 {
+    fn from(status: StatusCode) -> u16 {
+        status.0
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
+        write!(format, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn has_canonical_reason_phrases() {
+        assert_eq!(StatusCode::OK.reason(), Some("OK"));
+        assert_eq!(StatusCode(413).reason(), Some("Content Too Large"));
+        assert_eq!(StatusCode(418).reason(), None);
+        assert_eq!(StatusCode::NOT_FOUND, StatusCode::from(404));
+        assert_eq!(u16::from(StatusCode::IM_USED), 226);
+    }
+
+    #[test]
+    fn has_classes() {
+        assert!(StatusCode::CONTINUE.is_informational());
+        assert!(StatusCode::NO_CONTENT.is_success());
+        assert!(StatusCode::NOT_MODIFIED.is_redirection());
+        assert!(StatusCode::GONE.is_client_error() && !StatusCode::GONE.is_server_error());
+        assert!(StatusCode::BAD_GATEWAY.is_server_error());
+    }
+
+    #[test]
+    fn has_a_response_constructor_for_every_status() {
+        let response = Response::range_not_satisfiable();
+        assert_eq!((response.code, response.description), (416, "Range Not Satisfiable"));
+        assert_eq!(Response::continue_().code, 100);
+        assert_eq!(Response::status(StatusCode(299)).description, "");
+        assert_eq!(Response::ok().status_code(), StatusCode::OK);
+    }
+}
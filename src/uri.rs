@@ -0,0 +1,798 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Result};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use regex::Regex;
+use crate::ast::Parameters;
+use crate::parser::parse_all;
+use crate::form::{hex, is_unreserved, parse_query, percent_decode, serialize_query};
+use crate::grammar::is_sub_delim;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Component {
+    /// The user name part of user info, where `:` starts the password
+    User,
+    UserInfo,
+    Host,
+    Path,
+    Segment,
+    Query,
+    Fragment,
+}
+
+fn is_allowed(component: Component, chr: u8) -> bool {
+    // pchar = unreserved / pct-encoded / sub-delims / ":" / "@"
+    let pchar = is_unreserved(chr) || is_sub_delim(chr) || chr == b':' || chr == b'@';
+    match component {
+        Component::User => is_unreserved(chr) || is_sub_delim(chr),
+        Component::UserInfo => is_unreserved(chr) || is_sub_delim(chr) || chr == b':',
+        Component::Host => is_unreserved(chr) || is_sub_delim(chr),
+        Component::Segment => pchar,
+        Component::Path => pchar || chr == b'/',
+        Component::Query | Component::Fragment => pchar || chr == b'/' || chr == b'?',
+    }
+}
+
+pub fn encode(value: &str, component: Component) -> Cow<'_, str> {
+    if value.bytes().all(|chr| is_allowed(component, chr)) {
+        return Cow::from(value);
+    }
+    let mut encoded = String::with_capacity(value.len() * 3);
+    for chr in value.bytes() {
+        if is_allowed(component, chr) {
+            encoded.push(chr as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", chr));
+        }
+    }
+    Cow::from(encoded)
+}
+
+pub fn normalize_percent(value: &str) -> Cow<'_, str> {
+    if !value.contains('%') {
+        return Cow::from(value);
+    }
+    let bytes = value.as_bytes();
+    let mut normalized = String::with_capacity(value.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escape = match (bytes[index], bytes.get(index + 1).cloned().and_then(hex), bytes.get(index + 2).cloned().and_then(hex)) {
+            (b'%', Some(high), Some(low)) => Some(high << 4 | low),
+            _ => None,
+        };
+        match escape {
+            Some(chr) if is_unreserved(chr) => normalized.push(chr as char),
+            Some(chr) => normalized.push_str(&format!("%{:02X}", chr)),
+            None => {
+                let length = value[index..].chars().next().map_or(1, char::len_utf8);
+                normalized.push_str(&value[index..index + length]);
+                index += length;
+                continue;
+            }
+        }
+        index += 3;
+    }
+    Cow::from(normalized)
+}
+
+/// RFC 3986 section 5.2.4
+pub fn remove_dot_segments(path: &str) -> String {
+    fn pop(output: &mut String) {
+        let index = output.rfind('/').unwrap_or(0);
+        output.truncate(index);
+    }
+
+    let mut input = path;
+    let mut output = String::with_capacity(path.len());
+    while !input.is_empty() {
+        if input.starts_with("../") {
+            input = &input[3..];
+        } else if input.starts_with("./") || input.starts_with("/./") {
+            input = &input[2..];
+        } else if input == "/." {
+            input = "/";
+        } else if input.starts_with("/../") {
+            input = &input[3..];
+            pop(&mut output);
+        } else if input == "/.." {
+            input = "/";
+            pop(&mut output);
+        } else if input == "." || input == ".." {
+            input = "";
+        } else {
+            let end = input.char_indices().skip(1).find(|&(_, chr)| chr == '/').map_or(input.len(), |(index, _)| index);
+            output.push_str(&input[..end]);
+            input = &input[end..];
+        }
+    }
+    output
+}
+
+pub fn default_port(scheme: &str) -> Option<u16> {
+    match scheme.to_ascii_lowercase().as_str() {
+        "http" | "ws" => Some(80),
+        "https" | "wss" => Some(443),
+        "ftp" => Some(21),
+        _ => None,
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Host<'a> {
+    RegName(&'a str),
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr),
+}
+
+impl<'a> Host<'a> {
+    /// Lower cased for comparison, IPv6 addresses are without brackets
+    pub fn name(&self) -> Cow<'a, str> {
+        match *self {
+            Host::RegName(name) if name.bytes().any(|chr| chr.is_ascii_uppercase()) => Cow::from(name.to_ascii_lowercase()),
+            Host::RegName(name) => Cow::from(name),
+            Host::Ipv4(address) => Cow::from(address.to_string()),
+            Host::Ipv6(address) => Cow::from(address.to_string()),
+        }
+    }
+}
+
+impl<'a> fmt::Display for Host<'a> {
+    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Host::RegName(name) => format.write_str(name),
+            Host::Ipv4(address) => write!(format, "{}", address),
+            Host::Ipv6(address) => write!(format, "[{}]", address),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Authority<'a> {
+    pub user_info: Option<&'a str>,
+    pub host: Host<'a>,
+    pub port: Option<u16>,
+}
+
+impl<'a> Authority<'a> {
+    pub fn parse(value: &'a str) -> Result<Authority<'a>> {
+        use crate::grammar::authority;
+
+        parse_all("authority", value.as_bytes(), authority(value.as_bytes()))
+    }
+
+    /// Parses a `Host` header which unlike an authority never has user info
+    pub fn host(value: &'a str) -> Result<Authority<'a>> {
+        use crate::grammar::host;
+
+        let value = value.trim();
+        parse_all("Host", value.as_bytes(), host(value.as_bytes()))
+    }
+
+    pub fn address(&self, default_port: u16) -> String {
+        format!("{}:{}", self.host, self.port.unwrap_or(default_port))
+    }
+
+    pub fn host_port(&self) -> Authority<'a> {
+        Authority { user_info: None, host: self.host.clone(), port: self.port }
+    }
+}
+
+impl<'a> fmt::Display for Authority<'a> {
+    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(user_info) = self.user_info {
+            write!(format, "{}@", user_info)?;
+        }
+        write!(format, "{}", self.host)?;
+        if let Some(port) = self.port {
+            write!(format, ":{}", port)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Uri<'a> {
+    pub scheme: Option<&'a str>,
+    pub authority: Option<&'a str>,
+    pub path: &'a str,
+    pub query: Option<&'a str>,
+    pub fragment: Option<&'a str>,
+}
+
+impl<'a> Uri<'a> {
+    pub fn parse(value: &'a str) -> Uri<'a> {
+        lazy_static! {
+            static ref RFC3986: Regex = Regex::new("^(?:([^:/?\\#]+):)?(?://([^/?\\#]*))?([^?\\#]*)(?:\\?([^\\#]*))?(?:\\#(.*))?").unwrap();
+        }
+
+        let result = RFC3986.captures(value).unwrap();
+        Uri {
+            scheme: result.get(1).map(|s|s.as_str()),
+            authority: result.get(2).map(|s|s.as_str()),
+            path: result.get(3).unwrap().as_str(),
+            query: result.get(4).map(|s|s.as_str()),
+            fragment: result.get(5).map(|s|s.as_str()),
+        }
+    }
+
+    /// Decoded query parameters, `+` is left alone as it only means space in forms
+    pub fn query_params(&self) -> Parameters<'a> {
+        self.query.map(parse_query).unwrap_or_default()
+    }
+
+    pub fn parse_authority(&self) -> Option<Authority<'a>> {
+        self.authority.and_then(|authority| Authority::parse(authority).ok())
+    }
+
+    /// Decoded path segments, an escaped `/` stays inside its segment
+    pub fn segments(&self) -> Vec<Cow<'a, str>> {
+        let path = self.path.strip_prefix('/').unwrap_or(self.path);
+        if path.is_empty() {
+            return vec!();
+        }
+        path.split('/').map(percent_decode).collect()
+    }
+
+    pub fn decoded_path(&self) -> Cow<'a, str> {
+        percent_decode(self.path)
+    }
+
+    pub fn decoded_query(&self) -> Option<Cow<'a, str>> {
+        self.query.map(percent_decode)
+    }
+
+    pub fn decoded_fragment(&self) -> Option<Cow<'a, str>> {
+        self.fragment.map(percent_decode)
+    }
+
+    /// Syntax-based normalization (RFC 3986 section 6.2.2) plus an empty path becoming `/` when there is an authority
+    pub fn normalize(&self) -> String {
+        let scheme = self.scheme.map(str::to_ascii_lowercase);
+        let authority = self.authority.map(|authority| {
+            let (user_info, host) = match authority.rfind('@') {
+                Some(index) => (&authority[..=index], &authority[index + 1..]),
+                None => ("", authority),
+            };
+            format!("{}{}", normalize_percent(user_info), normalize_percent(&host.to_ascii_lowercase()))
+        });
+        let path = normalize_percent(self.path);
+        let path = match (&authority, path.is_empty()) {
+            (Some(_), true) => "/".to_string(),
+            _ if scheme.is_some() || authority.is_some() || path.starts_with('/') => remove_dot_segments(&path),
+            _ => path.into_owned(),
+        };
+        let query = self.query.map(normalize_percent);
+        let fragment = self.fragment.map(normalize_percent);
+        Uri {
+            scheme: scheme.as_deref(),
+            authority: authority.as_deref(),
+            path: &path,
+            query: query.as_deref(),
+            fragment: fragment.as_deref(),
+        }.to_string()
+    }
+
+    /// Resolves a reference against this base URI (RFC 3986 section 5.2.2, strict)
+    pub fn resolve(&self, reference: &Uri) -> String {
+        let (scheme, authority, path, query) = if reference.scheme.is_some() {
+            (reference.scheme, reference.authority, remove_dot_segments(reference.path), reference.query)
+        } else if reference.authority.is_some() {
+            (self.scheme, reference.authority, remove_dot_segments(reference.path), reference.query)
+        } else if reference.path.is_empty() {
+            (self.scheme, self.authority, self.path.to_string(), reference.query.or(self.query))
+        } else if reference.path.starts_with('/') {
+            (self.scheme, self.authority, remove_dot_segments(reference.path), reference.query)
+        } else {
+            (self.scheme, self.authority, remove_dot_segments(&self.merge(reference.path)), reference.query)
+        };
+        Uri { scheme, authority, path: &path, query, fragment: reference.fragment }.to_string()
+    }
+
+    pub fn join(&self, reference: &str) -> String {
+        self.resolve(&Uri::parse(reference))
+    }
+
+    // RFC 3986 section 5.2.3
+    fn merge(&self, path: &str) -> String {
+        if self.authority.is_some() && self.path.is_empty() {
+            return format!("/{}", path);
+        }
+        match self.path.rfind('/') {
+            Some(index) => format!("{}{}", &self.path[..=index], path),
+            None => path.to_string(),
+        }
+    }
+}
+
+impl<'a> fmt::Display for Uri<'a> {
+    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(scheme) = self.scheme {
+            write!(format, "{}:", scheme)?;
+        }
+        if let Some(authority) = self.authority {
+            write!(format, "//{}", authority)?;
+        }
+        format.write_str(self.path)?;
+        if let Some(query) = self.query {
+            write!(format, "?{}", query)?;
+        }
+        if let Some(fragment) = self.fragment {
+            write!(format, "#{}", fragment)?;
+        }
+        Ok(())
+    }
+}
+
+/// An owned `Uri` that can be modified, setters take decoded values and percent-encode them
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct UriBuf {
+    pub scheme: Option<String>,
+    pub authority: Option<String>,
+    pub path: String,
+    pub query: Option<String>,
+    pub fragment: Option<String>,
+}
+
+impl UriBuf {
+    pub fn new() -> UriBuf {
+        UriBuf::default()
+    }
+
+    pub fn parse(value: &str) -> UriBuf {
+        UriBuf::from(&Uri::parse(value))
+    }
+
+    pub fn as_uri(&self) -> Uri<'_> {
+        Uri {
+            scheme: self.scheme.as_deref(),
+            authority: self.authority.as_deref(),
+            path: &self.path,
+            query: self.query.as_deref(),
+            fragment: self.fragment.as_deref(),
+        }
+    }
+
+    pub fn set_scheme(&mut self, scheme: &str) -> &mut UriBuf {
+        self.scheme = Some(scheme.to_ascii_lowercase());
+        self
+    }
+
+    fn update_authority<F>(&mut self, fun: F) -> Result<&mut UriBuf> where F: FnOnce(&mut String, &mut Option<String>, &mut Option<u16>) {
+        let (mut host, mut user_info, mut port) = match self.authority.as_deref() {
+            Some(authority) => {
+                let authority = Authority::parse(authority)?;
+                (authority.host.to_string(), authority.user_info.map(String::from), authority.port)
+            }
+            None => (String::new(), None, None),
+        };
+        fun(&mut host, &mut user_info, &mut port);
+        if host.is_empty() && port.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "A port needs a host"));
+        }
+        let mut authority = user_info.map(|user_info| format!("{}@", user_info)).unwrap_or_default();
+        authority.push_str(&host);
+        if let Some(port) = port {
+            authority.push_str(&format!(":{}", port));
+        }
+        self.authority = Some(authority);
+        if !self.path.is_empty() && !self.path.starts_with('/') {
+            self.path.insert(0, '/');
+        }
+        Ok(self)
+    }
+
+    /// IPv6 addresses are bracketed, anything else is a registered name
+    pub fn set_host(&mut self, host: &str) -> Result<&mut UriBuf> {
+        let host = match host.trim_start_matches('[').trim_end_matches(']').parse::<Ipv6Addr>() {
+            Ok(address) => Host::Ipv6(address).to_string(),
+            Err(_) => encode(host, Component::Host).into_owned(),
+        };
+        self.update_authority(|current, _, _| *current = host)
+    }
+
+    pub fn set_port(&mut self, port: Option<u16>) -> Result<&mut UriBuf> {
+        self.update_authority(|_, _, current| *current = port)
+    }
+
+    pub fn set_user_info(&mut self, user: &str, password: Option<&str>) -> Result<&mut UriBuf> {
+        let mut user_info = encode(user, Component::User).into_owned();
+        if let Some(password) = password {
+            user_info.push(':');
+            user_info.push_str(&encode(password, Component::UserInfo));
+        }
+        self.update_authority(|_, current, _| *current = Some(user_info))
+    }
+
+    pub fn set_path(&mut self, path: &str) -> &mut UriBuf {
+        let mut path = encode(path, Component::Path).into_owned();
+        if self.authority.is_some() && !path.is_empty() && !path.starts_with('/') {
+            path.insert(0, '/');
+        }
+        if self.authority.is_none() && path.starts_with("//") {
+            path.insert_str(0, "/.");
+        }
+        if self.scheme.is_none() && self.authority.is_none() {
+            let end = path.find('/').unwrap_or(path.len());
+            let first = path[..end].replace(':', "%3A");
+            path.replace_range(..end, &first);
+        }
+        self.path = path;
+        self
+    }
+
+    pub fn push_segment(&mut self, segment: &str) -> &mut UriBuf {
+        if !self.path.ends_with('/') && (!self.path.is_empty() || self.authority.is_some()) {
+            self.path.push('/');
+        }
+        self.path.push_str(&encode(segment, Component::Segment));
+        self
+    }
+
+    pub fn set_query(&mut self, query: Option<&str>) -> &mut UriBuf {
+        self.query = query.map(|query| encode(query, Component::Query).into_owned());
+        self
+    }
+
+    fn update_query<F>(&mut self, fun: F) -> &mut UriBuf where F: FnOnce(&mut Parameters) {
+        let mut parameters = Parameters(self.as_uri().query_params().0.into_iter().
+            map(|(name, value)| (Cow::from(name.into_owned()), Cow::from(value.into_owned()))).
+            collect());
+        fun(&mut parameters);
+        self.query = if parameters.is_empty() { None } else { Some(serialize_query(&parameters)) };
+        self
+    }
+
+    pub fn append_query_param(&mut self, name: &str, value: &str) -> &mut UriBuf {
+        self.update_query(|parameters| { parameters.add(name.to_string(), value.to_string()); })
+    }
+
+    pub fn set_query_param(&mut self, name: &str, value: &str) -> &mut UriBuf {
+        self.update_query(|parameters| {
+            match parameters.0.iter().position(|(key, _)| key == name) {
+                Some(index) => {
+                    parameters.0[index].1 = Cow::from(value.to_string());
+                    let mut seen = 0;
+                    parameters.0.retain(|(key, _)| key != name || { seen += 1; seen == 1 });
+                }
+                None => { parameters.add(name.to_string(), value.to_string()); }
+            }
+        })
+    }
+
+    pub fn remove_query_param(&mut self, name: &str) -> &mut UriBuf {
+        self.update_query(|parameters| parameters.0.retain(|(key, _)| key != name))
+    }
+
+    pub fn set_fragment(&mut self, fragment: Option<&str>) -> &mut UriBuf {
+        self.fragment = fragment.map(|fragment| encode(fragment, Component::Fragment).into_owned());
+        self
+    }
+}
+
+impl<'a> From<&Uri<'a>> for UriBuf {
+    fn from(uri: &Uri<'a>) -> UriBuf {
+        UriBuf {
+            scheme: uri.scheme.map(String::from),
+            authority: uri.authority.map(String::from),
+            path: uri.path.to_string(),
+            query: uri.query.map(String::from),
+            fragment: uri.fragment.map(String::from),
+        }
+    }
+}
+
+impl fmt::Display for UriBuf {
+    fn fmt(&self, format: &mut fmt::Formatter) -> fmt::Result {
+        self.as_uri().fmt(format)
+    }
+}
+
+#[derive(Default)]
+pub struct UriBuilder {
+    uri: UriBuf,
+    user_info: Option<(String, Option<String>)>,
+    host: Option<String>,
+    port: Option<u16>,
+    path: Option<String>,
+    segments: Vec<String>,
+}
+
+impl UriBuilder {
+    pub fn new() -> UriBuilder {
+        UriBuilder::default()
+    }
+
+    pub fn scheme(mut self, scheme: &str) -> UriBuilder {
+        self.uri.set_scheme(scheme);
+        self
+    }
+
+    pub fn user_info(mut self, user: &str, password: Option<&str>) -> UriBuilder {
+        self.user_info = Some((user.to_string(), password.map(String::from)));
+        self
+    }
+
+    pub fn host(mut self, host: &str) -> UriBuilder {
+        self.host = Some(host.to_string());
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> UriBuilder {
+        self.port = Some(port);
+        self
+    }
+
+    pub fn path(mut self, path: &str) -> UriBuilder {
+        self.path = Some(path.to_string());
+        self.segments.clear();
+        self
+    }
+
+    pub fn segment(mut self, segment: &str) -> UriBuilder {
+        self.segments.push(segment.to_string());
+        self
+    }
+
+    pub fn query_param(mut self, name: &str, value: &str) -> UriBuilder {
+        self.uri.append_query_param(name, value);
+        self
+    }
+
+    pub fn fragment(mut self, fragment: &str) -> UriBuilder {
+        self.uri.set_fragment(Some(fragment));
+        self
+    }
+
+    /// The authority and then the path are put together last so the parts can be given in any order,
+    /// a port without a host fails
+    pub fn build(mut self) -> Result<UriBuf> {
+        if let Some(host) = self.host {
+            self.uri.set_host(&host)?;
+        }
+        if let Some((user, password)) = self.user_info {
+            self.uri.set_user_info(&user, password.as_deref())?;
+        }
+        if self.port.is_some() {
+            self.uri.set_port(self.port)?;
+        }
+        if let Some(path) = self.path {
+            self.uri.set_path(&path);
+        }
+        for segment in &self.segments {
+            self.uri.push_segment(segment);
+        }
+        Ok(self.uri)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_parse_uri() {
+        let uri = Uri::parse("http://authority/some/path?query=string#fragment");
+        assert_eq!(uri.scheme, Some("http"));
+        assert_eq!(uri.authority, Some("authority"));
+        assert_eq!(uri.path, "/some/path");
+        assert_eq!(uri.query, Some("query=string"));
+        assert_eq!(uri.fragment, Some("fragment"));
+    }
+
+    #[test]
+    fn supports_relative() {
+        let uri = Uri::parse("some/path");
+        assert_eq!(uri.scheme, None);
+        assert_eq!(uri.authority, None);
+        assert_eq!(uri.path, "some/path");
+        assert_eq!(uri.query, None);
+        assert_eq!(uri.fragment, None);
+    }
+
+    #[test]
+    fn supports_urns() {
+        let uri = Uri::parse("uuid:720f11db-1a29-4a68-a034-43f80b27659d");
+        assert_eq!(uri.scheme, Some("uuid"));
+        assert_eq!(uri.authority, None);
+        assert_eq!(uri.path, "720f11db-1a29-4a68-a034-43f80b27659d");
+        assert_eq!(uri.query, None);
+        assert_eq!(uri.fragment, None);
+    }
+
+    #[test]
+    fn is_reverse_able() {
+        let original = "http://authority/some/path?query=string#fragment";
+        assert_eq!(Uri::parse(original).to_string(), original.to_string());
+        let another = "some/path";
+        assert_eq!(Uri::parse(another).to_string(), another.to_string());
+    }
+
+    #[test]
+    fn can_decode_query_params() {
+        let uri = Uri::parse("/search?q=caf%C3%A9&tag=a&tag=b");
+        let params = uri.query_params();
+        assert_eq!(params.get("q"), Some("café"));
+        assert_eq!(params.values("tag"), vec!("a", "b"));
+        assert!(Uri::parse("/search").query_params().is_empty());
+    }
+
+    #[test]
+    fn encodes_per_component() {
+        assert_eq!(encode("a b/c?d%", Component::Path), "a%20b/c%3Fd%25");
+        assert_eq!(encode("a b/c?d", Component::Segment), "a%20b%2Fc%3Fd");
+        assert_eq!(encode("a b/c?d#", Component::Query), "a%20b/c?d%23");
+        assert_eq!(encode("user:p@ss", Component::UserInfo), "user:p%40ss");
+        assert_eq!(encode("ex ample:80", Component::Host), "ex%20ample%3A80");
+        assert_eq!(encode("café", Component::Fragment), "caf%C3%A9");
+        assert!(matches!(encode("plain", Component::Path), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn decodes_components() {
+        let uri = Uri::parse("/a%20b/c%2Fd?q=%C3%A9#top%21");
+        assert_eq!(uri.decoded_path(), "/a b/c/d");
+        assert_eq!(uri.segments(), vec!("a b", "c/d"));
+        assert_eq!(uri.decoded_query(), Some(Cow::from("q=é")));
+        assert_eq!(uri.decoded_fragment(), Some(Cow::from("top!")));
+        assert!(Uri::parse("/").segments().is_empty());
+    }
+
+    #[test]
+    fn removes_dot_segments() {
+        assert_eq!(remove_dot_segments("/a/b/c/./../../g"), "/a/g");
+        assert_eq!(remove_dot_segments("mid/content=5/../6"), "mid/6");
+        assert_eq!(remove_dot_segments("/../../etc/passwd"), "/etc/passwd");
+        assert_eq!(remove_dot_segments("é"), "é");
+        assert_eq!(remove_dot_segments("ü/x/../é"), "ü/é");
+        assert_eq!(Uri::parse("http://a/b/c").join("g:é"), "g:é");
+    }
+
+    #[test]
+    fn normalizes() {
+        assert_eq!(Uri::parse("HTTP://www.Example.COM/%7Efoo/a/./b/../c?%3d%7e#%2a").normalize(), "http://www.example.com/~foo/a/c?%3D~#%2A");
+        assert_eq!(Uri::parse("eXAMPLE://a/./b/../b/%63/%7bfoo%7d").normalize(), "example://a/b/c/%7Bfoo%7D");
+        assert_eq!(Uri::parse("http://User%3a@EXAMPLE.com").normalize(), "http://User%3A@example.com/");
+        assert_eq!(Uri::parse("a/./b/../c").normalize(), "a/./b/../c");
+    }
+
+    #[test]
+    fn resolves_normal_examples_from_rfc_3986() {
+        let base = Uri::parse("http://a/b/c/d;p?q");
+        for (reference, expected) in vec!(
+            ("g:h", "g:h"), ("g", "http://a/b/c/g"), ("./g", "http://a/b/c/g"), ("g/", "http://a/b/c/g/"),
+            ("/g", "http://a/g"), ("//g", "http://g"), ("?y", "http://a/b/c/d;p?y"), ("g?y", "http://a/b/c/g?y"),
+            ("#s", "http://a/b/c/d;p?q#s"), ("g#s", "http://a/b/c/g#s"), ("g?y#s", "http://a/b/c/g?y#s"),
+            (";x", "http://a/b/c/;x"), ("g;x", "http://a/b/c/g;x"), ("g;x?y#s", "http://a/b/c/g;x?y#s"),
+            ("", "http://a/b/c/d;p?q"), (".", "http://a/b/c/"), ("./", "http://a/b/c/"), ("..", "http://a/b/"),
+            ("../", "http://a/b/"), ("../g", "http://a/b/g"), ("../..", "http://a/"), ("../../", "http://a/"),
+            ("../../g", "http://a/g")) {
+            assert_eq!(base.join(reference), expected, "resolving {}", reference);
+        }
+    }
+
+    #[test]
+    fn resolves_abnormal_examples_from_rfc_3986() {
+        let base = Uri::parse("http://a/b/c/d;p?q");
+        for (reference, expected) in vec!(
+            ("../../../g", "http://a/g"), ("../../../../g", "http://a/g"), ("/./g", "http://a/g"), ("/../g", "http://a/g"),
+            ("g.", "http://a/b/c/g."), (".g", "http://a/b/c/.g"), ("g..", "http://a/b/c/g.."), ("..g", "http://a/b/c/..g"),
+            ("./../g", "http://a/b/g"), ("./g/.", "http://a/b/c/g/"), ("g/./h", "http://a/b/c/g/h"), ("g/../h", "http://a/b/c/h"),
+            ("g;x=1/./y", "http://a/b/c/g;x=1/y"), ("g;x=1/../y", "http://a/b/c/y"), ("g?y/./x", "http://a/b/c/g?y/./x"),
+            ("g?y/../x", "http://a/b/c/g?y/../x"), ("g#s/./x", "http://a/b/c/g#s/./x"), ("g#s/../x", "http://a/b/c/g#s/../x"),
+            ("http:g", "http:g")) {
+            assert_eq!(base.join(reference), expected, "resolving {}", reference);
+        }
+    }
+
+    #[test]
+    fn parses_authority() {
+        let authority = Uri::parse("http://dan@[::1]:8080/").parse_authority().unwrap();
+        assert_eq!(authority.user_info, Some("dan"));
+        assert_eq!(authority.host, Host::Ipv6(Ipv6Addr::LOCALHOST));
+        assert_eq!(authority.port, Some(8080));
+        assert_eq!(authority.to_string(), "dan@[::1]:8080");
+        assert_eq!(authority.host_port().to_string(), "[::1]:8080");
+        assert_eq!(Uri::parse("http://bad host/").parse_authority(), None);
+        assert_eq!(Uri::parse("/relative").parse_authority(), None);
+    }
+
+    #[test]
+    fn adds_default_port() {
+        assert_eq!(Authority::parse("proxy").unwrap().address(80), "proxy:80");
+        assert_eq!(Authority::parse("proxy:3128").unwrap().address(80), "proxy:3128");
+        assert_eq!(Authority::parse("[::1]").unwrap().address(80), "[::1]:80");
+        assert_eq!(Authority::parse("[::1]:3128").unwrap().address(80), "[::1]:3128");
+        assert_eq!(Authority::parse("user:pass@proxy:").unwrap().address(80), "proxy:80");
+        assert!(Authority::parse("[::1").is_err());
+        assert_eq!(default_port("HTTPS"), Some(443));
+        assert_eq!(default_port("gopher"), None);
+    }
+
+    #[test]
+    fn parses_host_header() {
+        assert_eq!(Authority::host(" Example.COM:8080 ").unwrap().host.name(), "example.com");
+        assert_eq!(Authority::host("[2001:db8::1]").unwrap().host.name(), "2001:db8::1");
+        assert!(Authority::host("user@example.com").is_err());
+        assert!(Authority::host("example.com:http").is_err());
+    }
+
+    #[test]
+    fn builds_uris_with_encoding() {
+        let uri = UriBuilder::new().
+            scheme("HTTPS").
+            user_info("dan@home", Some("p:ss")).
+            host("::1").
+            port(8443).
+            path("a b").
+            segment("c/d").
+            query_param("q", "x y&z").
+            query_param("q", "2").
+            fragment("top section").
+            build().unwrap();
+        assert_eq!(uri.to_string(), "https://dan%40home:p:ss@[::1]:8443/a%20b/c%2Fd?q=x%20y%26z&q=2#top%20section");
+        assert_eq!(UriBuf::from(&Uri::parse(&uri.to_string())), uri);
+        assert_eq!(uri.as_uri().segments(), vec!("a b", "c/d"));
+        assert_eq!(uri.as_uri().query_params().values("q"), vec!("x y&z", "2"));
+    }
+
+    #[test]
+    fn modifies_owned_uris() {
+        let mut uri = UriBuf::parse("http://example.com/docs?page=1&sort=asc&page=2#intro");
+        uri.set_scheme("https").set_port(Some(8443)).unwrap().push_segment("api v2").
+            set_query_param("page", "3").remove_query_param("sort").append_query_param("lang", "en").
+            set_fragment(None);
+        assert_eq!(uri.to_string(), "https://example.com:8443/docs/api%20v2?page=3&lang=en");
+        uri.set_host("Other.example.com").unwrap().set_port(None).unwrap().set_query(None);
+        assert_eq!(uri.to_string(), "https://Other.example.com/docs/api%20v2");
+    }
+
+    #[test]
+    fn refuses_authorities_it_can_not_keep() {
+        let mut user = UriBuf::parse("http://example.com/");
+        user.set_user_info("dan:home", Some("p:ss")).unwrap();
+        assert_eq!(user.to_string(), "http://dan%3Ahome:p:ss@example.com/");
+
+        assert!(UriBuf::parse("http://exa mple.com/").set_host("example.com").is_err());
+        assert!(UriBuf::new().set_port(Some(8080)).is_err());
+        assert!(UriBuilder::new().port(8080).build().is_err());
+        assert_eq!(UriBuilder::new().port(8080).host("example.com").build().unwrap().to_string(), "//example.com:8080");
+    }
+
+    #[test]
+    fn builds_paths_after_the_authority() {
+        assert_eq!(UriBuilder::new().host("h").path("a:b").build().unwrap().to_string(), "//h/a:b");
+        assert_eq!(UriBuilder::new().path("a:b").host("h").build().unwrap().to_string(), "//h/a:b");
+        assert_eq!(UriBuilder::new().host("h").path("//x").build().unwrap().to_string(), "//h//x");
+        assert_eq!(UriBuilder::new().segment("a").host("h").segment("b").build().unwrap().to_string(), "//h/a/b");
+        assert_eq!(UriBuilder::new().path("a:b").build().unwrap().to_string(), "a%3Ab");
+        assert_eq!(UriBuilder::new().path("//x").build().unwrap().to_string(), "/.//x");
+    }
+
+    #[test]
+    fn keeps_paths_unambiguous() {
+        let mut relative = UriBuf::new();
+        relative.set_path("a:b/c:d");
+        assert_eq!(relative.to_string(), "a%3Ab/c:d");
+        assert_eq!(Uri::parse(&relative.to_string()).scheme, None);
+        relative.set_path("//not-authority");
+        assert_eq!(Uri::parse(&relative.to_string()).authority, None);
+
+        let mut absolute = UriBuf::new();
+        absolute.set_host("example.com").unwrap().push_segment("x");
+        assert_eq!(absolute.to_string(), "//example.com/x");
+        absolute.set_path("y");
+        assert_eq!(absolute.to_string(), "//example.com/y");
+    }
+
+    #[test]
+    fn round_trips_through_parse() {
+        for value in ["http://a/b/c/d;p?q", "urn:isbn:0451450523", "//host", "?q", "#f", "", "mailto:dan@example.com"] {
+            assert_eq!(UriBuf::parse(value).to_string(), value);
+        }
+    }
+
+    #[test]
+    fn merges_with_empty_base_path() {
+        assert_eq!(Uri::parse("http://a").join("g"), "http://a/g");
+    }
+}